@@ -0,0 +1,438 @@
+//! in-toto attestations in DSSE envelopes (`sign --format dsse`): an in-toto Statement v1 whose
+//! predicate is the SBOM and whose subjects are the artifacts it describes, signed over the DSSE
+//! pre-authentication encoding (PAE) of the statement.
+
+use crate::sbom::SbomFormat;
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use sha3::{Digest, Sha3_256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// DSSE payload type of an in-toto statement.
+pub const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const CYCLONEDX_PREDICATE_TYPE: &str = "https://cyclonedx.org/bom";
+pub const SPDX_PREDICATE_TYPE: &str = "https://spdx.dev/Document";
+
+/// DSSE envelope, as written to `<file>.intoto.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DsseEnvelope {
+    #[serde(rename = "payloadType")]
+    pub payload_type: String,
+    /// Base64 of the serialized statement.
+    pub payload: String,
+    pub signatures: Vec<DsseSignature>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DsseSignature {
+    /// Key id of the signing key (hex SHA3-256 of the public key); empty if unknown.
+    #[serde(default)]
+    pub keyid: String,
+    /// Base64 Dilithium5 signature over the PAE.
+    pub sig: String,
+}
+
+/// in-toto Statement v1.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    pub subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    pub predicate: Value,
+}
+
+/// An artifact the statement is about, by name and digests (in-toto DigestSet algorithm names).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Subject {
+    pub name: String,
+    pub digest: BTreeMap<String, String>,
+}
+
+impl Subject {
+    /// True if the subjects share an algorithm and agree on every shared one.
+    pub fn matches(&self, other: &Subject) -> bool {
+        let mut shared = self
+            .digest
+            .iter()
+            .filter_map(|(alg, d)| other.digest.get(alg).map(|o| d.eq_ignore_ascii_case(o)))
+            .peekable();
+        shared.peek().is_some() && shared.all(|same| same)
+    }
+}
+
+/// DSSE pre-authentication encoding: `DSSEv1 <len(type)> <type> <len(body)> <body>`.
+pub fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut out = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    out.extend_from_slice(payload);
+    out
+}
+
+impl DsseEnvelope {
+    /// Envelope for the in-toto `payload` with one signature.
+    pub fn new(payload: &[u8], signature: &[u8], keyid: Option<String>) -> Self {
+        Self {
+            payload_type: PAYLOAD_TYPE.to_string(),
+            payload: STANDARD.encode(payload),
+            signatures: vec![DsseSignature {
+                keyid: keyid.unwrap_or_default(),
+                sig: STANDARD.encode(signature),
+            }],
+        }
+    }
+
+    /// The message every signature is over: the PAE of the payload.
+    pub fn signed_message(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(pae(&self.payload_type, &self.payload()?))
+    }
+
+    pub fn payload(&self) -> anyhow::Result<Vec<u8>> {
+        STANDARD
+            .decode(&self.payload)
+            .map_err(|e| anyhow!("Invalid DSSE payload: {}", e))
+    }
+
+    /// The in-toto statement in the payload.
+    pub fn statement(&self) -> anyhow::Result<Statement> {
+        if self.payload_type != PAYLOAD_TYPE {
+            return Err(anyhow!(
+                "DSSE payload type is {}, not {}",
+                self.payload_type,
+                PAYLOAD_TYPE
+            ));
+        }
+        let statement: Statement = serde_json::from_slice(&self.payload()?)
+            .map_err(|e| anyhow!("Invalid in-toto statement: {}", e))?;
+        if statement.statement_type != STATEMENT_TYPE {
+            return Err(anyhow!(
+                "Statement type is {}, not {}",
+                statement.statement_type,
+                STATEMENT_TYPE
+            ));
+        }
+        Ok(statement)
+    }
+
+    /// Every signature in the envelope.
+    pub fn signatures(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.signatures
+            .iter()
+            .map(|s| {
+                STANDARD
+                    .decode(&s.sig)
+                    .map_err(|e| anyhow!("Invalid DSSE signature: {}", e))
+            })
+            .collect()
+    }
+
+    /// The signature by the key `key_id`, or one that names no key.
+    pub fn signature_by(&self, key_id: &str) -> anyhow::Result<Vec<u8>> {
+        let signature = self
+            .signatures
+            .iter()
+            .find(|s| s.keyid.eq_ignore_ascii_case(key_id))
+            .or_else(|| self.signatures.iter().find(|s| s.keyid.is_empty()))
+            .ok_or_else(|| anyhow!("DSSE envelope has no signature by key {}", key_id))?;
+        STANDARD
+            .decode(&signature.sig)
+            .map_err(|e| anyhow!("Invalid DSSE signature: {}", e))
+    }
+}
+
+/// True if `bytes` is a DSSE envelope rather than a `.sig` envelope.
+pub fn is_dsse(bytes: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(bytes).is_ok_and(|v| v.get("payloadType").is_some())
+}
+
+pub fn predicate_type(format: SbomFormat) -> &'static str {
+    match format {
+        SbomFormat::CycloneDx => CYCLONEDX_PREDICATE_TYPE,
+        SbomFormat::Spdx => SPDX_PREDICATE_TYPE,
+    }
+}
+
+/// Statement with the SBOM as predicate.
+pub fn statement(sbom: Value, format: SbomFormat, subject: Vec<Subject>) -> Statement {
+    Statement {
+        statement_type: STATEMENT_TYPE.to_string(),
+        subject,
+        predicate_type: predicate_type(format).to_string(),
+        predicate: sbom,
+    }
+}
+
+/// Subject for a file on disk: its file name with SHA-256 and SHA3-256 digests.
+pub fn file_subject(path: &Path) -> anyhow::Result<Subject> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed to read subject {}: {}", path.display(), e))?;
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_string();
+    Ok(Subject {
+        name,
+        digest: BTreeMap::from([
+            ("sha256".to_string(), hex::encode(Sha256::digest(&bytes))),
+            ("sha3_256".to_string(), hex::encode(Sha3_256::digest(&bytes))),
+        ]),
+    })
+}
+
+/// in-toto DigestSet name for a CycloneDX or SPDX hash algorithm.
+fn digest_name(algorithm: &str) -> Option<&'static str> {
+    Some(match algorithm.to_ascii_uppercase().replace('-', "").as_str() {
+        "SHA1" => "sha1",
+        "SHA224" => "sha224",
+        "SHA256" => "sha256",
+        "SHA384" => "sha384",
+        "SHA512" => "sha512",
+        "SHA3256" => "sha3_256",
+        "SHA3384" => "sha3_384",
+        "SHA3512" => "sha3_512",
+        "MD5" => "md5",
+        _ => return None,
+    })
+}
+
+/// Name and digests of a CycloneDX component or SPDX package, if it has any usable hash.
+fn subject_of(name: Option<&str>, hashes: &[Value], alg: &str, value: &str) -> Option<Subject> {
+    let digest: BTreeMap<_, _> = hashes
+        .iter()
+        .filter_map(|h| {
+            let name = digest_name(h.get(alg)?.as_str()?)?;
+            Some((name.to_string(), h.get(value)?.as_str()?.to_lowercase()))
+        })
+        .collect();
+    (!digest.is_empty()).then(|| Subject {
+        name: name.unwrap_or_default().to_string(),
+        digest,
+    })
+}
+
+/// The artifacts a JSON SBOM says it describes, with their hashes: the CycloneDX
+/// `metadata.component`, or the SPDX 2 packages named in `documentDescribes` or by a `DESCRIBES`
+/// relationship of the document.
+pub fn described_subjects(sbom: &Value, format: SbomFormat) -> Vec<Subject> {
+    let empty = Vec::new();
+    match format {
+        SbomFormat::CycloneDx => sbom
+            .pointer("/metadata/component")
+            .and_then(|c| {
+                let hashes = c.get("hashes").and_then(Value::as_array).unwrap_or(&empty);
+                subject_of(c.get("name").and_then(Value::as_str), hashes, "alg", "content")
+            })
+            .into_iter()
+            .collect(),
+        SbomFormat::Spdx => {
+            let mut described: Vec<&str> = sbom
+                .get("documentDescribes")
+                .and_then(Value::as_array)
+                .unwrap_or(&empty)
+                .iter()
+                .filter_map(Value::as_str)
+                .collect();
+            let document = sbom.get("SPDXID").and_then(Value::as_str).unwrap_or("SPDXRef-DOCUMENT");
+            described.extend(
+                sbom.get("relationships")
+                    .and_then(Value::as_array)
+                    .unwrap_or(&empty)
+                    .iter()
+                    .filter(|r| {
+                        r.get("spdxElementId").and_then(Value::as_str) == Some(document)
+                            && r.get("relationshipType").and_then(Value::as_str) == Some("DESCRIBES")
+                    })
+                    .filter_map(|r| r.get("relatedSpdxElement").and_then(Value::as_str)),
+            );
+            sbom.get("packages")
+                .and_then(Value::as_array)
+                .unwrap_or(&empty)
+                .iter()
+                .filter(|p| {
+                    p.get("SPDXID")
+                        .and_then(Value::as_str)
+                        .is_some_and(|id| described.contains(&id))
+                })
+                .filter_map(|p| {
+                    let hashes = p.get("checksums").and_then(Value::as_array).unwrap_or(&empty);
+                    subject_of(
+                        p.get("packageFileName")
+                            .or_else(|| p.get("name"))
+                            .and_then(Value::as_str),
+                        hashes,
+                        "algorithm",
+                        "checksumValue",
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn subject(name: &str, digests: &[(&str, &str)]) -> Subject {
+        Subject {
+            name: name.to_string(),
+            digest: digests
+                .iter()
+                .map(|(alg, d)| (alg.to_string(), d.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn pae_matches_the_dsse_spec() {
+        // The example in the DSSE protocol specification.
+        assert_eq!(
+            pae("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+        assert_eq!(pae("", b""), b"DSSEv1 0  0 ");
+        // Lengths count bytes, not characters.
+        assert_eq!(pae("t", "é".as_bytes()), "DSSEv1 1 t 2 é".as_bytes());
+    }
+
+    #[test]
+    fn cyclonedx_describes_its_metadata_component() {
+        let bom = json!({
+            "bomFormat": "CycloneDX",
+            "metadata": {
+                "component": {
+                    "type": "application",
+                    "name": "app.tar.gz",
+                    "hashes": [
+                        { "alg": "SHA-256", "content": "AB12" },
+                        { "alg": "SHA3-256", "content": "cd34" },
+                        { "alg": "BLAKE3", "content": "ef56" }
+                    ]
+                }
+            },
+            "components": [
+                { "type": "library", "name": "lib", "hashes": [{ "alg": "SHA-256", "content": "99" }] }
+            ]
+        });
+        assert_eq!(
+            described_subjects(&bom, SbomFormat::CycloneDx),
+            vec![subject("app.tar.gz", &[("sha256", "ab12"), ("sha3_256", "cd34")])]
+        );
+        let mut unhashed = bom.clone();
+        unhashed["metadata"]["component"]["hashes"] = json!([{ "alg": "BLAKE3", "content": "ef56" }]);
+        assert!(described_subjects(&unhashed, SbomFormat::CycloneDx).is_empty());
+    }
+
+    #[test]
+    fn spdx_describes_listed_and_related_packages() {
+        let package = |id: &str, file: Option<&str>, sha256: &str| {
+            let mut p = json!({
+                "SPDXID": id,
+                "name": format!("{}-name", id),
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": sha256 }]
+            });
+            if let Some(file) = file {
+                p["packageFileName"] = json!(file);
+            }
+            p
+        };
+        let doc = json!({
+            "spdxVersion": "SPDX-2.3",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "documentDescribes": ["SPDXRef-app"],
+            "packages": [
+                package("SPDXRef-app", Some("app.tar.gz"), "01"),
+                package("SPDXRef-cli", None, "02"),
+                package("SPDXRef-dep", None, "03")
+            ],
+            "relationships": [
+                { "spdxElementId": "SPDXRef-DOCUMENT", "relationshipType": "DESCRIBES", "relatedSpdxElement": "SPDXRef-cli" },
+                { "spdxElementId": "SPDXRef-app", "relationshipType": "DEPENDS_ON", "relatedSpdxElement": "SPDXRef-dep" },
+                { "spdxElementId": "SPDXRef-cli", "relationshipType": "DESCRIBES", "relatedSpdxElement": "SPDXRef-dep" }
+            ]
+        });
+        assert_eq!(
+            described_subjects(&doc, SbomFormat::Spdx),
+            vec![
+                subject("app.tar.gz", &[("sha256", "01")]),
+                subject("SPDXRef-cli-name", &[("sha256", "02")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn subjects_match_on_shared_algorithms() {
+        let file = subject("app", &[("sha256", "aa"), ("sha3_256", "bb")]);
+        assert!(file.matches(&subject("app", &[("sha256", "AA")])));
+        assert!(file.matches(&subject("other name", &[("sha256", "aa"), ("sha512", "cc")])));
+        assert!(!file.matches(&subject("app", &[("sha256", "aa"), ("sha3_256", "00")])));
+        // No algorithm in common: nothing was compared, so no match.
+        assert!(!file.matches(&subject("app", &[("sha512", "cc"), ("md5", "dd")])));
+        assert!(!file.matches(&subject("app", &[])));
+    }
+
+    #[test]
+    fn envelope_signature_is_over_the_pae() {
+        use crate::crypto::{self, key_id, load_public_key, load_signature, verify_signature};
+        use crate::ledger::Rotation;
+        use crate::sign::{self, Output, SignOptions};
+        use crate::{key_provider::FileSystemProvider, verify};
+
+        let dir = std::env::temp_dir().join(format!("qs_notary_dsse_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        crypto::generate_keypair(&dir).unwrap();
+        let artifact = dir.join("app.tar.gz");
+        std::fs::write(&artifact, b"release").unwrap();
+        let sbom = dir.join("bom.json");
+        std::fs::write(
+            &sbom,
+            r#"{"bomFormat":"CycloneDX","specVersion":"1.6","version":1,"metadata":{"component":{"type":"application","name":"app"}}}"#,
+        )
+        .unwrap();
+        let opts = SignOptions {
+            ledger_path: &dir.join("ledger.json"),
+            rotation: &Rotation::default(),
+            tsa_url: None,
+            upload: None,
+        };
+        sign::run(
+            &sbom,
+            &FileSystemProvider::new(&dir.join("private.key")),
+            &opts,
+            false,
+            Output::Attestation(vec![artifact.clone()]),
+        )
+        .unwrap();
+
+        let path = sign::attestation_path_for(&sbom);
+        let dsse: DsseEnvelope = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let pk = load_public_key(&dir.join("public.key")).unwrap();
+        assert_eq!(dsse.signatures[0].keyid, key_id(&pk));
+        // Any DSSE verifier checks the signature over the PAE bytes themselves.
+        let sig = load_signature(&STANDARD.decode(&dsse.signatures[0].sig).unwrap()).unwrap();
+        let message = pae(&dsse.payload_type, &dsse.payload().unwrap());
+        verify_signature(&sig, &message, &pk).unwrap();
+        assert!(verify_signature(&sig, &Sha3_256::digest(&message), &pk).is_err());
+
+        let subjects = [artifact.clone()];
+        let verify = || verify::run(&sbom, Some(&path), &subjects, &dir.join("public.key"), None, None, None);
+        verify().unwrap();
+        std::fs::write(&artifact, b"tampered").unwrap();
+        assert!(verify().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! API tokens: per-team tokens for remote signing and admin tokens for the key registry.
+
+use axum::http::{header, HeaderMap, StatusCode};
+use serde::Deserialize;
+use sha3::{Digest, Sha3_256};
+use std::path::Path;
+
+/// API token file: `[[token]]` tables for teams, `[[admin]]` tables for registry administrators.
+/// Tokens are stored as their hex SHA3-256, never in the clear.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TokenFile {
+    #[serde(default)]
+    token: Vec<TokenGrant>,
+    #[serde(default)]
+    admin: Vec<AdminToken>,
+}
+
+/// A team's token and the signing keys it may use.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenGrant {
+    pub team: String,
+    token_sha3: String,
+    pub keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdminToken {
+    name: String,
+    token_sha3: String,
+}
+
+pub struct ApiTokens {
+    grants: Vec<TokenGrant>,
+    admins: Vec<AdminToken>,
+}
+
+/// Bearer token from the `Authorization` header; 401 if there is none.
+pub fn bearer(headers: &HeaderMap) -> Result<&str, (StatusCode, String)> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or((StatusCode::UNAUTHORIZED, "missing API token".to_string()))
+}
+
+pub fn token_hash(token: &str) -> String {
+    hex::encode(Sha3_256::digest(token.as_bytes()))
+}
+
+impl ApiTokens {
+    /// Load the token file; a missing file means no tokens.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file: TokenFile = match std::fs::read_to_string(path) {
+            Ok(s) => toml::from_str(&s).map_err(|e| {
+                anyhow::anyhow!("Invalid API token file {}: {}", path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => TokenFile::default(),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            grants: file.token,
+            admins: file.admin,
+        })
+    }
+
+    pub fn grants(&self) -> &[TokenGrant] {
+        &self.grants
+    }
+
+    pub fn len(&self) -> usize {
+        self.grants.len() + self.admins.len()
+    }
+
+    /// Team grant for `token`; 401 if unknown.
+    pub fn team(&self, token: &str) -> Result<&TokenGrant, (StatusCode, String)> {
+        let hash = token_hash(token);
+        self.grants
+            .iter()
+            .find(|g| g.token_sha3.eq_ignore_ascii_case(&hash))
+            .ok_or((StatusCode::UNAUTHORIZED, "unknown API token".to_string()))
+    }
+
+    /// Administrator name for the request's bearer token; 401 if missing or not an admin token.
+    pub fn admin(&self, headers: &HeaderMap) -> Result<String, (StatusCode, String)> {
+        let hash = token_hash(bearer(headers)?);
+        self.admins
+            .iter()
+            .find(|a| a.token_sha3.eq_ignore_ascii_case(&hash))
+            .map(|a| a.name.clone())
+            .ok_or((StatusCode::UNAUTHORIZED, "admin token required".to_string()))
+    }
+}
@@ -0,0 +1,424 @@
+//! Server configuration: command-line flags and `QS_*` environment variables override values from
+//! an optional TOML config file, which override the built-in defaults.
+
+use clap::Parser;
+use qs_notary::ledger::{LedgerBackend, Rotation};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "qs_server")]
+#[command(about = "Transparency log server for qs_notary")]
+pub struct Args {
+    /// TOML config file.
+    #[arg(long, env = "QS_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address to listen on (default: 0.0.0.0:8080).
+    #[arg(long, env = "QS_LISTEN")]
+    listen: Option<SocketAddr>,
+
+    /// Directory for the central ledger and server key (default: current directory).
+    #[arg(long, env = "QS_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Ledger backend (default: jsonl).
+    #[arg(long, env = "QS_LEDGER_BACKEND", value_enum)]
+    ledger_backend: Option<LedgerBackend>,
+
+    /// Log filter, e.g. `info` or `qs_server=debug` (default: info).
+    #[arg(long, env = "QS_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// Maximum request body size in bytes (default: 1 MiB).
+    #[arg(long, env = "QS_MAX_BODY_BYTES")]
+    max_body_bytes: Option<usize>,
+
+    /// Maximum body size of `POST /upload` in bytes (default: 64 KiB).
+    #[arg(long, env = "QS_MAX_UPLOAD_BYTES")]
+    max_upload_bytes: Option<usize>,
+
+    /// Uploads per second allowed per uploading key (client IP for uploads that do not verify); 0
+    /// disables rate limiting (default: 10).
+    #[arg(long, env = "QS_UPLOAD_RATE_LIMIT")]
+    upload_rate_limit: Option<f64>,
+
+    /// Uploads a client may send at once before the rate limit applies (default: 100).
+    #[arg(long, env = "QS_UPLOAD_RATE_BURST")]
+    upload_rate_burst: Option<u32>,
+
+    /// Rate-limit uploads that do not verify by the first `X-Forwarded-For` address instead of the
+    /// peer address (only behind a reverse proxy that sets it).
+    #[arg(long, env = "QS_TRUST_FORWARDED_FOR")]
+    trust_forwarded_for: bool,
+
+    /// PEM certificate chain; with --tls-key, the server speaks HTTPS only.
+    #[arg(long, env = "QS_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the TLS certificate.
+    #[arg(long, env = "QS_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// PEM bundle of CAs for client certificates; every client must then present one (mutual TLS).
+    #[arg(long, env = "QS_TLS_CLIENT_CA")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Per-request timeout in seconds (default: 30).
+    #[arg(long, env = "QS_REQUEST_TIMEOUT_SECS")]
+    request_timeout_secs: Option<u64>,
+
+    /// Maximum number of requests handled concurrently (default: 256).
+    #[arg(long, env = "QS_MAX_CONCURRENT_REQUESTS")]
+    max_concurrent_requests: Option<usize>,
+
+    /// Private key used to sign receipts, timestamps and segment seals
+    /// (default: <data-dir>/server_private.key).
+    #[arg(long, env = "QS_SERVER_KEY")]
+    server_key: Option<PathBuf>,
+
+    /// Directory of enrolled public keys allowed to upload (default: <data-dir>/keys).
+    #[arg(long, env = "QS_KEYS_DIR")]
+    keys_dir: Option<PathBuf>,
+
+    /// Directory of keys for remote signing, as <name>.private.key plus <name>.public.key
+    /// (default: <data-dir>/signing_keys).
+    #[arg(long, env = "QS_SIGNING_KEYS_DIR")]
+    signing_keys_dir: Option<PathBuf>,
+
+    /// TOML file of API tokens and the signing keys each may use (default: <data-dir>/api_tokens.toml).
+    #[arg(long, env = "QS_API_TOKENS")]
+    api_tokens: Option<PathBuf>,
+
+    /// Directory of named verification policies, as <name>.json (default: <data-dir>/policies).
+    #[arg(long, env = "QS_POLICIES_DIR")]
+    policies_dir: Option<PathBuf>,
+
+    /// Directory of witness public keys whose tree head cosignatures are accepted
+    /// (default: <data-dir>/witnesses).
+    #[arg(long, env = "QS_WITNESSES_DIR")]
+    witnesses_dir: Option<PathBuf>,
+
+    /// TOML file of tenants, each with its own log under <data-dir>/tenants/<name>
+    /// (default: <data-dir>/tenants.toml).
+    #[arg(long, env = "QS_TENANTS")]
+    tenants: Option<PathBuf>,
+
+    /// Seal and rotate the ledger once the active segment reaches this many bytes.
+    #[arg(long, env = "QS_ROTATE_MAX_BYTES")]
+    rotate_max_bytes: Option<u64>,
+
+    /// Seal and rotate the ledger once its oldest entry is this many days old.
+    #[arg(long, env = "QS_ROTATE_MAX_DAYS")]
+    rotate_max_days: Option<u32>,
+
+    /// Run as a read-only mirror of the qs_server at this URL.
+    #[arg(long, env = "QS_MIRROR_OF")]
+    mirror_of: Option<String>,
+
+    /// Upstream server's public key, to check its signed tree heads (required with --mirror-of).
+    #[arg(long, env = "QS_MIRROR_PUBLIC_KEY")]
+    mirror_public_key: Option<PathBuf>,
+
+    /// Seconds between polls of the upstream (default: 10).
+    #[arg(long, env = "QS_MIRROR_INTERVAL_SECS")]
+    mirror_interval_secs: Option<u64>,
+}
+
+/// Config file contents; every key is optional.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    listen: Option<SocketAddr>,
+    data_dir: Option<PathBuf>,
+    ledger_backend: Option<LedgerBackend>,
+    log_level: Option<String>,
+    max_body_bytes: Option<usize>,
+    max_upload_bytes: Option<usize>,
+    upload_rate_limit: Option<f64>,
+    upload_rate_burst: Option<u32>,
+    trust_forwarded_for: Option<bool>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_client_ca: Option<PathBuf>,
+    request_timeout_secs: Option<u64>,
+    max_concurrent_requests: Option<usize>,
+    server_key: Option<PathBuf>,
+    keys_dir: Option<PathBuf>,
+    signing_keys_dir: Option<PathBuf>,
+    api_tokens: Option<PathBuf>,
+    policies_dir: Option<PathBuf>,
+    witnesses_dir: Option<PathBuf>,
+    tenants: Option<PathBuf>,
+    rotate_max_bytes: Option<u64>,
+    rotate_max_days: Option<u32>,
+    mirror_of: Option<String>,
+    mirror_public_key: Option<PathBuf>,
+    mirror_interval_secs: Option<u64>,
+}
+
+/// Upstream to follow in mirror mode.
+pub struct MirrorConfig {
+    pub upstream: String,
+    pub public_key: PathBuf,
+    pub interval: Duration,
+}
+
+/// Certificate and key to serve HTTPS with, and the CA bundle client certificates must chain to
+/// when mutual TLS is on.
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Resolved server configuration.
+pub struct Config {
+    pub listen: SocketAddr,
+    pub data_dir: PathBuf,
+    pub ledger_backend: LedgerBackend,
+    pub log_level: String,
+    pub max_body_bytes: usize,
+    pub max_upload_bytes: usize,
+    /// Uploads per second per client; `None` disables rate limiting.
+    pub upload_rate_limit: Option<f64>,
+    pub upload_rate_burst: u32,
+    pub trust_forwarded_for: bool,
+    /// HTTPS settings; `None` serves plain HTTP.
+    pub tls: Option<TlsConfig>,
+    pub request_timeout: Duration,
+    pub max_concurrent_requests: usize,
+    pub server_key: PathBuf,
+    pub keys_dir: PathBuf,
+    pub signing_keys_dir: PathBuf,
+    pub api_tokens: PathBuf,
+    pub policies_dir: PathBuf,
+    pub witnesses_dir: PathBuf,
+    pub tenants: PathBuf,
+    pub rotation: Rotation,
+    pub mirror: Option<MirrorConfig>,
+}
+
+impl Config {
+    /// Parse flags and environment, then fill gaps from the config file and defaults.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::from_args(Args::parse())
+    }
+
+    /// Resolve parsed flags and environment against the config file and defaults.
+    pub fn from_args(args: Args) -> anyhow::Result<Self> {
+        let file = match &args.config {
+            Some(path) => load_file(path)?,
+            None => FileConfig::default(),
+        };
+        let data_dir = args.data_dir.or(file.data_dir).unwrap_or_else(|| PathBuf::from("."));
+        let server_key = args
+            .server_key
+            .or(file.server_key)
+            .unwrap_or_else(|| data_dir.join("server_private.key"));
+        let keys_dir = args
+            .keys_dir
+            .or(file.keys_dir)
+            .unwrap_or_else(|| data_dir.join("keys"));
+        let signing_keys_dir = args
+            .signing_keys_dir
+            .or(file.signing_keys_dir)
+            .unwrap_or_else(|| data_dir.join("signing_keys"));
+        let api_tokens = args
+            .api_tokens
+            .or(file.api_tokens)
+            .unwrap_or_else(|| data_dir.join("api_tokens.toml"));
+        let policies_dir = args
+            .policies_dir
+            .or(file.policies_dir)
+            .unwrap_or_else(|| data_dir.join("policies"));
+        let witnesses_dir = args
+            .witnesses_dir
+            .or(file.witnesses_dir)
+            .unwrap_or_else(|| data_dir.join("witnesses"));
+        let tenants = args
+            .tenants
+            .or(file.tenants)
+            .unwrap_or_else(|| data_dir.join("tenants.toml"));
+        let mirror = match args.mirror_of.or(file.mirror_of) {
+            Some(upstream) => Some(MirrorConfig {
+                upstream,
+                public_key: args
+                    .mirror_public_key
+                    .or(file.mirror_public_key)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Mirror mode needs the upstream's public key (mirror_public_key)")
+                    })?,
+                interval: Duration::from_secs(
+                    args.mirror_interval_secs.or(file.mirror_interval_secs).unwrap_or(10),
+                ),
+            }),
+            None => None,
+        };
+        let tls = match (
+            args.tls_cert.or(file.tls_cert),
+            args.tls_key.or(file.tls_key),
+            args.tls_client_ca.or(file.tls_client_ca),
+        ) {
+            (Some(cert), Some(key), client_ca) => Some(TlsConfig {
+                cert,
+                key,
+                client_ca,
+            }),
+            (None, None, None) => None,
+            (_, _, Some(_)) => {
+                return Err(anyhow::anyhow!(
+                    "Client certificate verification (tls_client_ca) needs tls_cert and tls_key"
+                ))
+            }
+            _ => return Err(anyhow::anyhow!("TLS needs both tls_cert and tls_key")),
+        };
+        Ok(Self {
+            listen: args
+                .listen
+                .or(file.listen)
+                .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 8080))),
+            ledger_backend: args
+                .ledger_backend
+                .or(file.ledger_backend)
+                .unwrap_or(LedgerBackend::Jsonl),
+            log_level: args.log_level.or(file.log_level).unwrap_or_else(|| "info".to_string()),
+            max_body_bytes: args.max_body_bytes.or(file.max_body_bytes).unwrap_or(1024 * 1024),
+            max_upload_bytes: args.max_upload_bytes.or(file.max_upload_bytes).unwrap_or(64 * 1024),
+            upload_rate_limit: Some(
+                args.upload_rate_limit.or(file.upload_rate_limit).unwrap_or(10.0),
+            )
+            .filter(|rate| *rate > 0.0),
+            upload_rate_burst: args.upload_rate_burst.or(file.upload_rate_burst).unwrap_or(100),
+            trust_forwarded_for: args.trust_forwarded_for
+                || file.trust_forwarded_for.unwrap_or(false),
+            tls,
+            request_timeout: Duration::from_secs(
+                args.request_timeout_secs.or(file.request_timeout_secs).unwrap_or(30),
+            ),
+            max_concurrent_requests: args
+                .max_concurrent_requests
+                .or(file.max_concurrent_requests)
+                .unwrap_or(256),
+            rotation: Rotation {
+                rotate_max_bytes: args.rotate_max_bytes.or(file.rotate_max_bytes),
+                rotate_max_days: args.rotate_max_days.or(file.rotate_max_days),
+            },
+            server_key,
+            keys_dir,
+            signing_keys_dir,
+            api_tokens,
+            policies_dir,
+            witnesses_dir,
+            tenants,
+            mirror,
+            data_dir,
+        })
+    }
+
+    /// Central ledger path inside the data directory (`central_ledger.jsonl` or `central_ledger.db`).
+    pub fn ledger_path(&self) -> PathBuf {
+        self.data_dir.join(self.ledger_file_name())
+    }
+
+    fn ledger_file_name(&self) -> &'static str {
+        match self.ledger_backend {
+            LedgerBackend::Jsonl => "central_ledger.jsonl",
+            LedgerBackend::Sqlite => "central_ledger.db",
+        }
+    }
+
+    /// Files of the server's own log, as configured above.
+    pub fn log_paths(&self) -> LogPaths {
+        LogPaths {
+            dir: self.data_dir.clone(),
+            ledger: self.ledger_path(),
+            server_key: self.server_key.clone(),
+            keys_dir: self.keys_dir.clone(),
+            signing_keys_dir: self.signing_keys_dir.clone(),
+            api_tokens: self.api_tokens.clone(),
+            policies_dir: self.policies_dir.clone(),
+            witnesses_dir: self.witnesses_dir.clone(),
+        }
+    }
+
+    /// Files of a tenant's log: the default layout under `<data-dir>/tenants/<name>`.
+    pub fn tenant_paths(&self, name: &str) -> LogPaths {
+        let dir = self.data_dir.join("tenants").join(name);
+        LogPaths {
+            ledger: dir.join(self.ledger_file_name()),
+            server_key: dir.join("server_private.key"),
+            keys_dir: dir.join("keys"),
+            signing_keys_dir: dir.join("signing_keys"),
+            api_tokens: dir.join("api_tokens.toml"),
+            policies_dir: dir.join("policies"),
+            witnesses_dir: dir.join("witnesses"),
+            dir,
+        }
+    }
+}
+
+/// Where one log keeps its ledger, signing key, key registry, API tokens, remote signing keys,
+/// policies, witness keys and other state (`dir`).
+pub struct LogPaths {
+    pub dir: PathBuf,
+    pub ledger: PathBuf,
+    pub server_key: PathBuf,
+    pub keys_dir: PathBuf,
+    pub signing_keys_dir: PathBuf,
+    pub api_tokens: PathBuf,
+    pub policies_dir: PathBuf,
+    pub witnesses_dir: PathBuf,
+}
+
+fn load_file(path: &Path) -> anyhow::Result<FileConfig> {
+    let s = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path.display(), e))?;
+    toml::from_str(&s).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_environment_which_overrides_the_file_which_overrides_defaults() {
+        let dir = std::env::temp_dir().join(format!("qs_server_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("qs_server.toml");
+        std::fs::write(
+            &file,
+            "request_timeout_secs = 50\nmax_body_bytes = 2048\nmax_concurrent_requests = 12\n\
+             listen = \"127.0.0.1:9000\"\n",
+        )
+        .unwrap();
+        // Only this test sets QS_REQUEST_TIMEOUT_SECS, so parallel tests are unaffected.
+        std::env::set_var("QS_REQUEST_TIMEOUT_SECS", "45");
+        let parse = |flags: &[&str]| {
+            let args = ["qs_server", "--config", file.to_str().unwrap()]
+                .into_iter()
+                .chain(flags.iter().copied());
+            Config::from_args(Args::try_parse_from(args).unwrap()).unwrap()
+        };
+        let from_env = parse(&["--max-body-bytes", "4096"]);
+        let from_flag = parse(&["--request-timeout-secs", "60"]);
+        std::env::remove_var("QS_REQUEST_TIMEOUT_SECS");
+        let from_file = parse(&[]);
+
+        assert_eq!(from_flag.request_timeout, Duration::from_secs(60));
+        assert_eq!(from_env.request_timeout, Duration::from_secs(45));
+        assert_eq!(from_file.request_timeout, Duration::from_secs(50));
+        assert_eq!(from_env.max_body_bytes, 4096);
+        assert_eq!(from_file.max_body_bytes, 2048);
+        assert_eq!(from_file.max_concurrent_requests, 12);
+        assert_eq!(from_file.listen, SocketAddr::from(([127, 0, 0, 1], 9000)));
+        // Keys set nowhere fall back to the defaults, including paths under the data directory.
+        assert_eq!(from_file.upload_rate_burst, 100);
+        assert_eq!(from_file.max_upload_bytes, 64 * 1024);
+        assert_eq!(from_file.data_dir, PathBuf::from("."));
+        assert_eq!(from_file.server_key, PathBuf::from("./server_private.key"));
+        assert!(from_file.tls.is_none() && from_file.mirror.is_none());
+    }
+}
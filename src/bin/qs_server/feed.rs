@@ -0,0 +1,244 @@
+//! Live feed of appended entries for monitors: `GET /entries/stream` (server-sent events).
+
+use crate::{ApiError, AppState};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::Stream;
+use qs_notary::ledger::EntryFilter;
+use qs_notary::protocol::IndexedEntry;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Entries buffered per subscriber before it lags and has to catch up from the store.
+const FEED_CAPACITY: usize = 1024;
+/// Page size when catching up from the store.
+const BACKFILL_PAGE: u64 = 1000;
+/// Comment sent on idle streams so clients and proxies can tell a quiet log from a dead connection.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Fan-out of newly appended entries to open streams.
+pub struct Feed {
+    entries: broadcast::Sender<IndexedEntry>,
+    closing: watch::Sender<bool>,
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Self {
+            entries: broadcast::channel(FEED_CAPACITY).0,
+            closing: watch::channel(false).0,
+        }
+    }
+
+    /// Announce an entry just appended to the log (called by the writer).
+    pub fn publish(&self, entry: IndexedEntry) {
+        // No subscribers is not an error.
+        let _ = self.entries.send(entry);
+    }
+
+    /// End every open stream, so graceful shutdown does not wait on them forever.
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    /// Replay entries with a log index greater than this before going live.
+    after: Option<u64>,
+    key_id: Option<String>,
+}
+
+/// `GET /entries/stream?after=&key_id=`: one `entry` event per appended entry (data: `{ "index",
+/// "entry" }`, id: the log index), optionally only for one signer. With `after` (or a
+/// `Last-Event-ID` header, which wins) entries after that index are replayed from the log first,
+/// so a monitor that reconnects misses nothing; without it the stream starts with the next append.
+pub async fn stream(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let after = match headers.get("last-event-id") {
+        Some(v) => Some(
+            v.to_str()
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .ok_or((StatusCode::BAD_REQUEST, "invalid Last-Event-ID".to_string()))?,
+        ),
+        None => query.after,
+    };
+    let mut filter = EntryFilter {
+        key_id: query.key_id,
+        ..Default::default()
+    };
+    if let Some(ref tenant) = state.tenant {
+        tenant.retain(&mut filter);
+    }
+    // Subscribe before reading the log size: the writer records an entry's index before
+    // publishing it, so every later entry still reaches this subscriber.
+    let live = state.feed.entries.subscribe();
+    let next = after.map_or_else(|| state.leaves.read().unwrap().len(), |a| a + 1);
+    let closing = state.feed.closing.subscribe();
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(forward(state, filter, next, after.is_some(), live, closing, tx));
+    let events = futures_util::stream::unfold(rx, |mut rx| async move {
+        let entry: IndexedEntry = rx.recv().await?;
+        let event = Event::default()
+            .event("entry")
+            .id(entry.index.to_string())
+            .json_data(&entry);
+        Some((event, rx))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::new().interval(KEEP_ALIVE)))
+}
+
+/// Send matching entries from log index `next` on to one subscriber until it disconnects or the
+/// server shuts down: first the backlog (if `replay`), then live appends. A subscriber that falls
+/// behind the broadcast buffer catches up from the store. Entries are sent in log order without
+/// repeats.
+async fn forward(
+    state: AppState,
+    filter: EntryFilter,
+    mut next: u64,
+    replay: bool,
+    mut live: broadcast::Receiver<IndexedEntry>,
+    mut closing: watch::Receiver<bool>,
+    tx: mpsc::Sender<IndexedEntry>,
+) {
+    if replay {
+        match backfill(&state, &filter, next, &tx).await {
+            Some(n) => next = n,
+            None => return,
+        }
+    }
+    loop {
+        let received = tokio::select! {
+            r = live.recv() => r,
+            _ = tx.closed() => return,
+            _ = closing.wait_for(|closed| *closed) => return,
+        };
+        match received {
+            Ok(entry) => {
+                if entry.index < next {
+                    continue;
+                }
+                next = entry.index + 1;
+                if filter.matches(&entry.entry) && tx.send(entry).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!(skipped, "feed subscriber lagged; catching up from the log");
+                match backfill(&state, &filter, next, &tx).await {
+                    Some(n) => next = n,
+                    None => return,
+                }
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Send stored matching entries from index `next` on. Returns the index after the last one sent
+/// (`next` if none), or `None` if the subscriber is gone or the store failed.
+async fn backfill(
+    state: &AppState,
+    filter: &EntryFilter,
+    mut next: u64,
+    tx: &mpsc::Sender<IndexedEntry>,
+) -> Option<u64> {
+    loop {
+        // Each page starts at the next unsent index, so stores skip what was already sent.
+        let page_filter = EntryFilter {
+            from_index: Some(next),
+            limit: Some(BACKFILL_PAGE),
+            ..filter.clone()
+        };
+        let rows = crate::read_store(state, move |store| store.query(&page_filter))
+            .await
+            .ok()?;
+        let full = rows.len() as u64 == BACKFILL_PAGE;
+        for (index, entry) in rows {
+            tx.send(IndexedEntry { index, entry }).await.ok()?;
+            next = index + 1;
+        }
+        if !full {
+            return Some(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+    use axum::body::BodyDataStream;
+    use axum::http::StatusCode;
+    use futures_util::StreamExt;
+    use qs_notary::protocol::IndexedEntry;
+
+    /// Read `count` `entry` events off an SSE body, checking each event's id against its entry.
+    async fn read_events(body: &mut BodyDataStream, buffer: &mut String, count: usize) -> Vec<u64> {
+        let mut indexes = Vec::new();
+        while indexes.len() < count {
+            let Some(end) = buffer.find("\n\n") else {
+                let chunk = tokio::time::timeout(std::time::Duration::from_secs(10), body.next())
+                    .await
+                    .expect("no event within 10s")
+                    .expect("stream ended")
+                    .unwrap();
+                buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+                continue;
+            };
+            let event: String = buffer.drain(..end + 2).collect();
+            let field = |name: &str| {
+                event
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .map(|value| value.trim_start().to_string())
+            };
+            assert_eq!(field("event:").as_deref(), Some("entry"), "{}", event);
+            let entry: IndexedEntry = serde_json::from_str(&field("data:").unwrap()).unwrap();
+            assert_eq!(field("id:"), Some(entry.index.to_string()));
+            indexes.push(entry.index);
+        }
+        indexes
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_replays_after_an_index_then_goes_live_without_gaps_or_repeats() {
+        let log = testing::open("feed_stream", &[]);
+        let key = log.enroll("signer");
+        for i in 0..3 {
+            let entry = testing::signed_entry(&key, &format!("before-{}.json", i));
+            log.state.writer.append(entry).await.unwrap();
+        }
+
+        let (status, mut body) = log.open_stream("/entries/stream?after=0").await;
+        assert_eq!(status, StatusCode::OK);
+        // Appends racing the replay are delivered once, whichever of the two picks them up.
+        let writer = log.state.writer.clone();
+        let appends = tokio::spawn({
+            let key = log.enroll("racer");
+            async move {
+                for i in 0..3 {
+                    let entry = testing::signed_entry(&key, &format!("during-{}.json", i));
+                    writer.append(entry).await.unwrap();
+                }
+            }
+        });
+        let mut buffer = String::new();
+        assert_eq!(read_events(&mut body, &mut buffer, 5).await, vec![1, 2, 3, 4, 5]);
+        appends.await.unwrap();
+
+        for i in 0..2 {
+            let entry = testing::signed_entry(&key, &format!("after-{}.json", i));
+            log.state.writer.append(entry).await.unwrap();
+        }
+        assert_eq!(read_events(&mut body, &mut buffer, 2).await, vec![6, 7]);
+        assert!(buffer.is_empty(), "{}", buffer);
+    }
+}
@@ -0,0 +1,155 @@
+//! Idempotency keys for `POST /upload`: a retry that repeats the `Idempotency-Key` header of an
+//! accepted upload gets a receipt for the entry logged the first time instead of a second entry.
+
+use axum::http::{HeaderMap, StatusCode};
+use qs_notary::protocol::SignedUpload;
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a key is remembered after its upload.
+const KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Keys remembered per log.
+const MAX_KEYS: usize = 100_000;
+/// Longest accepted key.
+const MAX_KEY_LEN: usize = 255;
+
+type Rejection = (StatusCode, &'static str);
+
+enum Slot {
+    /// The first request with the key is still running.
+    Pending { request: [u8; 32], since: Instant },
+    /// The upload was logged at `log_index`.
+    Done {
+        request: [u8; 32],
+        log_index: u64,
+        since: Instant,
+    },
+}
+
+impl Slot {
+    fn since(&self) -> Instant {
+        match self {
+            Slot::Pending { since, .. } | Slot::Done { since, .. } => *since,
+        }
+    }
+}
+
+/// Outcome of claiming a key.
+pub enum Claim {
+    /// First use: run the upload and complete the guard.
+    New(Guard),
+    /// Repeat of an accepted upload, logged at this index.
+    Replay(u64),
+}
+
+/// Keys seen by one log in the last `KEY_TTL`, in memory (after a restart a retried upload of the
+/// same entry is still recognised by its leaf hash).
+#[derive(Default)]
+pub struct IdempotencyKeys {
+    slots: Mutex<HashMap<String, Slot>>,
+}
+
+/// The `Idempotency-Key` header, if present; 400 unless it is 1-255 visible ASCII characters.
+pub fn key(headers: &HeaderMap) -> Result<Option<String>, Rejection> {
+    let Some(value) = headers.get("idempotency-key") else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .filter(|k| !k.is_empty() && k.len() <= MAX_KEY_LEN && k.bytes().all(|b| b.is_ascii_graphic()))
+        .map(|k| Some(k.to_string()))
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "Idempotency-Key must be 1-255 visible ASCII characters",
+        ))
+}
+
+/// Fingerprint of an upload, to tell a retry from a different request that reuses the key.
+fn fingerprint(upload: &SignedUpload) -> [u8; 32] {
+    Sha3_256::digest(serde_json::to_vec(upload).unwrap_or_default()).into()
+}
+
+impl IdempotencyKeys {
+    /// Claim `key` for `upload`. 422 if the key was used for a different upload, 409 while the
+    /// first request with it is still running, 503 if the log holds `MAX_KEYS` live keys.
+    pub fn claim(self: &Arc<Self>, key: String, upload: &SignedUpload) -> Result<Claim, Rejection> {
+        let request = fingerprint(upload);
+        let mut slots = self.slots.lock().unwrap();
+        if slots.len() >= MAX_KEYS {
+            slots.retain(|_, slot| slot.since().elapsed() < KEY_TTL);
+        }
+        match slots.get(&key) {
+            Some(slot) if slot.since().elapsed() < KEY_TTL => {
+                return match *slot {
+                    Slot::Pending { request: r, .. } | Slot::Done { request: r, .. }
+                        if r != request =>
+                    {
+                        Err((
+                            StatusCode::UNPROCESSABLE_ENTITY,
+                            "Idempotency-Key was used for a different upload",
+                        ))
+                    }
+                    Slot::Pending { .. } => Err((
+                        StatusCode::CONFLICT,
+                        "an upload with this Idempotency-Key is in progress",
+                    )),
+                    Slot::Done { log_index, .. } => Ok(Claim::Replay(log_index)),
+                };
+            }
+            _ => {}
+        }
+        if slots.len() >= MAX_KEYS {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "too many idempotency keys; retry later",
+            ));
+        }
+        slots.insert(
+            key.clone(),
+            Slot::Pending {
+                request,
+                since: Instant::now(),
+            },
+        );
+        Ok(Claim::New(Guard {
+            keys: self.clone(),
+            key,
+            request,
+            done: false,
+        }))
+    }
+}
+
+/// A claimed key. `complete` records where the upload was logged; dropping the guard without it
+/// (the upload failed) frees the key for a retry.
+pub struct Guard {
+    keys: Arc<IdempotencyKeys>,
+    key: String,
+    request: [u8; 32],
+    done: bool,
+}
+
+impl Guard {
+    pub fn complete(mut self, log_index: u64) {
+        self.keys.slots.lock().unwrap().insert(
+            std::mem::take(&mut self.key),
+            Slot::Done {
+                request: self.request,
+                log_index,
+                since: Instant::now(),
+            },
+        );
+        self.done = true;
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if !self.done {
+            self.keys.slots.lock().unwrap().remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Key registry endpoints: enrolment and revocation (admin tokens) and key distribution.
+
+use crate::{ApiError, AppState};
+use axum::{
+    extract::{Json, Path as UrlPath, State},
+    http::{HeaderMap, StatusCode},
+};
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use pqcrypto_traits::sign::PublicKey as _;
+use qs_notary::key_provider::KeyProvider;
+use qs_notary::ledger::{KeyRevocation, LedgerEntry};
+use qs_notary::protocol::{
+    self, EnrollRequest, KeyInfo, RegistrySnapshot, RevokeRequest, RevokeResponse,
+};
+use sha3::{Digest, Sha3_256};
+
+/// `GET /keys`: every registered key with its revocation, if any.
+pub async fn list(State(state): State<AppState>) -> Json<RegistrySnapshot> {
+    Json(state.registry.snapshot())
+}
+
+/// `GET /keys/{id}`: one registered key; 404 if unknown.
+pub async fn get(
+    State(state): State<AppState>,
+    UrlPath(key_id): UrlPath<String>,
+) -> Result<Json<KeyInfo>, ApiError> {
+    state
+        .registry
+        .info(&key_id)
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "no such key".to_string()))
+}
+
+/// `POST /keys`: enroll a public key so it may upload. Needs an admin token; 400 for a malformed
+/// key. Enrolling a registered key returns it unchanged.
+pub async fn enroll(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<EnrollRequest>,
+) -> Result<Json<KeyInfo>, ApiError> {
+    let admin = state.tokens.admin(&headers)?;
+    let public_key = hex::decode(req.public_key.trim())
+        .ok()
+        .and_then(|b| PublicKey::from_bytes(&b).ok())
+        .ok_or((StatusCode::BAD_REQUEST, "invalid public_key".to_string()))?;
+    let _guard = state.admin_lock.lock().await;
+    let info = state.registry.enroll(public_key).map_err(|e| {
+        tracing::error!(error = %e, "enrolment failed");
+        (StatusCode::INTERNAL_SERVER_ERROR, "enroll".to_string())
+    })?;
+    tracing::info!(admin = %admin, key_id = %info.key_id, "key enrolled");
+    Ok(Json(info))
+}
+
+/// `POST /keys/{id}/revoke`: revoke a key from `effective_at` (default now). The revocation is
+/// signed with the server key and appended to the log before the registry changes, so the log
+/// alone is enough to rebuild it. Needs an admin token; 404 for an unknown key, 409 if it is
+/// already revoked, 400 for a malformed `effective_at`.
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    UrlPath(key_id): UrlPath<String>,
+    Json(req): Json<RevokeRequest>,
+) -> Result<Json<RevokeResponse>, ApiError> {
+    let admin = state.tokens.admin(&headers)?;
+    let effective_at = match req.effective_at.as_deref() {
+        Some(t) => chrono::DateTime::parse_from_rfc3339(t)
+            .map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "effective_at must be an RFC 3339 time".to_string(),
+                )
+            })?
+            .to_rfc3339(),
+        None => chrono::Utc::now().to_rfc3339(),
+    };
+    let _guard = state.admin_lock.lock().await;
+    let key = state
+        .registry
+        .get(&key_id)
+        .ok_or((StatusCode::NOT_FOUND, "no such key".to_string()))?;
+    if key.revocation.is_some() {
+        return Err((StatusCode::CONFLICT, "key already revoked".to_string()));
+    }
+    let revocation = KeyRevocation {
+        key_id: key_id.to_lowercase(),
+        reason: req.reason,
+        effective_at,
+    };
+    let signer = state.server_signer.clone();
+    let to_sign = revocation.clone();
+    let (digest, sig_bytes) = tokio::task::spawn_blocking(move || {
+        let message = protocol::revocation_message(&to_sign)?;
+        Ok::<_, anyhow::Error>((Sha3_256::digest(&message), signer.sign(&message)?))
+    })
+    .await
+    .map_err(crate::internal)?
+    .map_err(|e| {
+        tracing::error!(error = %e, "signing revocation failed");
+        (StatusCode::INTERNAL_SERVER_ERROR, "sign".to_string())
+    })?;
+    let entry = LedgerEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        file_name: "key-revocation".to_string(),
+        signature_hash: hex::encode(sig_bytes),
+        digest: Some(hex::encode(digest)),
+        key_id: Some(state.log_key_id.clone()),
+        revocation: Some(revocation.clone()),
+        attributes: None,
+    };
+    let receipt = state
+        .writer
+        .append(entry)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "ledger write failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "write".to_string())
+        })?
+        .receipt;
+    state.registry.revoke(revocation.clone());
+    tracing::info!(
+        admin = %admin,
+        key_id = %revocation.key_id,
+        effective_at = %revocation.effective_at,
+        index = receipt.body.log_index,
+        "key revoked"
+    );
+    Ok(Json(RevokeResponse {
+        revocation,
+        receipt,
+    }))
+}
@@ -0,0 +1,245 @@
+//! Upload limits: field validation and a per-uploader token-bucket rate limiter.
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use qs_notary::protocol::{SignedAttributes, SignedUpload};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest accepted `file_name`, in bytes.
+pub const MAX_FILE_NAME_BYTES: usize = 1024;
+/// Longest accepted signed attribute value, in bytes.
+const MAX_ATTRIBUTE_BYTES: usize = 64;
+/// Clients tracked before idle ones (whose bucket has refilled) are forgotten; if none is idle the
+/// least recently seen one is.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+type Rejection = (StatusCode, &'static str);
+
+fn check(ok: bool, reason: &'static str) -> Result<(), Rejection> {
+    if ok {
+        Ok(())
+    } else {
+        Err((StatusCode::BAD_REQUEST, reason))
+    }
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_sha3(s: &str) -> bool {
+    is_hex(s, 64)
+}
+
+fn is_signature(s: &str) -> bool {
+    is_hex(s, 2 * pqcrypto_dilithium::dilithium5::signature_bytes())
+}
+
+/// 400 unless `name` is 1 to `MAX_FILE_NAME_BYTES` bytes without control characters.
+pub fn validate_file_name(name: &str) -> Result<(), Rejection> {
+    check(
+        !name.is_empty()
+            && name.len() <= MAX_FILE_NAME_BYTES
+            && !name.chars().any(char::is_control),
+        "file_name must be 1-1024 bytes without control characters",
+    )
+}
+
+/// 400 unless each signed attribute is 1 to `MAX_ATTRIBUTE_BYTES` bytes without control
+/// characters.
+pub fn validate_attributes(attributes: &SignedAttributes) -> Result<(), Rejection> {
+    let ok = |value: &str| {
+        !value.is_empty() && value.len() <= MAX_ATTRIBUTE_BYTES && !value.chars().any(char::is_control)
+    };
+    check(
+        ok(&attributes.sbom_format)
+            && ok(&attributes.spec_version)
+            && ok(&attributes.encoding)
+            && attributes.canonicalization.as_deref().is_none_or(ok),
+        "attributes must be 1-64 bytes each without control characters",
+    )
+}
+
+/// 400 unless every field of the upload is well-formed: hex key ids and digest, hex Dilithium5
+/// signatures, an RFC 3339 timestamp, a bounded file name and bounded attributes. Runs before any key lookup or
+/// signature check.
+pub fn validate_upload(upload: &SignedUpload) -> Result<(), Rejection> {
+    let entry = &upload.entry;
+    check(is_sha3(&upload.key_id), "key_id must be a hex SHA3-256 key id")?;
+    check(
+        is_signature(&upload.signature),
+        "signature must be a hex Dilithium5 signature",
+    )?;
+    check(
+        is_signature(&entry.signature_hash),
+        "entry.signature_hash must be a hex Dilithium5 signature",
+    )?;
+    check(
+        chrono::DateTime::parse_from_rfc3339(&entry.timestamp).is_ok(),
+        "entry.timestamp must be an RFC 3339 time",
+    )?;
+    validate_file_name(&entry.file_name)?;
+    check(
+        entry.digest.as_deref().is_none_or(is_sha3),
+        "entry.digest must be a hex SHA3-256 hash",
+    )?;
+    check(
+        entry.key_id.as_deref().is_none_or(is_sha3),
+        "entry.key_id must be a hex SHA3-256 key id",
+    )?;
+    entry.attributes.as_ref().map_or(Ok(()), validate_attributes)
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket per client: up to `burst` requests at once, refilled at `rate` per second. A
+/// client is the uploading key once its signature has been verified, otherwise its IP address, so
+/// signers behind one address do not share a bucket and made-up key ids cannot each get a fresh one.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32, trust_forwarded_for: bool) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1).into(),
+            trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for `client`; if there is none, the time until the next one.
+    fn take(&self, client: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let refilled = |b: &Bucket| {
+            (b.tokens + now.duration_since(b.updated).as_secs_f64() * self.rate).min(self.burst)
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, b| refilled(b) < self.burst);
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, b)| b.updated)
+                    .map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = refilled(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Client name of a verified uploading key.
+    pub fn key_client(key_id: &str) -> String {
+        format!("key:{}", key_id.to_ascii_lowercase())
+    }
+
+    /// Client name of a request without a verified key: the first `X-Forwarded-For` address if
+    /// trusted, else the peer address.
+    pub fn ip_client(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> String {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+            .flatten()
+            .and_then(|v| v.split(',').next())
+            .map(|ip| ip.trim().to_string());
+        let peer = || peer.map(|p| p.ip().to_string());
+        format!("ip:{}", forwarded.or_else(peer).unwrap_or_default())
+    }
+
+    /// Take a token for `client`; once its bucket is empty, the time until the next one.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.take(client).inspect_err(|_| {
+            tracing::warn!(client = %client, "upload rate limit exceeded");
+        })
+    }
+}
+
+/// 429 with `Retry-After` (whole seconds) for a client that has to `wait`.
+pub fn rate_limited(wait: Duration) -> Response {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after.to_string())],
+        "rate limit exceeded",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn forwarded_for_is_used_only_when_trusted() {
+        let peer = Some(SocketAddr::from(([192, 0, 2, 7], 4000)));
+        let forwarded = headers("198.51.100.1, 192.0.2.7");
+        let limiter = RateLimiter::new(1.0, 1, false);
+        assert_eq!(limiter.ip_client(&forwarded, peer), "ip:192.0.2.7");
+        let limiter = RateLimiter::new(1.0, 1, true);
+        assert_eq!(limiter.ip_client(&forwarded, peer), "ip:198.51.100.1");
+        assert_eq!(limiter.ip_client(&HeaderMap::new(), peer), "ip:192.0.2.7");
+    }
+
+    #[test]
+    fn empty_bucket_answers_with_retry_after() {
+        let limiter = RateLimiter::new(0.5, 1, false);
+        let client = RateLimiter::key_client("AB");
+        assert_eq!(client, "key:ab");
+        limiter.check(&client).unwrap();
+        let response = rate_limited(limiter.check(&client).unwrap_err());
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        limiter.check("key:cd").unwrap();
+    }
+
+    #[test]
+    fn tracked_clients_are_capped() {
+        let limiter = RateLimiter::new(0.001, 1, false);
+        for client in ["ip:oldest", "ip:touched"] {
+            limiter.take(client).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // Every bucket is drained, so none counts as idle.
+        for i in 2..MAX_TRACKED_CLIENTS {
+            limiter.take(&format!("ip:{}", i)).unwrap();
+        }
+        assert!(limiter.take("ip:touched").is_err());
+        limiter.take("ip:new").unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_CLIENTS);
+        assert!(buckets.contains_key("ip:new"));
+        assert!(buckets.contains_key("ip:touched"));
+        assert!(!buckets.contains_key("ip:oldest"), "least recently seen client is evicted");
+    }
+}
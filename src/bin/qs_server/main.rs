@@ -0,0 +1,1276 @@
+//! qs_server: HTTP server for transparency log uploads (POST /upload -> central_ledger.jsonl), with
+//! optional per-tenant logs under `/t/{tenant}`.
+
+mod auth;
+mod config;
+mod feed;
+mod idempotency;
+mod keys;
+mod limits;
+mod metrics;
+mod mirror;
+mod policies;
+mod registry;
+mod signing;
+mod tenants;
+mod tls;
+mod tree;
+mod tsa;
+#[cfg(test)]
+mod testing;
+mod writer;
+
+use auth::ApiTokens;
+use axum_server::tls_rustls::RustlsConfig;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, Json, Path as UrlPath, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Router,
+};
+use config::{Config, LogPaths, MirrorConfig};
+use feed::Feed;
+use idempotency::{Claim, IdempotencyKeys};
+use limits::RateLimiter;
+use metrics::{LedgerStats, Metrics};
+use mirror::Mirror;
+use policies::PolicyStore;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use pqcrypto_traits::sign::PublicKey as _;
+use qs_notary::crypto;
+use qs_notary::key_provider::{self, FileSystemProvider};
+use qs_notary::ledger::{self, EntryFilter, LedgerEntry, LedgerStore};
+use qs_notary::protocol::{
+    self, EntryPage, IndexedEntry, RegistrySnapshot, SignRequest, SignatureEnvelope,
+    SignedUpload, TimestampRequest, TimestampToken, Verdict, VerifyRequest,
+};
+use qs_notary::verify as verifier;
+use registry::{KeyRegistry, RegisteredKey};
+use signing::SigningService;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tower::limit::ConcurrencyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use tenants::{Tenant, TenantConfig};
+use tokio::task::JoinHandle;
+use tree::{Cosignatures, TreeHeads};
+use tsa::TimestampAuthority;
+use writer::{Appended, LeafIndex, LedgerWriter, QuotaExceeded};
+
+#[derive(Clone)]
+struct AppState {
+    writer: LedgerWriter,
+    leaves: LeafIndex,
+    feed: Arc<Feed>,
+    metrics: Arc<Metrics>,
+    ledger_path: PathBuf,
+    tree_heads: Arc<TreeHeads>,
+    cosignatures: Arc<Cosignatures>,
+    /// Set in mirror mode.
+    mirror: Option<Arc<Mirror>>,
+    registry: Arc<KeyRegistry>,
+    tsa: Arc<TimestampAuthority>,
+    signing: Arc<SigningService>,
+    policies: Arc<PolicyStore>,
+    tokens: Arc<ApiTokens>,
+    /// Server key, for signing logged revocations.
+    server_signer: Arc<FileSystemProvider>,
+    log_key_id: String,
+    /// Public half of the server key, to check receipts presented to `/verify`.
+    log_public_key: Arc<PublicKey>,
+    /// Serializes registry changes, so a key cannot be revoked twice concurrently.
+    admin_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Separate store handle for read endpoints, so queries never wait behind the writer queue.
+    reader: Arc<Mutex<Box<dyn LedgerStore>>>,
+    /// `Idempotency-Key`s of recent uploads.
+    idempotency: Arc<IdempotencyKeys>,
+    /// Set on a tenant's log: its name, quota and retention.
+    tenant: Option<Arc<TenantConfig>>,
+    /// Every tenant, for the cross-tenant admin view (on the server's own log).
+    tenants: Arc<Vec<Tenant>>,
+}
+
+/// Default and maximum page size for `GET /entries`.
+const DEFAULT_PAGE_SIZE: u64 = 100;
+const MAX_PAGE_SIZE: u64 = 1000;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let config = Config::load()?;
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::try_new(&config.log_level)?)
+        .init();
+
+    if config.rotation.is_enabled() && config.ledger_backend != ledger::LedgerBackend::Jsonl {
+        return Err(anyhow::anyhow!("Ledger rotation is only supported for the jsonl backend"));
+    }
+    std::fs::create_dir_all(&config.data_dir).map_err(|e| {
+        anyhow::anyhow!("Failed to create data dir {}: {}", config.data_dir.display(), e)
+    })?;
+    // The timestamp authority and metrics are shared by every log on this server.
+    let tsa = Arc::new(TimestampAuthority::open(
+        &config.data_dir,
+        Box::new(FileSystemProvider::new(&config.server_key)),
+        crypto::key_id(&server_public_key(&config.server_key)?),
+    )?);
+    let metrics = Arc::new(Metrics::new());
+    let (mut state, writer_task) = open_log(
+        &config,
+        &config.log_paths(),
+        &metrics,
+        &tsa,
+        None,
+        config.mirror.as_ref(),
+    )?;
+    let tenant_configs = tenants::load(&config.tenants)?;
+    if config.mirror.is_some() && !tenant_configs.is_empty() {
+        return Err(anyhow::anyhow!("Tenants are not supported in mirror mode"));
+    }
+    let mut feeds = vec![state.feed.clone()];
+    let mut writer_tasks = vec![writer_task];
+    let mut tenant_states = Vec::new();
+    let mut tenant_list = Vec::new();
+    for tenant in tenant_configs {
+        let tenant = Arc::new(tenant);
+        let paths = config.tenant_paths(&tenant.name);
+        let (tenant_state, task) =
+            open_log(&config, &paths, &metrics, &tsa, Some(tenant.clone()), None)?;
+        feeds.push(tenant_state.feed.clone());
+        writer_tasks.push(task);
+        tenant_list.push(Tenant::of(&tenant_state, tenant.clone()));
+        tenant_states.push((tenant, tenant_state));
+    }
+    if !tenant_list.is_empty() {
+        tracing::info!(tenants = tenant_list.len(), file = %config.tenants.display(), "tenants loaded");
+    }
+    state.tenants = Arc::new(tenant_list);
+    let mirror_task = state.mirror.clone().map(|m| {
+        tokio::spawn(m.run(state.writer.clone(), state.leaves.clone(), state.tree_heads.clone()))
+    });
+
+    let limiter = rate_limiter(&config);
+    let routes = log_routes(&config, limiter.clone(), state.mirror.is_some())
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(render_metrics));
+    let routes = if state.mirror.is_some() {
+        routes
+    } else {
+        routes.route("/admin/tenants", get(tenants::list))
+    };
+    let ledger_path = state.ledger_path.clone();
+    let mut app: Router = routes.with_state(state);
+    for (tenant, tenant_state) in tenant_states {
+        app = app.nest(
+            &format!("/t/{}", tenant.name),
+            log_routes(&config, limiter.clone(), false).with_state(tenant_state),
+        );
+    }
+    let app = app
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(TimeoutLayer::with_status_code(
+            StatusCode::REQUEST_TIMEOUT,
+            config.request_timeout,
+        ))
+        .layer(ConcurrencyLimitLayer::new(config.max_concurrent_requests))
+        .layer(axum::middleware::from_fn_with_state(metrics, metrics::track))
+        // Access log: one span per request (method, URI) and an INFO event with status and latency.
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO))
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(tracing::Level::INFO)
+                        .latency_unit(tower_http::LatencyUnit::Millis),
+                ),
+        );
+
+    let rustls = match &config.tls {
+        Some(tls) => Some(RustlsConfig::from_config(Arc::new(tls::server_config(tls)?))),
+        None => None,
+    };
+    let listener = tokio::net::TcpListener::bind(config.listen)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to bind {}: {}", config.listen, e))?;
+    tracing::info!(
+        listen = %config.listen,
+        tls = config.tls.is_some(),
+        mutual_tls = config.tls.as_ref().is_some_and(|t| t.client_ca.is_some()),
+        ledger = %ledger_path.display(),
+        "qs_server started"
+    );
+    let shutdown = async move {
+        shutdown_signal().await;
+        for feed in feeds {
+            feed.close();
+        }
+    };
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    match rustls {
+        Some(rustls) => {
+            let handle = axum_server::Handle::new();
+            let on_shutdown = handle.clone();
+            tokio::spawn(async move {
+                shutdown.await;
+                on_shutdown.graceful_shutdown(None);
+            });
+            axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+                .handle(handle)
+                .serve(app)
+                .await?;
+        }
+        None => axum::serve(listener, app).with_graceful_shutdown(shutdown).await?,
+    }
+
+    // Stop mirroring before waiting for the writer: the mirror task holds a writer handle.
+    if let Some(task) = mirror_task {
+        task.abort();
+        let _ = task.await;
+    }
+    // The router (and with it every writer handle) is gone; wait for queued writes to land.
+    for task in writer_tasks {
+        task.await?;
+    }
+    tracing::info!("qs_server stopped; pending writes flushed");
+    Ok(())
+}
+
+/// Endpoints of one log: the server's own at the root, each tenant's under `/t/{tenant}`. Read
+/// endpoints are served by every instance; a mirror accepts no writes. `POST /upload` has its own
+/// body limit and, with `limiter`, a per-uploader rate limit.
+fn log_routes(config: &Config, limiter: Option<Arc<RateLimiter>>, mirror: bool) -> Router<AppState> {
+    let routes = Router::new()
+        .route("/tree-head", get(tree::current))
+        .route("/tree-head/consistency", get(tree::consistency))
+        .route("/entries/:index/proof", get(tree::inclusion))
+        .route("/entries", get(list_entries))
+        .route("/entries/stream", get(feed::stream))
+        .route("/entries/:index", get(entry_by_index))
+        .route("/entries/by-digest/:digest", get(entries_by_digest))
+        .route("/entries/by-leaf/:leaf_hash", get(entry_by_leaf));
+    if mirror {
+        routes.route("/mirror/status", get(mirror::status))
+    } else {
+        let mut upload_route = post(upload).layer(DefaultBodyLimit::max(config.max_upload_bytes));
+        if let Some(limiter) = limiter {
+            upload_route = upload_route.layer(Extension(limiter));
+        }
+        routes
+            .route("/tree-head/cosigned", get(tree::cosigned))
+            .route("/tree-head/cosignatures", post(tree::cosign))
+            .route("/upload", upload_route)
+            .route("/timestamp", post(timestamp))
+            .route("/sign", post(remote_sign))
+            .route("/verify", post(verify))
+            .route("/keys", get(keys::list).post(keys::enroll))
+            .route("/keys/:key_id", get(keys::get))
+            .route("/keys/:key_id/revoke", post(keys::revoke))
+    }
+}
+
+/// The upload rate limiter shared by all logs, unless rate limiting is disabled.
+fn rate_limiter(config: &Config) -> Option<Arc<RateLimiter>> {
+    config.upload_rate_limit.map(|rate| {
+        Arc::new(RateLimiter::new(
+            rate,
+            config.upload_rate_burst,
+            config.trust_forwarded_for,
+        ))
+    })
+}
+
+/// Open one log (the server's own, or a tenant's with `tenant` set) with its key registry, API
+/// tokens, remote signing keys, policies and witnesses, and spawn its writer. With `mirror` the
+/// log follows that upstream instead of taking writes.
+fn open_log(
+    config: &Config,
+    paths: &LogPaths,
+    metrics: &Arc<Metrics>,
+    tsa: &Arc<TimestampAuthority>,
+    tenant: Option<Arc<TenantConfig>>,
+    mirror: Option<&MirrorConfig>,
+) -> anyhow::Result<(AppState, JoinHandle<()>)> {
+    let _span = tenant
+        .as_ref()
+        .map(|t| tracing::info_span!("tenant", name = %t.name).entered());
+    std::fs::create_dir_all(&paths.dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", paths.dir.display(), e))?;
+    let path = paths.ledger.clone();
+    let store = ledger::open_store(&path, Some(config.ledger_backend))?;
+    let signer = Box::new(FileSystemProvider::new(&paths.server_key));
+    let log_public_key = Arc::new(server_public_key(&paths.server_key)?);
+    let log_key_id = crypto::key_id(&log_public_key);
+    tracing::info!(key_id = %log_key_id, "server signing key loaded");
+    let entries = store.entries()?;
+    let registry = Arc::new(KeyRegistry::load(&paths.keys_dir, &entries, &log_key_id)?);
+    let tokens = Arc::new(ApiTokens::load(&paths.api_tokens)?);
+    let signing = Arc::new(SigningService::load(&paths.signing_keys_dir, tokens.clone())?);
+    // Server-held signing keys are legitimate keys too: enroll them so they can be looked up
+    // and revoked like any other.
+    for pk in signing.public_keys() {
+        registry.enroll(*pk)?;
+    }
+    tracing::info!(keys = registry.len(), dir = %paths.keys_dir.display(), "key registry loaded");
+    tracing::info!(
+        keys = signing.key_count(),
+        tokens = tokens.len(),
+        "remote signing keys and API tokens loaded"
+    );
+    let policies = Arc::new(PolicyStore::load(&paths.policies_dir)?);
+    tracing::info!(policies = policies.len(), dir = %paths.policies_dir.display(), "policies loaded");
+    let leaves = writer::index_entries(&entries);
+    let feed = Arc::new(Feed::new());
+    // Only the server's own log feeds the ledger metrics; tenants are reported per tenant.
+    let on_append: Box<dyn Fn(IndexedEntry) + Send> = if tenant.is_none() {
+        if let Some(t) = entries
+            .last()
+            .and_then(|e| chrono::DateTime::parse_from_rfc3339(&e.timestamp).ok())
+        {
+            metrics.appended_at(t.with_timezone(&chrono::Utc));
+        }
+        let (feed, metrics) = (feed.clone(), metrics.clone());
+        Box::new(move |entry| {
+            metrics.appended();
+            feed.publish(entry);
+        })
+    } else {
+        let feed = feed.clone();
+        Box::new(move |entry| feed.publish(entry))
+    };
+    let (writer, writer_task) =
+        LedgerWriter::spawn(
+            path.clone(),
+            store,
+            config.rotation.clone(),
+            signer,
+            log_key_id.clone(),
+            leaves.clone(),
+            tenant.as_ref().and_then(|t| t.max_entries),
+            on_append,
+        );
+    let reader = Arc::new(Mutex::new(ledger::open_store(&path, Some(config.ledger_backend))?));
+    let server_signer = Arc::new(FileSystemProvider::new(&paths.server_key));
+    let (mirror, tree_heads) = match mirror {
+        Some(m) => {
+            let public_key = crypto::load_public_key(&m.public_key)?;
+            let mirror = Arc::new(Mirror::open(
+                m.upstream.clone(),
+                public_key,
+                m.interval,
+                &paths.dir,
+                &leaves.read().unwrap(),
+            )?);
+            let tree_heads = Arc::new(TreeHeads::mirrored(mirror.status().verified));
+            tracing::info!(upstream = %m.upstream, "mirror mode: read-only copy of the upstream log");
+            (Some(mirror), tree_heads)
+        }
+        None => {
+            let tree_heads = TreeHeads::signed(server_signer.clone(), log_key_id.clone());
+            (None, Arc::new(tree_heads))
+        }
+    };
+    let cosignatures = Arc::new(Cosignatures::load(&paths.witnesses_dir, &paths.dir)?);
+    tracing::info!(
+        witnesses = cosignatures.witness_count(),
+        dir = %paths.witnesses_dir.display(),
+        "witness keys loaded"
+    );
+    let state = AppState {
+        writer,
+        leaves,
+        feed,
+        metrics: metrics.clone(),
+        ledger_path: path,
+        tree_heads,
+        cosignatures,
+        mirror,
+        registry,
+        tsa: tsa.clone(),
+        signing,
+        policies,
+        tokens,
+        server_signer,
+        log_key_id,
+        log_public_key,
+        admin_lock: Arc::new(tokio::sync::Mutex::new(())),
+        reader,
+        idempotency: Arc::default(),
+        tenant,
+        tenants: Arc::default(),
+    };
+    Ok((state, writer_task))
+}
+
+/// Public half of the server signing key (used for receipts, timestamps and segment seals). On
+/// first start the key pair is generated; operators hand out the public key so clients can check
+/// receipts offline.
+fn server_public_key(private_key: &Path) -> anyhow::Result<PublicKey> {
+    let public_key = key_provider::public_key_path(private_key)
+        .ok_or_else(|| anyhow::anyhow!("Invalid server key path {}", private_key.display()))?;
+    if !private_key.exists() {
+        crypto::write_keypair(&public_key, private_key)?;
+        tracing::info!(public_key = %public_key.display(), "generated server signing key");
+    }
+    crypto::load_public_key(&public_key).map_err(|e| {
+        anyhow::anyhow!(
+            "Server public key {} is missing or invalid; receipts need it: {}",
+            public_key.display(),
+            e
+        )
+    })
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("shutdown signal received");
+}
+
+/// `POST /upload`: append a signed entry and return a signed inclusion receipt. 400 for a malformed
+/// field, 401 if the uploading key is not enrolled, 403 if it is revoked, the signature is invalid,
+/// the entry names a different signing key or carries a revocation, 429 once a tenant's log is at
+/// its quota. With an `Idempotency-Key` header a retry gets the receipt of the first upload (422 if
+/// the key was used for a different upload, 409 while that one is still running). With a rate
+/// limiter, each upload costs a token of its verified key, or of the client address if the upload
+/// does not verify (429 with `Retry-After` once it has none left).
+async fn upload(
+    State(state): State<AppState>,
+    limiter: Option<Extension<Arc<RateLimiter>>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(payload): Json<SignedUpload>,
+) -> Response {
+    let verified = verify_upload(&state, &payload);
+    if let Some(Extension(limiter)) = limiter {
+        let client = match verified {
+            Ok(()) => RateLimiter::key_client(&payload.key_id),
+            Err(_) => limiter.ip_client(&headers, peer.map(|ConnectInfo(addr)| addr)),
+        };
+        if let Err(wait) = limiter.check(&client) {
+            return limits::rate_limited(wait);
+        }
+    }
+    let result = match verified {
+        Ok(()) => idempotent_upload(&state, &headers, payload).await,
+        Err(e) => Err(e),
+    };
+    state.metrics.upload(match &result {
+        Ok(appended) if appended.new => "appended",
+        Ok(_) => "duplicate",
+        Err((StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY, _)) => "invalid",
+        Err((StatusCode::UNAUTHORIZED, _)) => "unknown_key",
+        Err((StatusCode::FORBIDDEN, _)) => "rejected",
+        Err((StatusCode::TOO_MANY_REQUESTS, _)) => "over_quota",
+        Err(_) => "error",
+    });
+    result.map(|appended| Json(appended.receipt)).into_response()
+}
+
+/// Check an upload before it is logged: well-formed fields (before any key lookup), an enrolled
+/// and unrevoked key, a valid signature, and an entry that names no other key and no revocation.
+fn verify_upload(state: &AppState, payload: &SignedUpload) -> Result<(), (StatusCode, &'static str)> {
+    limits::validate_upload(payload)?;
+    let key = uploader_key(state, &payload.key_id)?;
+    if payload.verify(&key.public_key).is_err() {
+        tracing::warn!(key_id = %payload.key_id, "upload with bad signature");
+        return Err((StatusCode::FORBIDDEN, "bad signature"));
+    }
+    if payload
+        .entry
+        .key_id
+        .as_deref()
+        .is_some_and(|k| !k.eq_ignore_ascii_case(&payload.key_id))
+    {
+        return Err((StatusCode::FORBIDDEN, "entry key id does not match uploader"));
+    }
+    if payload.entry.revocation.is_some() {
+        return Err((StatusCode::FORBIDDEN, "revocations are logged through /keys"));
+    }
+    Ok(())
+}
+
+/// Log a verified upload.
+async fn idempotent_upload(
+    state: &AppState,
+    headers: &HeaderMap,
+    payload: SignedUpload,
+) -> Result<Appended, (StatusCode, &'static str)> {
+    let Some(key) = idempotency::key(headers)? else {
+        return append_upload(state, payload).await;
+    };
+    match state.idempotency.claim(key, &payload)? {
+        Claim::New(guard) => {
+            let appended = append_upload(state, payload).await?;
+            guard.complete(appended.receipt.body.log_index);
+            Ok(appended)
+        }
+        // The entry is in the log: the writer finds it and signs a receipt for its index.
+        Claim::Replay(index) => {
+            let entry = read_store(state, move |store| store.get(index))
+                .await
+                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "read"))?
+                .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "read"))?;
+            state.writer.append(entry).await.map_err(|e| write_failed(state, e))
+        }
+    }
+}
+
+/// The enrolled key an upload names; 401 if it is not enrolled, 403 if it is revoked.
+fn uploader_key(state: &AppState, key_id: &str) -> Result<RegisteredKey, (StatusCode, &'static str)> {
+    let key = state
+        .registry
+        .get(key_id)
+        .ok_or((StatusCode::UNAUTHORIZED, "unknown key"))?;
+    if key.is_revoked() {
+        return Err((StatusCode::FORBIDDEN, "key revoked"));
+    }
+    Ok(key)
+}
+
+async fn append_upload(
+    state: &AppState,
+    payload: SignedUpload,
+) -> Result<Appended, (StatusCode, &'static str)> {
+    // The writer enforces a tenant's quota; an entry the log already holds costs nothing.
+    state
+        .writer
+        .append(payload.entry)
+        .await
+        .map_err(|e| write_failed(state, e))
+}
+
+/// 429 for an append refused by a tenant's quota, 500 for any other write failure.
+fn write_failed(state: &AppState, e: anyhow::Error) -> (StatusCode, &'static str) {
+    if e.is::<QuotaExceeded>() {
+        if let Some(ref tenant) = state.tenant {
+            tracing::warn!(tenant = %tenant.name, max_entries = ?tenant.max_entries, "tenant quota exceeded");
+        }
+        return (StatusCode::TOO_MANY_REQUESTS, "tenant quota exceeded");
+    }
+    tracing::error!(error = %e, "ledger write failed");
+    (StatusCode::INTERNAL_SERVER_ERROR, "write")
+}
+
+/// `GET /healthz`: liveness; 200 while the process serves requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// `GET /readyz`: 200 once the ledger can take writes (writer task running, ledger file and data
+/// directory writable, also for every tenant's log), 503 with the reason otherwise.
+async fn readyz(State(state): State<AppState>) -> Result<&'static str, ApiError> {
+    if state.writer.is_closed() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "ledger writer stopped".to_string(),
+        ));
+    }
+    let mut ledger_paths = vec![state.ledger_path.clone()];
+    ledger_paths.extend(state.tenants.iter().map(|t| t.ledger_path.clone()));
+    tokio::task::spawn_blocking(move || ledger_paths.iter().try_for_each(|p| storage_writable(p)))
+        .await
+        .map_err(internal)?
+        .map_err(|e| {
+            tracing::warn!(error = %e, "readiness check failed");
+            (StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+        })?;
+    Ok("ready")
+}
+
+/// Open the ledger for appending (without writing) and create and remove a probe file next to it.
+fn storage_writable(ledger_path: &Path) -> anyhow::Result<()> {
+    std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(ledger_path)
+        .map_err(|e| anyhow::anyhow!("ledger {} not writable: {}", ledger_path.display(), e))?;
+    let dir = ledger_path.parent().unwrap_or(Path::new("."));
+    let probe = dir.join(".readyz");
+    std::fs::write(&probe, b"")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| anyhow::anyhow!("data dir {} not writable: {}", dir.display(), e))
+}
+
+/// `GET /metrics`: Prometheus text format.
+async fn render_metrics(State(state): State<AppState>) -> impl axum::response::IntoResponse {
+    let stats = LedgerStats {
+        entries: state.leaves.read().unwrap().len(),
+        bytes: std::fs::metadata(&state.ledger_path).map_or(0, |m| m.len()),
+        mirror: state.mirror.as_ref().map(|m| {
+            let status = m.status();
+            (
+                status.verified.map_or(0, |h| h.body.tree_size),
+                status.fork.is_some(),
+            )
+        }),
+        tenants: state
+            .tenants
+            .iter()
+            .map(|t| (t.config.name.clone(), t.leaves.read().unwrap().len()))
+            .collect(),
+    };
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(&stats),
+    )
+}
+
+/// Decode a hex SHA3-256 digest; 400 unless it is 64 hex characters.
+fn parse_digest(digest: &str) -> Result<Vec<u8>, ApiError> {
+    hex::decode(digest)
+        .ok()
+        .filter(|d| d.len() == 32)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "digest must be a hex SHA3-256 hash".to_string(),
+        ))
+}
+
+/// `POST /sign`: sign an artifact digest with a server-held key and log the entry before
+/// returning the signature, so every remote signature is in the log. Needs
+/// `Authorization: Bearer <token>` for a token allowed to use the key (see `SigningService::authorize`);
+/// 403 once the key is revoked in the registry, 429 once a tenant's log is at its quota.
+async fn remote_sign(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<SignRequest>,
+) -> Result<Json<SignatureEnvelope>, ApiError> {
+    let team = state.signing.authorize(auth::bearer(&headers)?, &req.key)?;
+    limits::validate_file_name(&req.file_name).map_err(|(status, msg)| (status, msg.to_string()))?;
+    if let Some(ref attributes) = req.attributes {
+        limits::validate_attributes(attributes).map_err(|(status, msg)| (status, msg.to_string()))?;
+    }
+    if state
+        .signing
+        .key_id(&req.key)
+        .and_then(|id| state.registry.get(id))
+        .is_some_and(|k| k.is_revoked())
+    {
+        return Err((StatusCode::FORBIDDEN, "signing key revoked".to_string()));
+    }
+    let message = protocol::artifact_message(&parse_digest(&req.digest)?, req.attributes.as_ref())
+        .map_err(internal)?;
+    let signing = state.signing.clone();
+    let key = req.key.clone();
+    let (sig_bytes, key_id) = tokio::task::spawn_blocking(move || signing.sign(&key, &message))
+        .await
+        .map_err(internal)?
+        .map_err(|e| {
+            tracing::error!(error = %e, "remote signing failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "sign".to_string())
+        })?;
+    let entry = LedgerEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        file_name: req.file_name,
+        signature_hash: hex::encode(&sig_bytes),
+        digest: Some(req.digest.to_lowercase()),
+        key_id: Some(key_id),
+        revocation: None,
+        attributes: req.attributes,
+    };
+    let receipt = state
+        .writer
+        .append(entry.clone())
+        .await
+        .map_err(|e| {
+            let (status, msg) = write_failed(&state, e);
+            (status, msg.to_string())
+        })?
+        .receipt;
+    tracing::info!(team = %team, key = %req.key, index = receipt.body.log_index, "remote signature issued");
+    Ok(Json(SignatureEnvelope {
+        signature: entry.signature_hash.clone(),
+        attributes: entry.attributes.clone(),
+        timestamp: Some(entry.timestamp.clone()),
+        timestamp_token: None,
+        log_entry: Some(entry),
+        receipt: Some(receipt),
+        inclusion_proof: None,
+    }))
+}
+
+/// Signer's public key for `/verify`: given in the request, or found by key id in the registry.
+fn verify_key(state: &AppState, req: &VerifyRequest) -> Result<PublicKey, ApiError> {
+    if let Some(ref key_hex) = req.public_key {
+        return hex::decode(key_hex.trim())
+            .ok()
+            .and_then(|b| PublicKey::from_bytes(&b).ok())
+            .ok_or((StatusCode::BAD_REQUEST, "invalid public_key".to_string()));
+    }
+    let key_id = req
+        .key_id
+        .as_deref()
+        .or_else(|| req.signature.log_entry.as_ref()?.key_id.as_deref())
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "give public_key or key_id".to_string(),
+        ))?;
+    state
+        .registry
+        .get(key_id)
+        .map(|k| k.public_key)
+        .ok_or((StatusCode::BAD_REQUEST, "unknown key_id".to_string()))
+}
+
+/// `POST /verify`: the checks of `qs_notary verify` (signature, optional named policy) plus log
+/// inclusion and key status in the registry. A receipt or inclusion proof in the envelope is checked
+/// against the server key; without either, the signature is looked up in the log by digest. Always 200 with a verdict for
+/// a well-formed request.
+async fn verify(
+    State(state): State<AppState>,
+    Json(req): Json<VerifyRequest>,
+) -> Result<Json<Verdict>, ApiError> {
+    let digest = match (&req.digest, &req.sbom) {
+        (Some(digest), None) => parse_digest(digest)?,
+        (None, Some(sbom)) => verifier::artifact_digest(sbom.as_bytes(), &req.signature)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give exactly one of digest or sbom".to_string(),
+            ))
+        }
+    };
+    let policy = match req.policy.as_deref() {
+        Some(name) => Some(
+            state
+                .policies
+                .get(name)
+                .ok_or((StatusCode::NOT_FOUND, "no such policy".to_string()))?,
+        ),
+        None => None,
+    };
+    let pk = verify_key(&state, &req)?;
+    let envelope = &req.signature;
+    let logged = envelope.receipt.is_some() || envelope.inclusion_proof.is_some();
+    let log_pk = logged.then_some(state.log_public_key.as_ref());
+    // Keys the registry knows get their revocation status checked; a caller-supplied key that was
+    // never enrolled is verified on its own.
+    let registry = state
+        .registry
+        .info(&crypto::key_id(&pk))
+        .map(|info| RegistrySnapshot { keys: vec![info] });
+    let mut verdict = verifier::evaluate(
+        verifier::Signed::Artifact(&digest),
+        envelope,
+        &pk,
+        policy,
+        log_pk,
+        registry.as_ref(),
+    );
+    if !logged {
+        let mut filter = EntryFilter {
+            digest: Some(hex::encode(&digest)),
+            ..Default::default()
+        };
+        if let Some(ref tenant) = state.tenant {
+            tenant.retain(&mut filter);
+        }
+        let signature = envelope.signature.to_lowercase();
+        let found = read_store(&state, move |store| {
+            Ok(store
+                .query(&filter)?
+                .into_iter()
+                .find(|(_, e)| e.signature_hash.eq_ignore_ascii_case(&signature)))
+        })
+        .await?;
+        verdict.push(
+            "log_inclusion",
+            found
+                .map(|(index, _)| format!("Logged at index {}", index))
+                .ok_or_else(|| anyhow::anyhow!("signature is not in the log")),
+        );
+    }
+    state.metrics.verification(
+        verdict.verified,
+        verdict
+            .checks
+            .iter()
+            .filter(|c| !c.ok)
+            .map(|c| c.name.as_str()),
+    );
+    Ok(Json(verdict))
+}
+
+/// `POST /timestamp`: sign a token binding the given SHA3-256 digest to the server's current time
+/// and a fresh serial. 400 unless `digest` is 64 hex characters.
+async fn timestamp(
+    State(state): State<AppState>,
+    Json(req): Json<TimestampRequest>,
+) -> Result<Json<TimestampToken>, ApiError> {
+    let digest = hex::encode(parse_digest(&req.digest)?);
+    let tsa = state.tsa.clone();
+    tokio::task::spawn_blocking(move || tsa.issue(digest))
+        .await
+        .map_err(internal)?
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!(error = %e, "timestamp failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "timestamp".to_string())
+        })
+}
+
+/// `GET /entries/by-leaf/{hex}`: 200 with the entry's log index if present, 404 otherwise.
+async fn entry_by_leaf(
+    State(state): State<AppState>,
+    UrlPath(leaf_hash): UrlPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let leaf_hash = leaf_hash.to_lowercase();
+    let index = state
+        .leaves
+        .read()
+        .unwrap()
+        .get(&leaf_hash)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::json!({ "leaf_hash": leaf_hash, "index": index })))
+}
+
+type ApiError = (StatusCode, String);
+
+fn internal(e: impl std::fmt::Display) -> ApiError {
+    tracing::error!(error = %e, "ledger read failed");
+    (StatusCode::INTERNAL_SERVER_ERROR, "read".to_string())
+}
+
+/// Run a query against the read store on the blocking pool.
+async fn read_store<T: Send + 'static>(
+    state: &AppState,
+    f: impl FnOnce(&dyn LedgerStore) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, ApiError> {
+    let reader = state.reader.clone();
+    tokio::task::spawn_blocking(move || f(reader.lock().unwrap().as_ref()))
+        .await
+        .map_err(internal)?
+        .map_err(internal)
+}
+
+/// Fetch one page: ask for one extra entry to learn whether another page follows.
+async fn page(state: &AppState, mut filter: EntryFilter) -> Result<EntryPage, ApiError> {
+    filter.validate().map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if let Some(ref tenant) = state.tenant {
+        tenant.retain(&mut filter);
+    }
+    let limit = filter.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    filter.limit = Some(limit + 1);
+    let offset = filter.offset;
+    let mut rows = read_store(state, move |store| store.query(&filter)).await?;
+    let next_offset = (rows.len() as u64 > limit).then(|| offset + limit);
+    rows.truncate(limit as usize);
+    Ok(EntryPage {
+        entries: rows
+            .into_iter()
+            .map(|(index, entry)| IndexedEntry { index, entry })
+            .collect(),
+        next_offset,
+    })
+}
+
+/// `GET /entries?file_name=&key_id=&digest=&since=&until=&offset=&limit=`: filtered, paginated listing.
+async fn list_entries(
+    State(state): State<AppState>,
+    Query(filter): Query<EntryFilter>,
+) -> Result<Json<EntryPage>, ApiError> {
+    Ok(Json(page(&state, filter).await?))
+}
+
+/// `GET /entries/{index}`: one entry by log index; 410 once it is past the tenant's retention.
+async fn entry_by_index(
+    State(state): State<AppState>,
+    UrlPath(index): UrlPath<u64>,
+) -> Result<Json<IndexedEntry>, ApiError> {
+    let entry = read_store(&state, move |store| store.get(index))
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "no such entry".to_string()))?;
+    if state.tenant.as_ref().is_some_and(|t| t.expired(&entry)) {
+        return Err((StatusCode::GONE, "entry is past retention".to_string()));
+    }
+    Ok(Json(IndexedEntry { index, entry }))
+}
+
+/// `GET /entries/by-digest/{hex}`: entries for an artifact digest (paginated like `/entries`); 404 if none.
+async fn entries_by_digest(
+    State(state): State<AppState>,
+    UrlPath(digest): UrlPath<String>,
+    Query(mut filter): Query<EntryFilter>,
+) -> Result<Json<EntryPage>, ApiError> {
+    filter.digest = Some(digest);
+    let page = page(&state, filter).await?;
+    if page.entries.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "no entries for digest".to_string()));
+    }
+    Ok(Json(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qs_notary::key_provider::KeyProvider;
+    use qs_notary::protocol::{Receipt, RevokeResponse};
+    use sha3::{Digest, Sha3_256};
+
+    #[tokio::test]
+    async fn upload_signature_must_cover_the_upload_message_itself() {
+        let log = testing::open("upload_message", &[]);
+        let key = log.enroll("uploader");
+        let key_id = key.key_id().unwrap();
+        let entry = testing::signed_entry(&key, "sbom.json");
+
+        // A signature over the digest of the upload message, as a signing service or `sign-all`
+        // makes for any 32 bytes it is given, is not an upload signature.
+        let digest = Sha3_256::digest(protocol::upload_message(&entry).unwrap());
+        let forged = SignedUpload {
+            entry: entry.clone(),
+            key_id: key_id.clone(),
+            signature: hex::encode(key.sign(&digest).unwrap()),
+        };
+        let (status, body) = log.post("/upload", &forged).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+        assert_eq!(body, "bad signature");
+        assert_eq!(log.get("/entries").await.1["entries"], serde_json::json!([]));
+
+        let (status, body) = log.post("/upload", SignedUpload::sign(entry.clone(), &key).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let receipt: Receipt = serde_json::from_value(body).unwrap();
+        receipt.verify(&entry, &log.state.log_public_key).unwrap();
+        assert_eq!(receipt.body.log_index, 0);
+    }
+
+    #[tokio::test]
+    async fn uploads_are_rate_limited_per_verified_key() {
+        let log = testing::open(
+            "upload_rate_limit",
+            &["--upload-rate-limit", "0.001", "--upload-rate-burst", "1"],
+        );
+        let first = log.enroll("first");
+        let second = log.enroll("second");
+        let upload = |key: &FileSystemProvider, name| {
+            SignedUpload::sign(testing::signed_entry(key, name), key).unwrap()
+        };
+
+        assert_eq!(log.post("/upload", upload(&first, "a.json")).await.0, StatusCode::OK);
+        let (status, body) = log.post("/upload", upload(&first, "b.json")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS, "{}", body);
+        // Same client address, different key: its own bucket.
+        assert_eq!(log.post("/upload", upload(&second, "c.json")).await.0, StatusCode::OK);
+
+        // An upload that does not verify is charged to the client address, not the key it names.
+        let mut forged = upload(&second, "d.json");
+        forged.entry.file_name = "e.json".to_string();
+        assert_eq!(log.post("/upload", &forged).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(log.post("/upload", &forged).await.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(log.get("/entries").await.1["entries"].as_array().unwrap().len(), 2);
+    }
+
+    /// Log indexes of the entries in a page.
+    fn indexes(page: &serde_json::Value) -> Vec<u64> {
+        page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["index"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn entries_are_paginated_and_filtered_in_both_stores() {
+        for (name, flags) in [
+            ("entries_jsonl", &[][..]),
+            ("entries_sqlite", &["--ledger-backend", "sqlite"][..]),
+        ] {
+            let log = testing::open(name, flags);
+            let first = log.enroll("first");
+            let second = log.enroll("second");
+            for (key, file_name) in [
+                (&first, "a.json"),
+                (&first, "b.json"),
+                (&first, "c.json"),
+                (&second, "d.json"),
+                (&first, "e.json"),
+                (&second, "f.json"),
+            ] {
+                let upload = SignedUpload::sign(testing::signed_entry(key, file_name), key).unwrap();
+                assert_eq!(log.post("/upload", upload).await.0, StatusCode::OK, "{}", name);
+            }
+
+            // Pages of two: each names the next offset until the last one.
+            let mut offset = Some(0);
+            let mut pages = Vec::new();
+            while let Some(o) = offset {
+                let (status, page) = log.get(&format!("/entries?offset={}&limit=2", o)).await;
+                assert_eq!(status, StatusCode::OK, "{}: {}", name, page);
+                pages.push(indexes(&page));
+                offset = page["next_offset"].as_u64();
+            }
+            assert_eq!(pages, [vec![0, 1], vec![2, 3], vec![4, 5]], "{}", name);
+            let (_, page) = log.get("/entries?offset=5").await;
+            assert_eq!((indexes(&page), page["next_offset"].clone()), (vec![5], serde_json::Value::Null));
+
+            // The index bound applies before the filters, the offset after them.
+            let second_id = second.key_id().unwrap();
+            let (_, page) = log.get(&format!("/entries?key_id={}", second_id.to_uppercase())).await;
+            assert_eq!(indexes(&page), [3, 5], "{}", name);
+            let (_, page) = log.get(&format!("/entries?key_id={}&from_index=4", second_id)).await;
+            assert_eq!(indexes(&page), [5], "{}", name);
+            let (_, page) = log.get(&format!("/entries?key_id={}&offset=1", first.key_id().unwrap())).await;
+            assert_eq!(indexes(&page), [1, 2, 4], "{}", name);
+            let (_, page) = log.get("/entries?file_name=c.json").await;
+            assert_eq!(indexes(&page), [2], "{}", name);
+            let (_, page) = log.get("/entries?since=2999-01-01T00:00:00Z").await;
+            assert_eq!(indexes(&page), Vec::<u64>::new(), "{}", name);
+            let (status, _) = log.get("/entries?since=yesterday").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", name);
+
+            let digest = hex::encode(Sha3_256::digest(b"d.json"));
+            let (_, page) = log.get(&format!("/entries/by-digest/{}", digest)).await;
+            assert_eq!(indexes(&page), [3], "{}", name);
+            let (status, _) = log.get(&format!("/entries/by-digest/{}", "00".repeat(32))).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", name);
+
+            let (status, entry) = log.get("/entries/5").await;
+            assert_eq!((status, entry["entry"]["file_name"].as_str()), (StatusCode::OK, Some("f.json")));
+            let (status, _) = log.get("/entries/6").await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", name);
+        }
+    }
+
+    /// A log with the remote signing keys `release` and `other`, a team token `build-token`
+    /// for `release` and `missing` (which the server does not hold) and an admin token
+    /// `admin-token`. Returns the log and the `release` key.
+    fn signing_log(name: &str) -> (testing::TestLog, FileSystemProvider) {
+        let setup = testing::scratch_dir(&format!("{}_setup", name));
+        let keys_dir = setup.join("signing_keys");
+        let release = testing::add_signing_key(&keys_dir, "release");
+        testing::add_signing_key(&keys_dir, "other");
+        let tokens = setup.join("api_tokens.toml");
+        std::fs::write(
+            &tokens,
+            format!(
+                "[[token]]\nteam = \"build\"\ntoken_sha3 = \"{}\"\nkeys = [\"release\", \"missing\"]\n\n\
+                 [[admin]]\nname = \"ops\"\ntoken_sha3 = \"{}\"\n",
+                auth::token_hash("build-token"),
+                auth::token_hash("admin-token")
+            ),
+        )
+        .unwrap();
+        let log = testing::open(
+            name,
+            &[
+                "--signing-keys-dir",
+                keys_dir.to_str().unwrap(),
+                "--api-tokens",
+                tokens.to_str().unwrap(),
+            ],
+        );
+        (log, release)
+    }
+
+    fn sign_request(key: &str, file_name: &str) -> SignRequest {
+        SignRequest {
+            key: key.to_string(),
+            digest: hex::encode(Sha3_256::digest(file_name.as_bytes())),
+            file_name: file_name.to_string(),
+            attributes: None,
+        }
+    }
+
+    async fn remote_sign_as(
+        log: &testing::TestLog,
+        token: Option<&str>,
+        request: &SignRequest,
+    ) -> (StatusCode, serde_json::Value) {
+        let body = serde_json::to_value(request).unwrap();
+        log.send(axum::http::Method::POST, "/sign", token, Some(body)).await
+    }
+
+    #[tokio::test]
+    async fn remote_signing_is_authorized_per_token_and_key() {
+        let (log, release) = signing_log("sign_auth");
+        let request = sign_request("release", "app.tar.gz");
+        for (token, key, status) in [
+            (None, "release", StatusCode::UNAUTHORIZED),
+            (Some("wrong-token"), "release", StatusCode::UNAUTHORIZED),
+            // An admin token is not a team token.
+            (Some("admin-token"), "release", StatusCode::UNAUTHORIZED),
+            (Some("build-token"), "other", StatusCode::FORBIDDEN),
+            (Some("build-token"), "missing", StatusCode::NOT_FOUND),
+        ] {
+            let request = SignRequest {
+                key: key.to_string(),
+                ..request.clone()
+            };
+            let (got, body) = remote_sign_as(&log, token, &request).await;
+            assert_eq!(got, status, "{:?} {}: {}", token, key, body);
+        }
+        assert_eq!(log.get("/entries").await.1["entries"], serde_json::json!([]));
+
+        // The signature is over the artifact message and is the one logged.
+        let (status, body) = remote_sign_as(&log, Some("build-token"), &request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let envelope: SignatureEnvelope = serde_json::from_value(body).unwrap();
+        let entry = envelope.log_entry.clone().unwrap();
+        assert_eq!(entry.signature_hash, envelope.signature);
+        assert_eq!(entry.digest.as_deref(), Some(request.digest.as_str()));
+        assert_eq!(entry.key_id, release.key_id());
+        let public_key = log.state.registry.get(&release.key_id().unwrap()).unwrap().public_key;
+        let message = protocol::artifact_message(&Sha3_256::digest(b"app.tar.gz"), None).unwrap();
+        let signature = crypto::load_signature(&hex::decode(&envelope.signature).unwrap()).unwrap();
+        crypto::verify_signature(&signature, &message, &public_key).unwrap();
+        envelope.receipt.unwrap().verify(&entry, &log.state.log_public_key).unwrap();
+        let (_, logged) = log.get("/entries/0").await;
+        assert_eq!(logged["entry"], serde_json::to_value(&entry).unwrap());
+    }
+
+    #[tokio::test]
+    async fn remote_signing_refuses_a_revoked_key() {
+        let (log, release) = signing_log("sign_revoked");
+        let key_id = release.key_id().unwrap();
+        let revoke = serde_json::json!({ "reason": "compromised" });
+        let (status, body) = log
+            .send(axum::http::Method::POST, &format!("/keys/{}/revoke", key_id), Some("admin-token"), Some(revoke))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, body) = remote_sign_as(&log, Some("build-token"), &sign_request("release", "app.tar.gz")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::FORBIDDEN, Some("signing key revoked")));
+        // Only the revocation is logged.
+        assert_eq!(indexes(&log.get("/entries").await.1), [0]);
+    }
+
+    #[tokio::test]
+    async fn remote_signature_over_an_upload_digest_is_not_an_upload_signature() {
+        let (log, release) = signing_log("sign_upload");
+        let entry = testing::signed_entry(&release, "sbom.json");
+        // A team that may only have artifacts signed asks for the digest of an upload message.
+        let request = SignRequest {
+            digest: hex::encode(Sha3_256::digest(protocol::upload_message(&entry).unwrap())),
+            ..sign_request("release", "upload")
+        };
+        let (status, body) = remote_sign_as(&log, Some("build-token"), &request).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let forged = SignedUpload {
+            entry,
+            key_id: release.key_id().unwrap(),
+            signature: body["signature"].as_str().unwrap().to_string(),
+        };
+        let (status, body) = log.post("/upload", &forged).await;
+        assert_eq!((status, body.as_str()), (StatusCode::FORBIDDEN, Some("bad signature")));
+        assert_eq!(indexes(&log.get("/entries").await.1), [0]);
+    }
+
+    async fn revoke_as_admin(
+        log: &testing::TestLog,
+        key_id: &str,
+        effective_at: chrono::DateTime<chrono::Utc>,
+    ) -> RevokeResponse {
+        let request = serde_json::json!({ "reason": "rotated", "effective_at": effective_at.to_rfc3339() });
+        let uri = format!("/keys/{}/revoke", key_id);
+        let (status, body) = log
+            .send(axum::http::Method::POST, &uri, Some("admin-token"), Some(request))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn revocations_are_logged_survive_a_restart_and_apply_from_effective_at() {
+        let (log, release) = signing_log("revocation");
+        let revoked = log.enroll("revoked");
+        let later = log.enroll("later");
+        let upload = |key: &FileSystemProvider, name| {
+            SignedUpload::sign(testing::signed_entry(key, name), key).unwrap()
+        };
+        let now = chrono::Utc::now();
+        let revocation = revoke_as_admin(&log, &revoked.key_id().unwrap(), now - chrono::Duration::hours(1)).await;
+        revoke_as_admin(&log, &later.key_id().unwrap(), now + chrono::Duration::days(365)).await;
+        let release_at = now + chrono::Duration::seconds(1);
+        revoke_as_admin(&log, &release.key_id().unwrap(), release_at).await;
+
+        // Each revocation is a log entry signed by the log key, with a receipt.
+        let (_, logged) = log.get(&format!("/entries/{}", revocation.receipt.body.log_index)).await;
+        let entry: LedgerEntry = serde_json::from_value(logged["entry"].clone()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&entry.revocation).unwrap(),
+            serde_json::to_value(&revocation.revocation).unwrap()
+        );
+        assert_eq!(entry.key_id.as_deref(), Some(crypto::key_id(&log.state.log_public_key).as_str()));
+        revocation.receipt.verify(&entry, &log.state.log_public_key).unwrap();
+        let message = protocol::revocation_message(&revocation.revocation).unwrap();
+        let signature = crypto::load_signature(&hex::decode(&entry.signature_hash).unwrap()).unwrap();
+        crypto::verify_signature(&signature, &message, &log.state.log_public_key).unwrap();
+
+        // Before effective_at the key still uploads and signs; from then on it does neither.
+        let (status, body) = log.post("/upload", upload(&revoked, "a.json")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::FORBIDDEN, Some("key revoked")));
+        assert_eq!(log.post("/upload", upload(&later, "b.json")).await.0, StatusCode::OK);
+        let request = sign_request("release", "app.tar.gz");
+        assert_eq!(remote_sign_as(&log, Some("build-token"), &request).await.0, StatusCode::OK);
+
+        let log = log.restart().await;
+        let (_, key) = log.get(&format!("/keys/{}", revoked.key_id().unwrap())).await;
+        assert_eq!(key["revocation"]["effective_at"], revocation.revocation.effective_at.as_str());
+        assert_eq!(log.post("/upload", upload(&revoked, "c.json")).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(log.post("/upload", upload(&later, "d.json")).await.0, StatusCode::OK);
+        let wait = release_at - chrono::Utc::now();
+        if let Ok(wait) = wait.to_std() {
+            tokio::time::sleep(wait).await;
+        }
+        let request = sign_request("release", "app-2.tar.gz");
+        let (status, body) = remote_sign_as(&log, Some("build-token"), &request).await;
+        assert_eq!((status, body.as_str()), (StatusCode::FORBIDDEN, Some("signing key revoked")));
+        let (status, body) = log.post("/upload", upload(&release, "e.json")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::FORBIDDEN, Some("key revoked")));
+        // Three revocations, the upload and the signature before the restart, the upload after.
+        assert_eq!(indexes(&log.get("/entries").await.1), [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn readyz_fails_once_the_ledger_writer_has_stopped() {
+        let log = testing::open("readyz_writer", &[]);
+        let key = log.enroll("signer");
+        assert_eq!(log.get("/readyz").await, (StatusCode::OK, serde_json::json!("ready")));
+
+        // Poison the leaf index so the writer panics on the next append and its task ends.
+        let leaves = log.state.leaves.clone();
+        std::thread::spawn(move || {
+            let _guard = leaves.write().unwrap();
+            panic!("poison the leaf index");
+        })
+        .join()
+        .unwrap_err();
+        let entry = testing::signed_entry(&key, "sbom.json");
+        assert!(log.state.writer.append(entry).await.is_err());
+        // The append fails as the writer unwinds; its queue closes once the unwinding is done.
+        for _ in 0..100 {
+            if log.state.writer.is_closed() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(log.state.writer.is_closed());
+
+        let (status, body) = log.get("/readyz").await;
+        assert_eq!(
+            (status, body.as_str()),
+            (StatusCode::SERVICE_UNAVAILABLE, Some("ledger writer stopped"))
+        );
+    }
+}
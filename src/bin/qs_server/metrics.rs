@@ -0,0 +1,249 @@
+//! Prometheus metrics (`GET /metrics`, text exposition format) and the request middleware that
+//! feeds them.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Upper bounds (seconds) of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    /// Cumulative count per bucket in `LATENCY_BUCKETS`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    uploads: BTreeMap<&'static str, u64>,
+    verifications: BTreeMap<&'static str, u64>,
+    verification_failures: BTreeMap<String, u64>,
+    /// Keyed by (method, route, status).
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Keyed by (method, route).
+    latency: BTreeMap<(String, String), Histogram>,
+}
+
+pub struct Metrics {
+    counters: Mutex<Counters>,
+    /// Unix time (seconds) of the last append; 0 if the log is empty.
+    last_append: AtomicI64,
+}
+
+/// Ledger figures read at scrape time.
+pub struct LedgerStats {
+    pub entries: u64,
+    pub bytes: u64,
+    /// Mirror mode only: size of the latest verified upstream head and whether a fork was seen.
+    pub mirror: Option<(u64, bool)>,
+    /// Entries in each tenant's log, by tenant name.
+    pub tenants: Vec<(String, u64)>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(Counters::default()),
+            last_append: AtomicI64::new(0),
+        }
+    }
+
+    /// Count an upload by outcome (`appended`, `duplicate`, `invalid`, `unknown_key`, `rejected`,
+    /// `over_quota`, `error`).
+    pub fn upload(&self, outcome: &'static str) {
+        *self.counters.lock().unwrap().uploads.entry(outcome).or_default() += 1;
+    }
+
+    /// Count a `/verify` verdict and each check that failed in it.
+    pub fn verification<'a>(&self, verified: bool, failed_checks: impl Iterator<Item = &'a str>) {
+        let mut counters = self.counters.lock().unwrap();
+        let result = if verified { "verified" } else { "failed" };
+        *counters.verifications.entry(result).or_default() += 1;
+        for check in failed_checks {
+            *counters
+                .verification_failures
+                .entry(check.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// Record that an entry was just appended to the log.
+    pub fn appended(&self) {
+        self.appended_at(chrono::Utc::now());
+    }
+
+    /// Record the time of the last append (on startup: the newest entry already in the log).
+    pub fn appended_at(&self, time: chrono::DateTime<chrono::Utc>) {
+        self.last_append.store(time.timestamp(), Ordering::Relaxed);
+    }
+
+    fn request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        counters
+            .latency
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// Everything in Prometheus text format.
+    pub fn render(&self, ledger: &LedgerStats) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+        header(&mut out, "qs_uploads_total", "counter", "Uploads by outcome.");
+        for (outcome, n) in &counters.uploads {
+            let _ = writeln!(out, "qs_uploads_total{{outcome=\"{}\"}} {}", outcome, n);
+        }
+        header(&mut out, "qs_verifications_total", "counter", "POST /verify verdicts by result.");
+        for (result, n) in &counters.verifications {
+            let _ = writeln!(out, "qs_verifications_total{{result=\"{}\"}} {}", result, n);
+        }
+        header(
+            &mut out,
+            "qs_verification_failures_total",
+            "counter",
+            "Failed checks in POST /verify verdicts, by check.",
+        );
+        for (check, n) in &counters.verification_failures {
+            let _ = writeln!(
+                out,
+                "qs_verification_failures_total{{check=\"{}\"}} {}",
+                escape(check),
+                n
+            );
+        }
+        header(&mut out, "qs_http_requests_total", "counter", "HTTP requests by method, route and status.");
+        for ((method, route, status), n) in &counters.requests {
+            let _ = writeln!(
+                out,
+                "qs_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method),
+                escape(route),
+                status,
+                n
+            );
+        }
+        header(
+            &mut out,
+            "qs_http_request_duration_seconds",
+            "histogram",
+            "Time to produce the response headers, by method and route.",
+        );
+        for ((method, route), h) in &counters.latency {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            for (le, n) in LATENCY_BUCKETS.iter().zip(h.buckets) {
+                let _ = writeln!(
+                    out,
+                    "qs_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, n
+                );
+            }
+            let _ = writeln!(
+                out,
+                "qs_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, h.count
+            );
+            let _ = writeln!(out, "qs_http_request_duration_seconds_sum{{{}}} {}", labels, h.sum);
+            let _ = writeln!(out, "qs_http_request_duration_seconds_count{{{}}} {}", labels, h.count);
+        }
+        header(&mut out, "qs_ledger_entries", "gauge", "Entries in the central log.");
+        let _ = writeln!(out, "qs_ledger_entries {}", ledger.entries);
+        header(&mut out, "qs_ledger_bytes", "gauge", "Size of the active ledger file.");
+        let _ = writeln!(out, "qs_ledger_bytes {}", ledger.bytes);
+        header(
+            &mut out,
+            "qs_ledger_last_append_timestamp_seconds",
+            "gauge",
+            "Unix time of the last append (0 if the log is empty).",
+        );
+        let _ = writeln!(
+            out,
+            "qs_ledger_last_append_timestamp_seconds {}",
+            self.last_append.load(Ordering::Relaxed)
+        );
+        if !ledger.tenants.is_empty() {
+            header(&mut out, "qs_tenant_ledger_entries", "gauge", "Entries in each tenant's log.");
+            for (tenant, n) in &ledger.tenants {
+                let _ = writeln!(out, "qs_tenant_ledger_entries{{tenant=\"{}\"}} {}", escape(tenant), n);
+            }
+        }
+        if let Some((verified, fork)) = ledger.mirror {
+            header(
+                &mut out,
+                "qs_mirror_verified_tree_size",
+                "gauge",
+                "Size of the latest upstream tree head this mirror verified.",
+            );
+            let _ = writeln!(out, "qs_mirror_verified_tree_size {}", verified);
+            header(
+                &mut out,
+                "qs_mirror_fork_detected",
+                "gauge",
+                "1 once the mirror has seen inconsistent upstream tree heads.",
+            );
+            let _ = writeln!(out, "qs_mirror_fork_detected {}", u8::from(fork));
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value (backslash, double quote, newline).
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware: count each request and time it, labelled by route template (`/entries/:index`, not
+/// the concrete path) so label cardinality stays bounded.
+pub async fn track(State(metrics): State<Arc<Metrics>>, req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |p| p.as_str())
+        .to_string();
+    let response = next.run(req).await;
+    metrics.request(
+        &method,
+        &route,
+        response.status().as_u16(),
+        start.elapsed().as_secs_f64(),
+    );
+    response
+}
@@ -0,0 +1,511 @@
+//! Mirror mode: follow another qs_server, verify its signed tree heads and keep a verified copy of
+//! its log. A head that contradicts what the mirror already verified is a fork: the mirror stops,
+//! raises an alarm and keeps both heads as evidence.
+
+use crate::tree::TreeHeads;
+use crate::{ApiError, AppState};
+use crate::writer::{LeafIndex, LedgerWriter, Leaves};
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use qs_notary::client;
+use qs_notary::ledger::{self, EntryFilter};
+use qs_notary::merkle;
+use qs_notary::protocol::SignedTreeHead;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Entries fetched per request while catching up.
+const FETCH_PAGE: u64 = 1000;
+
+/// Proof that the upstream log misbehaved: a signed head that contradicts the last verified one
+/// (or the entries the upstream served for it).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ForkEvidence {
+    pub detected_at: String,
+    pub reason: String,
+    /// Last head the mirror verified, if any.
+    pub trusted: Option<SignedTreeHead>,
+    /// Upstream-signed head that conflicts with it.
+    pub conflicting: SignedTreeHead,
+    /// Root the mirror computed from the entries it was served, when those disagreed with the head.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub computed_root: Option<String>,
+}
+
+/// Response of `GET /mirror/status`.
+#[derive(Serialize, Clone, Debug)]
+pub struct MirrorStatus {
+    pub upstream: String,
+    /// Latest upstream head whose entries this mirror holds and has checked.
+    pub verified: Option<SignedTreeHead>,
+    pub last_sync: Option<String>,
+    pub last_error: Option<String>,
+    pub fork: Option<ForkEvidence>,
+}
+
+pub struct Mirror {
+    upstream: String,
+    public_key: PublicKey,
+    interval: Duration,
+    /// Latest verified head, persisted so consistency checks continue across restarts.
+    head_path: PathBuf,
+    fork_path: PathBuf,
+    status: Mutex<MirrorStatus>,
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Write JSON via a temporary file, so a crash never leaves a partial file.
+fn write_json(path: &Path, value: &impl Serialize) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(value)?)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Run a blocking client call on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(f).await?
+}
+
+impl Mirror {
+    /// Set up mirroring of `upstream`, whose heads are signed by `public_key`. Loads the last
+    /// verified head from `data_dir`, and refuses to start if fork evidence from an earlier run is
+    /// still there or the local copy no longer matches the verified head.
+    pub fn open(
+        upstream: String,
+        public_key: PublicKey,
+        interval: Duration,
+        data_dir: &Path,
+        leaves: &Leaves,
+    ) -> anyhow::Result<Self> {
+        let head_path = data_dir.join("mirror_tree_head.json");
+        let fork_path = data_dir.join("mirror_fork.json");
+        if read_json::<ForkEvidence>(&fork_path)?.is_some() {
+            return Err(anyhow::anyhow!(
+                "Fork evidence in {}: the upstream log forked; review the file and remove it to mirror again",
+                fork_path.display()
+            ));
+        }
+        let verified: Option<SignedTreeHead> = read_json(&head_path)?;
+        if let Some(ref head) = verified {
+            head.verify(&public_key)
+                .map_err(|e| anyhow::anyhow!("{}: {}", head_path.display(), e))?;
+            let size = head.body.tree_size;
+            if size > leaves.len()
+                || merkle::root(&leaves.hashes()[..size as usize]) != head.body.root()?
+            {
+                return Err(anyhow::anyhow!(
+                    "Local log does not match the verified upstream tree head in {}",
+                    head_path.display()
+                ));
+            }
+        }
+        Ok(Self {
+            status: Mutex::new(MirrorStatus {
+                upstream: upstream.clone(),
+                verified,
+                last_sync: None,
+                last_error: None,
+                fork: None,
+            }),
+            upstream,
+            public_key,
+            interval,
+            head_path,
+            fork_path,
+        })
+    }
+
+    pub fn status(&self) -> MirrorStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Poll the upstream every interval until the task is aborted. Stops syncing once a fork is seen.
+    pub async fn run(self: Arc<Self>, writer: LedgerWriter, leaves: LeafIndex, heads: Arc<TreeHeads>) {
+        loop {
+            if self.status().fork.is_none() {
+                let result = self.sync(&writer, &leaves, &heads).await;
+                let mut status = self.status.lock().unwrap();
+                match result {
+                    Ok(()) => {
+                        status.last_sync = Some(chrono::Utc::now().to_rfc3339());
+                        status.last_error = None;
+                    }
+                    Err(e) => {
+                        tracing::warn!(upstream = %self.upstream, error = %e, "mirror sync failed");
+                        status.last_error = Some(e.to_string());
+                    }
+                }
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Fetch the upstream head, check it against the last verified one, copy the new entries and
+    /// check that they produce the signed root before storing them.
+    async fn sync(
+        &self,
+        writer: &LedgerWriter,
+        leaves: &LeafIndex,
+        heads: &TreeHeads,
+    ) -> anyhow::Result<()> {
+        let upstream = self.upstream.clone();
+        let head = blocking(move || client::tree_head(&upstream)).await?;
+        head.verify(&self.public_key)?;
+        let trusted = self.status().verified;
+        let size = head.body.tree_size;
+        let root = head.body.root()?;
+        let have = leaves.read().unwrap().len();
+        if size <= have {
+            // Nothing new to copy; the head must still describe the log we hold.
+            let local = merkle::root(&leaves.read().unwrap().hashes()[..size as usize]);
+            if local != root {
+                return self.fork("tree head does not match the verified log at that size", trusted, head, None);
+            }
+            if trusted.as_ref().is_none_or(|t| t.body.tree_size < size) {
+                self.accept(head, heads)?;
+            }
+            return Ok(());
+        }
+        if let Some(ref trusted) = trusted {
+            let (upstream, first) = (self.upstream.clone(), trusted.body.tree_size);
+            let proof = blocking(move || client::consistency(&upstream, first, size)).await?;
+            if let Err(e) = proof.verify(&trusted.body, &head.body) {
+                return self.fork(&e.to_string(), Some(trusted.clone()), head, None);
+            }
+        }
+        let mut fetched = Vec::new();
+        while have + (fetched.len() as u64) < size {
+            let offset = have + fetched.len() as u64;
+            let filter = EntryFilter {
+                offset,
+                limit: Some(FETCH_PAGE.min(size - offset)),
+                ..Default::default()
+            };
+            let upstream = self.upstream.clone();
+            let page = blocking(move || client::search(&upstream, &filter)).await?;
+            if page.entries.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "upstream served {} entries but signed a tree head of size {}",
+                    offset,
+                    size
+                ));
+            }
+            for e in page.entries.into_iter().take((size - offset) as usize) {
+                if e.index != have + fetched.len() as u64 {
+                    return Err(anyhow::anyhow!("upstream returned entry {} out of order", e.index));
+                }
+                fetched.push(e.entry);
+            }
+        }
+        let mut hashes = leaves.read().unwrap().hashes().to_vec();
+        hashes.extend(fetched.iter().map(ledger::leaf_hash));
+        let computed = merkle::root(&hashes);
+        if computed != root {
+            return self.fork(
+                "entries served by the upstream do not match its signed tree head",
+                trusted,
+                head,
+                Some(hex::encode(computed)),
+            );
+        }
+        for entry in fetched {
+            writer.append_verbatim(entry).await?;
+        }
+        tracing::info!(upstream = %self.upstream, tree_size = size, "mirrored up to tree head");
+        self.accept(head, heads)
+    }
+
+    /// Make `head` the latest verified head (persisted, served by `GET /tree-head`).
+    fn accept(&self, head: SignedTreeHead, heads: &TreeHeads) -> anyhow::Result<()> {
+        write_json(&self.head_path, &head)?;
+        heads.set_latest(head.clone());
+        self.status.lock().unwrap().verified = Some(head);
+        Ok(())
+    }
+
+    /// Raise the fork alarm: persist the evidence, log it and stop syncing.
+    fn fork(
+        &self,
+        reason: &str,
+        trusted: Option<SignedTreeHead>,
+        conflicting: SignedTreeHead,
+        computed_root: Option<String>,
+    ) -> anyhow::Result<()> {
+        let evidence = ForkEvidence {
+            detected_at: chrono::Utc::now().to_rfc3339(),
+            reason: reason.to_string(),
+            trusted,
+            conflicting,
+            computed_root,
+        };
+        write_json(&self.fork_path, &evidence)?;
+        tracing::error!(
+            upstream = %self.upstream,
+            reason,
+            trusted_size = evidence.trusted.as_ref().map(|h| h.body.tree_size),
+            conflicting_size = evidence.conflicting.body.tree_size,
+            evidence = %self.fork_path.display(),
+            "FORK DETECTED: upstream log is inconsistent; mirroring stopped"
+        );
+        self.status.lock().unwrap().fork = Some(evidence);
+        Err(anyhow::anyhow!("fork detected: {}", reason))
+    }
+}
+
+/// `GET /mirror/status`: upstream, latest verified head, last sync and any fork evidence.
+pub async fn status(State(state): State<AppState>) -> Result<Json<MirrorStatus>, ApiError> {
+    state
+        .mirror
+        .as_ref()
+        .map(|m| Json(m.status()))
+        .ok_or((StatusCode::NOT_FOUND, "not a mirror".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::index_entries;
+    use axum::extract::Query;
+    use axum::routing::get;
+    use axum::Router;
+    use qs_notary::crypto::{generate_keypair, key_id, load_public_key};
+    use qs_notary::key_provider::FileSystemProvider;
+    use qs_notary::ledger::LedgerEntry;
+    use qs_notary::protocol::{ConsistencyProof, EntryPage, IndexedEntry, TreeHeadBody};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("qs_server_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(history: &str, n: usize) -> Vec<LedgerEntry> {
+        (0..n)
+            .map(|i| LedgerEntry {
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                file_name: format!("{}_{}.json", history, i),
+                signature_hash: format!("{:02x}", i),
+                digest: None,
+                key_id: None,
+                revocation: None,
+                attributes: None,
+            })
+            .collect()
+    }
+
+    fn leaf_hashes(entries: &[LedgerEntry]) -> Vec<merkle::Hash> {
+        entries.iter().map(ledger::leaf_hash).collect()
+    }
+
+    /// An upstream log that serves whatever history and head the test gives it.
+    struct FakeUpstream {
+        signer: FileSystemProvider,
+        log_key_id: String,
+        log: Mutex<(Vec<LedgerEntry>, Option<SignedTreeHead>)>,
+        head_requests: AtomicUsize,
+    }
+
+    impl FakeUpstream {
+        /// Serve `entries` and a head signed over all of them.
+        fn publish(&self, entries: Vec<LedgerEntry>) {
+            let body = TreeHeadBody {
+                tree_size: entries.len() as u64,
+                root_hash: hex::encode(merkle::root(&leaf_hashes(&entries))),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                log_key_id: self.log_key_id.clone(),
+            };
+            let head = SignedTreeHead::sign(body, &self.signer).unwrap();
+            *self.log.lock().unwrap() = (entries, Some(head));
+        }
+    }
+
+    async fn serve(upstream: Arc<FakeUpstream>) -> String {
+        let router = Router::new()
+            .route(
+                "/tree-head",
+                get(|State(u): State<Arc<FakeUpstream>>| async move {
+                    u.head_requests.fetch_add(1, Ordering::SeqCst);
+                    Json(u.log.lock().unwrap().1.clone().unwrap())
+                }),
+            )
+            .route(
+                "/tree-head/consistency",
+                get(
+                    |State(u): State<Arc<FakeUpstream>>, Query(q): Query<HashMap<String, u64>>| async move {
+                        let leaves = leaf_hashes(&u.log.lock().unwrap().0);
+                        let (first, second) = (q["first"], q["second"]);
+                        let proof = merkle::consistency_proof(&leaves[..second as usize], first as usize);
+                        Json(ConsistencyProof {
+                            first,
+                            second,
+                            proof: proof.iter().map(hex::encode).collect(),
+                        })
+                    },
+                ),
+            )
+            .route(
+                "/entries",
+                get(
+                    |State(u): State<Arc<FakeUpstream>>, Query(q): Query<HashMap<String, u64>>| async move {
+                        let log = u.log.lock().unwrap();
+                        let offset = q.get("offset").copied().unwrap_or(0) as usize;
+                        let limit = q.get("limit").copied().unwrap_or(100) as usize;
+                        let entries = log.0.iter().cloned().enumerate().skip(offset).take(limit);
+                        Json(EntryPage {
+                            entries: entries
+                                .map(|(i, entry)| IndexedEntry { index: i as u64, entry })
+                                .collect(),
+                            next_offset: None,
+                        })
+                    },
+                ),
+            )
+            .with_state(upstream);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        url
+    }
+
+    struct Fixture {
+        dir: PathBuf,
+        upstream: Arc<FakeUpstream>,
+        upstream_pk: PublicKey,
+        url: String,
+        writer: LedgerWriter,
+        leaves: LeafIndex,
+        heads: Arc<TreeHeads>,
+    }
+
+    /// A fake upstream with its own key, and an empty mirror-side ledger and writer.
+    async fn fixture(name: &str) -> Fixture {
+        let dir = scratch_dir(name);
+        for name in ["upstream", "mirror"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+            generate_keypair(&dir.join(name)).unwrap();
+        }
+        let upstream_pk = load_public_key(&dir.join("upstream/public.key")).unwrap();
+        let upstream = Arc::new(FakeUpstream {
+            signer: FileSystemProvider::new(&dir.join("upstream/private.key")),
+            log_key_id: key_id(&upstream_pk),
+            log: Mutex::new((Vec::new(), None)),
+            head_requests: AtomicUsize::new(0),
+        });
+        let url = serve(upstream.clone()).await;
+        let ledger_path = dir.join("ledger.json");
+        let leaves = index_entries(&[]);
+        let (writer, _task) = LedgerWriter::spawn(
+            ledger_path.clone(),
+            ledger::open_store(&ledger_path, None).unwrap(),
+            ledger::Rotation::default(),
+            Box::new(FileSystemProvider::new(&dir.join("mirror/private.key"))),
+            String::new(),
+            leaves.clone(),
+            None,
+            Box::new(|_| {}),
+        );
+        Fixture {
+            dir,
+            upstream,
+            upstream_pk,
+            url,
+            writer,
+            leaves,
+            heads: Arc::new(TreeHeads::mirrored(None)),
+        }
+    }
+
+    impl Fixture {
+        fn open(&self, interval: Duration) -> anyhow::Result<Mirror> {
+            Mirror::open(
+                self.url.clone(),
+                self.upstream_pk,
+                interval,
+                &self.dir,
+                &self.leaves.read().unwrap(),
+            )
+        }
+
+        async fn sync(&self, mirror: &Mirror) -> anyhow::Result<()> {
+            mirror.sync(&self.writer, &self.leaves, &self.heads).await
+        }
+
+        fn evidence(&self) -> ForkEvidence {
+            read_json(&self.dir.join("mirror_fork.json"))
+                .unwrap()
+                .expect("fork evidence written")
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn conflicting_heads_raise_fork_and_stop_mirroring() {
+        let f = fixture("mirror_fork").await;
+        let mirror = Arc::new(f.open(Duration::from_millis(10)).unwrap());
+
+        // First head: three entries, copied and verified.
+        f.upstream.publish(entries("a", 3));
+        f.sync(&mirror).await.unwrap();
+        assert_eq!(mirror.status().verified.unwrap().body.tree_size, 3);
+        assert_eq!(f.leaves.read().unwrap().len(), 3);
+
+        // Second head: a larger tree over a different history, signed by the same upstream key.
+        f.upstream.publish(entries("b", 5));
+        let err = f.sync(&mirror).await.unwrap_err();
+        assert!(err.to_string().contains("fork detected"), "{}", err);
+
+        let evidence = f.evidence();
+        assert_eq!(evidence.trusted.unwrap().body.tree_size, 3);
+        assert_eq!(evidence.conflicting.body.tree_size, 5);
+        evidence.conflicting.verify(&f.upstream_pk).unwrap();
+        assert!(mirror.status().fork.is_some());
+        // Nothing from the forked history was copied; the served head stays the first one.
+        assert_eq!(f.leaves.read().unwrap().len(), 3);
+        assert_eq!(f.heads.latest().unwrap().body.tree_size, 3);
+
+        // The sync loop no longer contacts the upstream.
+        let polled = f.upstream.head_requests.load(Ordering::SeqCst);
+        let task = tokio::spawn(mirror.clone().run(f.writer.clone(), f.leaves.clone(), f.heads.clone()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        task.abort();
+        assert_eq!(f.upstream.head_requests.load(Ordering::SeqCst), polled);
+
+        // A restart refuses to mirror while the evidence is there, and resumes once it is removed.
+        let err = f.open(Duration::from_millis(10)).err().expect("startup refused");
+        assert!(err.to_string().contains("mirror_fork.json"), "{}", err);
+        std::fs::remove_file(f.dir.join("mirror_fork.json")).unwrap();
+        assert!(f.open(Duration::from_millis(10)).is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn same_size_head_with_other_root_is_a_fork() {
+        let f = fixture("mirror_fork_same_size").await;
+        let mirror = f.open(Duration::from_secs(1)).unwrap();
+
+        f.upstream.publish(entries("a", 4));
+        f.sync(&mirror).await.unwrap();
+        f.upstream.publish(entries("b", 4));
+        assert!(f.sync(&mirror).await.is_err());
+        let evidence = f.evidence();
+        assert_ne!(evidence.trusted.unwrap().body.root_hash, evidence.conflicting.body.root_hash);
+    }
+}
@@ -0,0 +1,45 @@
+//! Server-side policy store for `POST /verify`: one policy JSON file per name.
+
+use qs_notary::policy::Policy;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub struct PolicyStore {
+    policies: HashMap<String, Policy>,
+}
+
+impl PolicyStore {
+    /// Load every `<name>.json` in `dir` as the policy `<name>`. A missing directory is an empty store.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut policies = HashMap::new();
+        let read_dir = match std::fs::read_dir(dir) {
+            Ok(r) => r,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self { policies }),
+            Err(e) => {
+                return Err(anyhow::anyhow!("Failed to read policy dir {}: {}", dir.display(), e))
+            }
+        };
+        for entry in read_dir {
+            let path = entry?.path();
+            let Some(name) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            let policy = Policy::load(&path)
+                .map_err(|e| anyhow::anyhow!("Policy {}: {}", path.display(), e))?;
+            policies.insert(name.to_string(), policy);
+        }
+        Ok(Self { policies })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Policy> {
+        self.policies.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.policies.len()
+    }
+}
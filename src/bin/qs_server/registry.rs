@@ -0,0 +1,141 @@
+//! Key registry: enrolled public keys (allowed to upload) and their revocations, keyed by key id.
+//!
+//! Keys are persisted as files in the keys directory; revocations live only in the log and are
+//! replayed from it on startup.
+
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use pqcrypto_traits::sign::PublicKey as _;
+use qs_notary::crypto::{key_id, load_public_key};
+use qs_notary::ledger::{KeyRevocation, LedgerEntry};
+use qs_notary::protocol::{KeyInfo, RegistrySnapshot};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+#[derive(Clone)]
+pub struct RegisteredKey {
+    pub public_key: PublicKey,
+    pub revocation: Option<KeyRevocation>,
+}
+
+impl RegisteredKey {
+    /// True if the key's revocation has taken effect.
+    pub fn is_revoked(&self) -> bool {
+        self.revocation.as_ref().is_some_and(|r| {
+            chrono::DateTime::parse_from_rfc3339(&r.effective_at)
+                .map_or(true, |t| t <= chrono::Utc::now())
+        })
+    }
+
+    fn info(&self, key_id: &str) -> KeyInfo {
+        KeyInfo {
+            key_id: key_id.to_string(),
+            public_key: hex::encode(self.public_key.as_bytes()),
+            revocation: self.revocation.clone(),
+        }
+    }
+}
+
+pub struct KeyRegistry {
+    dir: PathBuf,
+    keys: RwLock<HashMap<String, RegisteredKey>>,
+}
+
+impl KeyRegistry {
+    /// Load every non-hidden file in `dir` as a Dilithium5 public key (a missing directory is an
+    /// empty registry), then apply the revocations that `entries` record under the server key
+    /// `log_key_id`.
+    pub fn load(dir: &Path, entries: &[LedgerEntry], log_key_id: &str) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        match std::fs::read_dir(dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let path = entry?.path();
+                    let hidden = path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .is_none_or(|n| n.starts_with('.'));
+                    if !path.is_file() || hidden {
+                        continue;
+                    }
+                    let pk = load_public_key(&path)
+                        .map_err(|e| anyhow::anyhow!("Enrolled key {}: {}", path.display(), e))?;
+                    keys.insert(
+                        key_id(&pk),
+                        RegisteredKey {
+                            public_key: pk,
+                            revocation: None,
+                        },
+                    );
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!("Failed to read key dir {}: {}", dir.display(), e))
+            }
+        }
+        let revocations = entries
+            .iter()
+            .filter(|e| e.key_id.as_deref() == Some(log_key_id))
+            .filter_map(|e| e.revocation.as_ref());
+        for revocation in revocations {
+            if let Some(key) = keys.get_mut(&revocation.key_id.to_lowercase()) {
+                key.revocation.get_or_insert_with(|| revocation.clone());
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            keys: RwLock::new(keys),
+        })
+    }
+
+    pub fn get(&self, key_id: &str) -> Option<RegisteredKey> {
+        self.keys.read().unwrap().get(&key_id.to_lowercase()).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.read().unwrap().len()
+    }
+
+    /// Enroll a key: write it to the keys directory as `<key id>.key` (via a hidden temporary
+    /// file, so a crash never leaves a partial key). Enrolling a known key changes nothing.
+    pub fn enroll(&self, public_key: PublicKey) -> anyhow::Result<KeyInfo> {
+        let id = key_id(&public_key);
+        if let Some(key) = self.get(&id) {
+            return Ok(key.info(&id));
+        }
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create key dir {}: {}", self.dir.display(), e))?;
+        let path = self.dir.join(format!("{}.key", id));
+        let tmp = self.dir.join(format!(".{}.tmp", id));
+        std::fs::write(&tmp, public_key.as_bytes())
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        let key = RegisteredKey {
+            public_key,
+            revocation: None,
+        };
+        let info = key.info(&id);
+        self.keys.write().unwrap().insert(id, key);
+        Ok(info)
+    }
+
+    /// Record a revocation that has been written to the log.
+    pub fn revoke(&self, revocation: KeyRevocation) {
+        if let Some(key) = self.keys.write().unwrap().get_mut(&revocation.key_id) {
+            key.revocation = Some(revocation);
+        }
+    }
+
+    pub fn info(&self, key_id: &str) -> Option<KeyInfo> {
+        self.get(key_id).map(|k| k.info(&key_id.to_lowercase()))
+    }
+
+    /// All keys, ordered by key id.
+    pub fn snapshot(&self) -> RegistrySnapshot {
+        let keys = self.keys.read().unwrap();
+        let mut infos: Vec<KeyInfo> = keys.iter().map(|(id, k)| k.info(id)).collect();
+        infos.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+        RegistrySnapshot { keys: infos }
+    }
+}
@@ -0,0 +1,106 @@
+//! Remote signing: server-held keys, usable by the team tokens that list them.
+
+use crate::auth::ApiTokens;
+use axum::http::StatusCode;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use qs_notary::crypto::{key_id, load_public_key};
+use qs_notary::key_provider::{self, FileSystemProvider, KeyProvider};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+struct SigningKey {
+    provider: FileSystemProvider,
+    key_id: String,
+    public_key: PublicKey,
+}
+
+pub struct SigningService {
+    keys: HashMap<String, SigningKey>,
+    tokens: Arc<ApiTokens>,
+}
+
+impl SigningService {
+    /// Load `<name>.private.key` files from `keys_dir` (each needs `<name>.public.key` next to it).
+    /// A missing directory means no keys. Team tokens say which keys each team may use.
+    pub fn load(keys_dir: &Path, tokens: Arc<ApiTokens>) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        match std::fs::read_dir(keys_dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let path = entry?.path();
+                    let Some(name) = path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .and_then(|n| n.strip_suffix(".private.key"))
+                    else {
+                        continue;
+                    };
+                    let public_key = key_provider::public_key_path(&path)
+                        .and_then(|p| load_public_key(&p).ok())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Signing key {} has no {}.public.key", path.display(), name)
+                        })?;
+                    keys.insert(
+                        name.to_string(),
+                        SigningKey {
+                            provider: FileSystemProvider::new(&path),
+                            key_id: key_id(&public_key),
+                            public_key,
+                        },
+                    );
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to read signing key dir {}: {}",
+                    keys_dir.display(),
+                    e
+                ))
+            }
+        }
+        for grant in tokens.grants() {
+            for key in grant.keys.iter().filter(|k| !keys.contains_key(*k)) {
+                tracing::warn!(team = %grant.team, key = %key, "API token grants an unknown signing key");
+            }
+        }
+        Ok(Self { keys, tokens })
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Team owning `token`, if the token may use `key`. 401 for an unknown token, 403 if the
+    /// key is not on the token's allowlist, 404 if the server has no such key.
+    pub fn authorize(&self, token: &str, key: &str) -> Result<String, (StatusCode, String)> {
+        let grant = self.tokens.team(token)?;
+        if !grant.keys.iter().any(|k| k == key) {
+            return Err((StatusCode::FORBIDDEN, "key not allowed for this token".to_string()));
+        }
+        if !self.keys.contains_key(key) {
+            return Err((StatusCode::NOT_FOUND, "no such signing key".to_string()));
+        }
+        Ok(grant.team.clone())
+    }
+
+    /// Public keys of all server-held signing keys.
+    pub fn public_keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.keys.values().map(|k| &k.public_key)
+    }
+
+    /// Key id of the named key.
+    pub fn key_id(&self, key: &str) -> Option<&str> {
+        self.keys.get(key).map(|k| k.key_id.as_str())
+    }
+
+    /// Sign `digest` with the named key; returns the signature and the key id.
+    pub fn sign(&self, key: &str, digest: &[u8]) -> anyhow::Result<(Vec<u8>, String)> {
+        let key = self
+            .keys
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("no signing key {}", key))?;
+        Ok((key.provider.sign(digest)?, key.key_id.clone()))
+    }
+}
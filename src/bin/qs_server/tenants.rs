@@ -0,0 +1,168 @@
+//! Tenants: teams sharing one server, each with its own log under `/t/{tenant}/...`.
+//!
+//! A tenant's log keeps the same files as the server's own log (ledger, signing key, key registry,
+//! API tokens, remote signing keys, policies, witnesses) under `<data-dir>/tenants/<name>`, so its
+//! entries, tree heads and receipts never mix with another tenant's.
+
+use crate::registry::KeyRegistry;
+use crate::writer::LeafIndex;
+use crate::{ApiError, AppState};
+use axum::{
+    extract::{Json, State},
+    http::HeaderMap,
+};
+use qs_notary::ledger::{EntryFilter, LedgerEntry};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Tenant file: one `[[tenant]]` table per tenant.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TenantFile {
+    #[serde(default)]
+    tenant: Vec<TenantConfig>,
+}
+
+/// A tenant's name (the `{tenant}` in its URLs and its directory name), quota and retention.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    pub name: String,
+    /// Most entries the tenant's log may hold; further uploads and remote signatures get 429.
+    pub max_entries: Option<u64>,
+    /// Entries older than this many days are no longer served. They stay in the log, so tree
+    /// heads, receipts and inclusion proofs keep verifying.
+    pub retention_days: Option<u32>,
+}
+
+/// Load the tenant file; a missing file means no tenants. Names must be unique and safe to use in
+/// URLs and paths (lowercase letters, digits, `-`, `_`).
+pub fn load(path: &Path) -> anyhow::Result<Vec<TenantConfig>> {
+    let file: TenantFile = match std::fs::read_to_string(path) {
+        Ok(s) => toml::from_str(&s)
+            .map_err(|e| anyhow::anyhow!("Invalid tenant file {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => TenantFile::default(),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+    for (i, tenant) in file.tenant.iter().enumerate() {
+        let valid = !tenant.name.is_empty()
+            && tenant.name.len() <= 64
+            && tenant
+                .name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid tenant name {:?} in {}: use 1-64 lowercase letters, digits, '-' or '_'",
+                tenant.name,
+                path.display()
+            ));
+        }
+        if file.tenant[..i].iter().any(|t| t.name == tenant.name) {
+            return Err(anyhow::anyhow!(
+                "Duplicate tenant {:?} in {}",
+                tenant.name,
+                path.display()
+            ));
+        }
+    }
+    Ok(file.tenant)
+}
+
+impl TenantConfig {
+    /// Oldest entry time still served, if retention is set.
+    fn retention_start(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.retention_days
+            .map(|days| chrono::Utc::now() - chrono::Duration::days(days.into()))
+    }
+
+    /// Narrow `filter` to entries within retention.
+    pub fn retain(&self, filter: &mut EntryFilter) {
+        let Some(start) = self.retention_start() else {
+            return;
+        };
+        let since = filter
+            .since
+            .as_deref()
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok());
+        if since.is_none_or(|s| s < start) {
+            filter.since = Some(start.to_rfc3339());
+        }
+    }
+
+    /// True if `entry` is past retention (entries with an unreadable time are kept).
+    pub fn expired(&self, entry: &LedgerEntry) -> bool {
+        self.retention_start().is_some_and(|start| {
+            chrono::DateTime::parse_from_rfc3339(&entry.timestamp).is_ok_and(|t| t < start)
+        })
+    }
+}
+
+/// What the cross-tenant admin view reads from each tenant's log.
+pub struct Tenant {
+    pub config: Arc<TenantConfig>,
+    pub log_key_id: String,
+    pub leaves: LeafIndex,
+    pub ledger_path: PathBuf,
+    pub registry: Arc<KeyRegistry>,
+}
+
+impl Tenant {
+    pub fn of(state: &AppState, config: Arc<TenantConfig>) -> Self {
+        Self {
+            config,
+            log_key_id: state.log_key_id.clone(),
+            leaves: state.leaves.clone(),
+            ledger_path: state.ledger_path.clone(),
+            registry: state.registry.clone(),
+        }
+    }
+}
+
+/// One tenant in `GET /admin/tenants`.
+#[derive(Serialize, Clone, Debug)]
+pub struct TenantSummary {
+    pub name: String,
+    /// Key id of the tenant's log key (receipts, tree heads, revocations).
+    pub log_key_id: String,
+    pub entries: u64,
+    /// Size of the active ledger file.
+    pub ledger_bytes: u64,
+    pub keys: usize,
+    pub revoked_keys: usize,
+    pub max_entries: Option<u64>,
+    pub retention_days: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TenantList {
+    pub tenants: Vec<TenantSummary>,
+}
+
+/// `GET /admin/tenants`: every tenant with its log size, registry and limits. Needs an admin token
+/// of the server's own log.
+pub async fn list(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<TenantList>, ApiError> {
+    state.tokens.admin(&headers)?;
+    let tenants = state
+        .tenants
+        .iter()
+        .map(|t| {
+            let keys = t.registry.snapshot().keys;
+            TenantSummary {
+                name: t.config.name.clone(),
+                log_key_id: t.log_key_id.clone(),
+                entries: t.leaves.read().unwrap().len(),
+                ledger_bytes: std::fs::metadata(&t.ledger_path).map_or(0, |m| m.len()),
+                keys: keys.len(),
+                revoked_keys: keys.iter().filter(|k| k.revocation.is_some()).count(),
+                max_entries: t.config.max_entries,
+                retention_days: t.config.retention_days,
+            }
+        })
+        .collect();
+    Ok(Json(TenantList { tenants }))
+}
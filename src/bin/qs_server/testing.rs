@@ -0,0 +1,164 @@
+//! Test harness: a log opened from a scratch data directory like `main` opens the server's own,
+//! with requests sent straight to its router.
+
+use crate::config::{Args, Config};
+use crate::metrics::Metrics;
+use crate::tsa::TimestampAuthority;
+use crate::{log_routes, open_log, rate_limiter, readyz, server_public_key, AppState};
+use axum::body::{Body, BodyDataStream};
+use axum::http::{header, Method, Request, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use clap::Parser;
+use qs_notary::crypto::{self, generate_keypair, load_public_key};
+use qs_notary::key_provider::{FileSystemProvider, KeyProvider};
+use qs_notary::ledger::LedgerEntry;
+use sha3::{Digest, Sha3_256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qs_server_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The server's own log, served without TLS.
+pub struct TestLog {
+    pub dir: PathBuf,
+    pub state: AppState,
+    app: Router,
+    flags: Vec<String>,
+    writer_task: JoinHandle<()>,
+}
+
+/// Open a log in a fresh scratch directory, configured with `flags` besides `--data-dir`.
+pub fn open(name: &str, flags: &[&str]) -> TestLog {
+    open_in(scratch_dir(name), flags.iter().map(|f| f.to_string()).collect())
+}
+
+fn open_in(dir: PathBuf, flags: Vec<String>) -> TestLog {
+    let args = ["qs_server", "--data-dir", dir.to_str().unwrap()]
+        .into_iter()
+        .chain(flags.iter().map(String::as_str));
+    let config = Config::from_args(Args::try_parse_from(args).unwrap()).unwrap();
+    let tsa = Arc::new(
+        TimestampAuthority::open(
+            &config.data_dir,
+            Box::new(FileSystemProvider::new(&config.server_key)),
+            crypto::key_id(&server_public_key(&config.server_key).unwrap()),
+        )
+        .unwrap(),
+    );
+    let metrics = Arc::new(Metrics::new());
+    let (state, writer_task) =
+        open_log(&config, &config.log_paths(), &metrics, &tsa, None, None).unwrap();
+    let app = log_routes(&config, rate_limiter(&config), false)
+        .route("/readyz", get(readyz))
+        .with_state(state.clone());
+    TestLog {
+        dir,
+        state,
+        app,
+        flags,
+        writer_task,
+    }
+}
+
+impl TestLog {
+    /// Shut the log down as the server does (drop every writer handle, then wait for queued
+    /// writes to land) and open it again from its data directory with the same flags.
+    pub async fn restart(self) -> TestLog {
+        let TestLog {
+            dir,
+            state,
+            app,
+            flags,
+            writer_task,
+        } = self;
+        drop(app);
+        drop(state);
+        writer_task.await.unwrap();
+        open_in(dir, flags)
+    }
+
+    /// Send a request with an optional bearer token and JSON body. Returns the status and the body,
+    /// parsed as JSON if it is JSON and as a JSON string otherwise.
+    pub async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&body).unwrap())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into()));
+        (status, body)
+    }
+
+    /// Send a GET request and return the status and the response body as it streams in.
+    pub async fn open_stream(&self, uri: &str) -> (StatusCode, BodyDataStream) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        (response.status(), response.into_body().into_data_stream())
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, serde_json::Value) {
+        self.send(Method::GET, uri, None, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: impl serde::Serialize) -> (StatusCode, serde_json::Value) {
+        self.send(Method::POST, uri, None, Some(serde_json::to_value(body).unwrap()))
+            .await
+    }
+
+    /// Generate a key pair in `<dir>/<name>` and enroll its public key in the registry.
+    pub fn enroll(&self, name: &str) -> FileSystemProvider {
+        let key_dir = self.dir.join(name);
+        std::fs::create_dir_all(&key_dir).unwrap();
+        generate_keypair(&key_dir).unwrap();
+        let pk = load_public_key(&key_dir.join("public.key")).unwrap();
+        self.state.registry.enroll(pk).unwrap();
+        FileSystemProvider::new(&key_dir.join("private.key"))
+    }
+}
+
+/// Write the signing key pair `<name>.public.key` and `<name>.private.key` into
+/// `signing_keys_dir`, where the server loads its remote signing keys from.
+pub fn add_signing_key(signing_keys_dir: &Path, name: &str) -> FileSystemProvider {
+    std::fs::create_dir_all(signing_keys_dir).unwrap();
+    let private_key = signing_keys_dir.join(format!("{}.private.key", name));
+    crypto::write_keypair(&signing_keys_dir.join(format!("{}.public.key", name)), &private_key).unwrap();
+    FileSystemProvider::new(&private_key)
+}
+
+/// A ledger entry for an artifact `file_name` signed by `key`, as `sign` records it.
+pub fn signed_entry(key: &dyn KeyProvider, file_name: &str) -> LedgerEntry {
+    let digest = Sha3_256::digest(file_name.as_bytes());
+    LedgerEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        file_name: file_name.to_string(),
+        signature_hash: hex::encode(key.sign(&digest).unwrap()),
+        digest: Some(hex::encode(digest)),
+        key_id: key.key_id(),
+        revocation: None,
+        attributes: None,
+    }
+}
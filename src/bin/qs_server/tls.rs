@@ -0,0 +1,27 @@
+//! HTTPS for the listener: rustls with the configured certificate chain and key and, with a client
+//! CA, mutual TLS (a client without a certificate issued by that CA cannot connect).
+
+use crate::config::TlsConfig;
+use qs_notary::tls::{load_certs, load_private_key, provider, root_store};
+use rustls::server::WebPkiClientVerifier;
+use std::sync::Arc;
+
+pub fn server_config(tls: &TlsConfig) -> anyhow::Result<rustls::ServerConfig> {
+    let builder =
+        rustls::ServerConfig::builder_with_provider(provider()).with_safe_default_protocol_versions()?;
+    let builder = match &tls.client_ca {
+        Some(ca) => {
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(root_store(ca)?), provider())
+                    .build()
+                    .map_err(|e| anyhow::anyhow!("Invalid client CA {}: {}", ca.display(), e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(load_certs(&tls.cert)?, load_private_key(&tls.key)?)
+        .map_err(|e| anyhow::anyhow!("Invalid TLS certificate {}: {}", tls.cert.display(), e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
@@ -0,0 +1,372 @@
+//! Signed tree heads, witness cosignatures, and inclusion and consistency proofs over the log's
+//! Merkle tree.
+
+use crate::{ApiError, AppState};
+use axum::{
+    extract::{Json, Path as UrlPath, Query, State},
+    http::StatusCode,
+};
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use qs_notary::crypto::{key_id, load_public_key};
+use qs_notary::key_provider::FileSystemProvider;
+use qs_notary::merkle;
+use qs_notary::protocol::{
+    ConsistencyProof, Cosignature, CosignedTreeHead, CosignedTreeHeads, InclusionProof,
+    SignedTreeHead, TreeHeadBody,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Cosigned tree heads kept for clients (the largest tree sizes).
+const KEPT_COSIGNED_HEADS: usize = 32;
+
+/// Source of the tree heads this server hands out: its own (signed with the server key) or, for a
+/// mirror, the latest upstream head it has verified.
+pub struct TreeHeads {
+    signer: Option<(Arc<FileSystemProvider>, String)>,
+    latest: Mutex<Option<SignedTreeHead>>,
+}
+
+impl TreeHeads {
+    /// Heads signed by this server with `signer` (key id `log_key_id`).
+    pub fn signed(signer: Arc<FileSystemProvider>, log_key_id: String) -> Self {
+        Self {
+            signer: Some((signer, log_key_id)),
+            latest: Mutex::new(None),
+        }
+    }
+
+    /// Heads verified by a mirror, starting from `verified` (if any).
+    pub fn mirrored(verified: Option<SignedTreeHead>) -> Self {
+        Self {
+            signer: None,
+            latest: Mutex::new(verified),
+        }
+    }
+
+    pub fn latest(&self) -> Option<SignedTreeHead> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    /// Record a head a mirror has verified.
+    pub fn set_latest(&self, head: SignedTreeHead) {
+        *self.latest.lock().unwrap() = Some(head);
+    }
+}
+
+/// Sign a head for the tree of `tree_size` leaves with root `root` (blocking).
+fn sign_head(
+    signer: &FileSystemProvider,
+    log_key_id: String,
+    tree_size: u64,
+    root: merkle::Hash,
+) -> anyhow::Result<SignedTreeHead> {
+    let body = TreeHeadBody {
+        tree_size,
+        root_hash: hex::encode(root),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        log_key_id,
+    };
+    SignedTreeHead::sign(body, signer)
+}
+
+/// `GET /tree-head`: the current signed tree head. Heads are re-signed only when the log has grown.
+/// A mirror serves the latest upstream head it verified (404 before the first sync).
+pub async fn current(State(state): State<AppState>) -> Result<Json<SignedTreeHead>, ApiError> {
+    let Some((signer, log_key_id)) = state.tree_heads.signer.clone() else {
+        return state
+            .tree_heads
+            .latest()
+            .map(Json)
+            .ok_or((StatusCode::NOT_FOUND, "no verified tree head yet".to_string()));
+    };
+    let size = state.leaves.read().unwrap().len();
+    if let Some(head) = state.tree_heads.latest().filter(|h| h.body.tree_size == size) {
+        return Ok(Json(head));
+    }
+    let leaves = state.leaves.clone();
+    let head = tokio::task::spawn_blocking(move || {
+        let (tree_size, root) = {
+            let leaves = leaves.read().unwrap();
+            (leaves.len(), merkle::root(leaves.hashes()))
+        };
+        sign_head(&signer, log_key_id, tree_size, root)
+    })
+    .await
+    .map_err(crate::internal)?
+    .map_err(|e| {
+        tracing::error!(error = %e, "signing tree head failed");
+        (StatusCode::INTERNAL_SERVER_ERROR, "sign".to_string())
+    })?;
+    state.tree_heads.set_latest(head.clone());
+    Ok(Json(head))
+}
+
+#[derive(Deserialize)]
+pub struct ConsistencyQuery {
+    first: u64,
+    /// Defaults to the current log size.
+    second: Option<u64>,
+}
+
+/// `GET /tree-head/consistency?first=&second=`: proof that the tree of `first` entries is a prefix
+/// of the tree of `second` entries. 400 unless `first <= second <= log size`.
+pub async fn consistency(
+    State(state): State<AppState>,
+    Query(query): Query<ConsistencyQuery>,
+) -> Result<Json<ConsistencyProof>, ApiError> {
+    let leaves = state.leaves.clone();
+    let proof = tokio::task::spawn_blocking(move || {
+        let leaves = leaves.read().unwrap();
+        let second = query.second.unwrap_or(leaves.len());
+        if query.first > second || second > leaves.len() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("need first <= second <= {}", leaves.len()),
+            ));
+        }
+        let hashes = &leaves.hashes()[..second as usize];
+        Ok(ConsistencyProof {
+            first: query.first,
+            second,
+            proof: merkle::consistency_proof(hashes, query.first as usize)
+                .iter()
+                .map(hex::encode)
+                .collect(),
+        })
+    })
+    .await
+    .map_err(crate::internal)??;
+    Ok(Json(proof))
+}
+
+#[derive(Deserialize)]
+pub struct ProofQuery {
+    /// Defaults to the current log size.
+    tree_size: Option<u64>,
+}
+
+/// `GET /entries/{index}/proof?tree_size=`: inclusion proof for entry `index` in the tree of the
+/// first `tree_size` entries. 400 unless `index < tree_size <= log size`.
+pub async fn inclusion(
+    State(state): State<AppState>,
+    UrlPath(index): UrlPath<u64>,
+    Query(query): Query<ProofQuery>,
+) -> Result<Json<InclusionProof>, ApiError> {
+    let leaves = state.leaves.clone();
+    let proof = tokio::task::spawn_blocking(move || {
+        let leaves = leaves.read().unwrap();
+        let tree_size = query.tree_size.unwrap_or(leaves.len());
+        if index >= tree_size || tree_size > leaves.len() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("need index < tree_size <= {}", leaves.len()),
+            ));
+        }
+        let hashes = &leaves.hashes()[..tree_size as usize];
+        Ok(InclusionProof {
+            index,
+            tree_size,
+            proof: merkle::inclusion_proof(hashes, index as usize)
+                .iter()
+                .map(hex::encode)
+                .collect(),
+        })
+    })
+    .await
+    .map_err(crate::internal)??;
+    Ok(Json(proof))
+}
+
+/// Witness cosignatures collected for this log's tree heads. The witnesses are the public keys in
+/// the witnesses directory; the heads are persisted in `<data-dir>/cosigned_tree_heads.json`.
+pub struct Cosignatures {
+    witnesses: HashMap<String, PublicKey>,
+    path: PathBuf,
+    /// Largest tree size first.
+    heads: Mutex<Vec<CosignedTreeHead>>,
+}
+
+impl Cosignatures {
+    /// Load every non-hidden file in `witnesses_dir` as a witness public key (a missing directory
+    /// means no witnesses) and the cosigned heads kept in `data_dir`.
+    pub fn load(witnesses_dir: &Path, data_dir: &Path) -> anyhow::Result<Self> {
+        let mut witnesses = HashMap::new();
+        match std::fs::read_dir(witnesses_dir) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let path = entry?.path();
+                    let hidden = path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .is_none_or(|n| n.starts_with('.'));
+                    if !path.is_file() || hidden {
+                        continue;
+                    }
+                    let pk = load_public_key(&path)
+                        .map_err(|e| anyhow::anyhow!("Witness key {}: {}", path.display(), e))?;
+                    witnesses.insert(key_id(&pk), pk);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to read witnesses dir {}: {}",
+                    witnesses_dir.display(),
+                    e
+                ))
+            }
+        }
+        let path = data_dir.join("cosigned_tree_heads.json");
+        let heads = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<CosignedTreeHeads>(&bytes)
+                .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))?
+                .tree_heads,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Self {
+            witnesses,
+            path,
+            heads: Mutex::new(heads),
+        })
+    }
+
+    pub fn witness_count(&self) -> usize {
+        self.witnesses.len()
+    }
+
+    pub fn heads(&self) -> Vec<CosignedTreeHead> {
+        self.heads.lock().unwrap().clone()
+    }
+
+    /// The kept head for `tree_size`, if any.
+    fn head(&self, tree_size: u64) -> Option<SignedTreeHead> {
+        self.heads
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|h| h.tree_head.body.tree_size == tree_size)
+            .map(|h| h.tree_head.clone())
+    }
+
+    /// Attach a checked cosignature to `head`, replacing an earlier one from the same witness, and
+    /// persist the kept heads (via a temporary file, so a crash never leaves a partial file).
+    fn add(&self, head: SignedTreeHead, cosignature: Cosignature) -> anyhow::Result<CosignedTreeHead> {
+        let mut heads = self.heads.lock().unwrap();
+        let size = head.body.tree_size;
+        let pos = match heads.iter().position(|h| h.tree_head.body.tree_size == size) {
+            Some(pos) => pos,
+            None => {
+                heads.push(CosignedTreeHead {
+                    tree_head: head,
+                    cosignatures: Vec::new(),
+                });
+                heads.sort_by_key(|h| std::cmp::Reverse(h.tree_head.body.tree_size));
+                heads
+                    .iter()
+                    .position(|h| h.tree_head.body.tree_size == size)
+                    .unwrap()
+            }
+        };
+        let cosigned = &mut heads[pos];
+        cosigned.cosignatures.retain(|c| {
+            !c.body
+                .witness_key_id
+                .eq_ignore_ascii_case(&cosignature.body.witness_key_id)
+        });
+        cosigned.cosignatures.push(cosignature);
+        let result = cosigned.clone();
+        heads.truncate(KEPT_COSIGNED_HEADS);
+        let tmp = self.path.with_extension("tmp");
+        let file = CosignedTreeHeads {
+            tree_heads: heads.clone(),
+        };
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&file)?)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", self.path.display(), e))?;
+        Ok(result)
+    }
+}
+
+/// `GET /tree-head/cosigned`: the recently cosigned tree heads with their cosignatures, largest
+/// first. `log prove` picks one of them for its inclusion proof.
+pub async fn cosigned(State(state): State<AppState>) -> Json<CosignedTreeHeads> {
+    Json(CosignedTreeHeads {
+        tree_heads: state.cosignatures.heads(),
+    })
+}
+
+/// `POST /tree-head/cosignatures`: record a witness's cosignature on this log's tree of that size.
+/// 401 for a key not in the witnesses directory, 403 for an invalid signature, 400 for another
+/// log's tree or one larger than the log, 409 if the root is not this log's root at that size.
+pub async fn cosign(
+    State(state): State<AppState>,
+    Json(cosignature): Json<Cosignature>,
+) -> Result<Json<CosignedTreeHead>, ApiError> {
+    let Some((signer, log_key_id)) = state.tree_heads.signer.clone() else {
+        return Err((StatusCode::NOT_FOUND, "not a log".to_string()));
+    };
+    let witness = *state
+        .cosignatures
+        .witnesses
+        .get(&cosignature.body.witness_key_id.to_lowercase())
+        .ok_or((StatusCode::UNAUTHORIZED, "unknown witness key".to_string()))?;
+    if !cosignature.body.log_key_id.eq_ignore_ascii_case(&log_key_id) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "cosignature is for a different log".to_string(),
+        ));
+    }
+    let cosigned = tokio::task::spawn_blocking(move || {
+        cosignature
+            .verify_signature(&witness)
+            .map_err(|_| (StatusCode::FORBIDDEN, "invalid cosignature".to_string()))?;
+        let size = cosignature.body.tree_size;
+        let root = {
+            let leaves = state.leaves.read().unwrap();
+            if size > leaves.len() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("tree size beyond the log ({} entries)", leaves.len()),
+                ));
+            }
+            merkle::root(&leaves.hashes()[..size as usize])
+        };
+        if !cosignature.body.root_hash.eq_ignore_ascii_case(&hex::encode(root)) {
+            tracing::error!(
+                witness = %cosignature.body.witness_key_id,
+                tree_size = size,
+                root_hash = %cosignature.body.root_hash,
+                "witness cosigned a root this log never had"
+            );
+            return Err((
+                StatusCode::CONFLICT,
+                "root does not match this log".to_string(),
+            ));
+        }
+        // Reuse the head already kept or served for this size; otherwise sign one.
+        let kept = state.cosignatures.head(size);
+        let head = match kept.or_else(|| state.tree_heads.latest().filter(|h| h.body.tree_size == size)) {
+            Some(head) => head,
+            None => sign_head(&signer, log_key_id, size, root).map_err(|e| {
+                tracing::error!(error = %e, "signing tree head failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "sign".to_string())
+            })?,
+        };
+        tracing::info!(
+            witness = %cosignature.body.witness_key_id,
+            tree_size = size,
+            "tree head cosigned"
+        );
+        state.cosignatures.add(head, cosignature).map_err(|e| {
+            tracing::error!(error = %e, "storing cosignature failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "write".to_string())
+        })
+    })
+    .await
+    .map_err(crate::internal)??;
+    Ok(Json(cosigned))
+}
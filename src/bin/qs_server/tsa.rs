@@ -0,0 +1,68 @@
+//! Timestamp authority: signs (digest, time, serial) tokens with the server key.
+
+use qs_notary::key_provider::KeyProvider;
+use qs_notary::protocol::{TimestampBody, TimestampToken};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub struct TimestampAuthority {
+    signer: Box<dyn KeyProvider + Send + Sync>,
+    key_id: String,
+    /// File holding the next serial, so serials stay unique across restarts.
+    serial_path: PathBuf,
+    next_serial: Mutex<u64>,
+}
+
+impl TimestampAuthority {
+    /// Open the authority, resuming the serial counter stored in `data_dir/tsa_serial`.
+    pub fn open(
+        data_dir: &Path,
+        signer: Box<dyn KeyProvider + Send + Sync>,
+        key_id: String,
+    ) -> anyhow::Result<Self> {
+        let serial_path = data_dir.join("tsa_serial");
+        let next_serial = match std::fs::read_to_string(&serial_path) {
+            Ok(s) => s.trim().parse().map_err(|e| {
+                anyhow::anyhow!("Invalid serial in {}: {}", serial_path.display(), e)
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 1,
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Failed to read {}: {}",
+                    serial_path.display(),
+                    e
+                ))
+            }
+        };
+        Ok(Self {
+            signer,
+            key_id,
+            serial_path,
+            next_serial: Mutex::new(next_serial),
+        })
+    }
+
+    /// Issue a token for a hex SHA3-256 digest. The serial is persisted before the token is
+    /// signed, so a crash can skip a serial but never reuse one.
+    pub fn issue(&self, digest: String) -> anyhow::Result<TimestampToken> {
+        let (serial, time) = {
+            let mut next = self.next_serial.lock().unwrap();
+            let serial = *next;
+            let tmp = self.serial_path.with_extension("tmp");
+            std::fs::write(&tmp, (serial + 1).to_string())
+                .and_then(|_| std::fs::rename(&tmp, &self.serial_path))
+                .map_err(|e| {
+                    anyhow::anyhow!("Failed to write {}: {}", self.serial_path.display(), e)
+                })?;
+            *next = serial + 1;
+            (serial, chrono::Utc::now().to_rfc3339())
+        };
+        let body = TimestampBody {
+            digest,
+            time,
+            serial,
+            tsa_key_id: self.key_id.clone(),
+        };
+        TimestampToken::sign(body, self.signer.as_ref())
+    }
+}
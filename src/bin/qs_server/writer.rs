@@ -0,0 +1,255 @@
+//! Single writer task per log: every append to the central ledger (or a tenant's) goes through it.
+
+use qs_notary::key_provider::KeyProvider;
+use qs_notary::ledger::{self, LedgerEntry, LedgerStore, Rotation};
+use qs_notary::merkle;
+use qs_notary::protocol::{IndexedEntry, Receipt, ReceiptBody};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Leaf hashes of the log in order (the Merkle tree's leaves) and the log index (0-based position)
+/// of each, keyed by hex leaf hash. The index answers "do you have entry X" and makes uploads
+/// idempotent.
+#[derive(Default)]
+pub struct Leaves {
+    hashes: Vec<merkle::Hash>,
+    index: HashMap<String, u64>,
+}
+
+impl Leaves {
+    pub fn len(&self) -> u64 {
+        self.hashes.len() as u64
+    }
+
+    /// Log index of the entry with this hex leaf hash (its first occurrence).
+    pub fn get(&self, leaf_hash: &str) -> Option<u64> {
+        self.index.get(leaf_hash).copied()
+    }
+
+    pub fn hashes(&self) -> &[merkle::Hash] {
+        &self.hashes
+    }
+
+    fn push(&mut self, leaf: merkle::Hash) -> u64 {
+        let index = self.len();
+        self.hashes.push(leaf);
+        self.index.entry(hex::encode(leaf)).or_insert(index);
+        index
+    }
+}
+
+pub type LeafIndex = Arc<RwLock<Leaves>>;
+
+/// Build the leaf index from the entries already in the store.
+pub fn index_entries(entries: &[LedgerEntry]) -> LeafIndex {
+    let mut leaves = Leaves::default();
+    for entry in entries {
+        leaves.push(ledger::leaf_hash(entry));
+    }
+    Arc::new(RwLock::new(leaves))
+}
+
+/// Result of an append: the signed receipt, and whether the entry is new (`false` if the log
+/// already held it).
+pub struct Appended {
+    pub receipt: Receipt,
+    pub new: bool,
+}
+
+/// Error of an append refused because the log already holds its `max_entries`.
+#[derive(Debug)]
+pub struct QuotaExceeded;
+
+impl std::fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("log quota exceeded")
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// An entry to append plus a channel to report the result back to the handler.
+struct WriteRequest {
+    entry: LedgerEntry,
+    /// Append even if the log already holds the entry (mirrors copy the upstream log verbatim).
+    verbatim: bool,
+    done: oneshot::Sender<anyhow::Result<Appended>>,
+}
+
+/// Handle to the writer task. The task exits once every handle has been dropped and the
+/// queued requests have been written, which is how shutdown flushes pending writes.
+#[derive(Clone)]
+pub struct LedgerWriter {
+    tx: mpsc::Sender<WriteRequest>,
+}
+
+impl LedgerWriter {
+    /// Spawn the writer on a blocking thread that owns the store. Requests are applied one at a
+    /// time, so entries are never interleaved (the JSONL backend also locks and fsyncs each line).
+    /// Before each append the active segment is sealed and rotated if `rotation` says so.
+    /// Each written entry gets a receipt signed by `signer` (whose key id is `log_key_id`).
+    /// Entries already in `leaves` are not appended again; the receipt carries their existing index.
+    /// Once the log holds `max_entries`, new entries fail with `QuotaExceeded`; revocations and
+    /// verbatim copies are always appended. The check and the append are one step, so concurrent
+    /// uploads cannot overshoot the quota.
+    /// `on_append` is called with each newly appended entry (live feed, metrics).
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        path: PathBuf,
+        mut store: Box<dyn LedgerStore>,
+        rotation: Rotation,
+        signer: Box<dyn KeyProvider + Send>,
+        log_key_id: String,
+        leaves: LeafIndex,
+        max_entries: Option<u64>,
+        on_append: Box<dyn Fn(IndexedEntry) + Send>,
+    ) -> (Self, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<WriteRequest>(1024);
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(req) = rx.blocking_recv() {
+                let leaf_bytes = ledger::leaf_hash(&req.entry);
+                let leaf = hex::encode(leaf_bytes);
+                let existing = if req.verbatim {
+                    None
+                } else {
+                    leaves.read().unwrap().get(&leaf)
+                };
+                let over_quota = existing.is_none()
+                    && !req.verbatim
+                    && req.entry.revocation.is_none()
+                    && max_entries.is_some_and(|max| leaves.read().unwrap().len() >= max);
+                let index = match existing {
+                    Some(index) => Ok(index),
+                    None if over_quota => Err(anyhow::Error::new(QuotaExceeded)),
+                    None => ledger::rotate_if_needed(&path, &rotation, signer.as_ref())
+                        .and_then(|_| store.append(&req.entry))
+                        .map(|_| {
+                            let index = leaves.write().unwrap().push(leaf_bytes);
+                            on_append(IndexedEntry {
+                                index,
+                                entry: req.entry.clone(),
+                            });
+                            index
+                        }),
+                };
+                let result = index.and_then(|log_index| {
+                    let body = ReceiptBody {
+                        log_index,
+                        integrated_time: chrono::Utc::now().to_rfc3339(),
+                        leaf_hash: leaf,
+                        log_key_id: log_key_id.clone(),
+                    };
+                    Ok(Appended {
+                        receipt: Receipt::sign(body, signer.as_ref())?,
+                        new: existing.is_none(),
+                    })
+                });
+                let _ = req.done.send(result);
+            }
+        });
+        (Self { tx }, handle)
+    }
+
+    /// True once the writer task has exited (it no longer accepts appends).
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Append an entry and return its signed inclusion receipt.
+    pub async fn append(&self, entry: LedgerEntry) -> anyhow::Result<Appended> {
+        self.send(entry, false).await
+    }
+
+    /// Append an entry copied from another log, even if an identical entry is already present.
+    pub async fn append_verbatim(&self, entry: LedgerEntry) -> anyhow::Result<Appended> {
+        self.send(entry, true).await
+    }
+
+    async fn send(&self, entry: LedgerEntry, verbatim: bool) -> anyhow::Result<Appended> {
+        let (done, rx) = oneshot::channel();
+        self.tx
+            .send(WriteRequest {
+                entry,
+                verbatim,
+                done,
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("ledger writer stopped"))?;
+        rx.await
+            .map_err(|_| anyhow::anyhow!("ledger writer stopped"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qs_notary::crypto::generate_keypair;
+    use qs_notary::key_provider::FileSystemProvider;
+    use qs_notary::ledger::KeyRevocation;
+
+    fn entry(i: usize) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            file_name: format!("f{}.bin", i),
+            signature_hash: format!("{:02x}", i),
+            digest: None,
+            key_id: None,
+            revocation: None,
+            attributes: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quota_holds_under_concurrent_appends() {
+        let dir = std::env::temp_dir().join(format!("qs_server_writer_quota_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        generate_keypair(&dir).unwrap();
+        let path = dir.join("ledger.json");
+        let leaves = index_entries(&[]);
+        let (writer, _task) = LedgerWriter::spawn(
+            path.clone(),
+            ledger::open_store(&path, None).unwrap(),
+            Rotation::default(),
+            Box::new(FileSystemProvider::new(&dir.join("private.key"))),
+            String::new(),
+            leaves.clone(),
+            Some(3),
+            Box::new(|_| {}),
+        );
+
+        let appends: Vec<_> = (0..10)
+            .map(|i| {
+                let writer = writer.clone();
+                tokio::spawn(async move { writer.append(entry(i)).await })
+            })
+            .collect();
+        let mut appended = 0;
+        for task in appends {
+            match task.await.unwrap() {
+                Ok(_) => appended += 1,
+                Err(e) => assert!(e.is::<QuotaExceeded>(), "{}", e),
+            }
+        }
+        assert_eq!(appended, 3);
+        assert_eq!(leaves.read().unwrap().len(), 3);
+
+        // An entry already in the log still gets its receipt, and revocations are always logged.
+        let held = ledger::open_store(&path, None).unwrap().get(0).unwrap().unwrap();
+        assert!(!writer.append(held).await.unwrap().new);
+        let revocation = LedgerEntry {
+            revocation: Some(KeyRevocation {
+                key_id: "aa".repeat(32),
+                reason: "compromised".to_string(),
+                effective_at: "2024-01-01T00:00:00+00:00".to_string(),
+            }),
+            ..entry(10)
+        };
+        assert!(writer.append(revocation).await.unwrap().new);
+        let refused = writer.append(entry(11)).await.err().expect("over quota");
+        assert!(refused.is::<QuotaExceeded>());
+    }
+}
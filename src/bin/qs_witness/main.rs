@@ -0,0 +1,236 @@
+//! qs_witness: independent witness for a qs_server log. Fetches the log's signed tree head, checks
+//! that it is consistent with the last head this witness cosigned, and submits a cosignature. A
+//! log that shows an inconsistent view is reported and never cosigned again.
+
+use clap::Parser;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use qs_notary::key_provider::{self, FileSystemProvider};
+use qs_notary::protocol::{Cosignature, CosignatureBody, SignedTreeHead};
+use qs_notary::tls::ClientTls;
+use qs_notary::{client, crypto};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "qs_witness")]
+#[command(about = "Witness that cosigns consistent tree heads of a qs_server log")]
+struct Args {
+    /// URL of the log server to witness.
+    #[arg(long, env = "QS_WITNESS_LOG_URL")]
+    log_url: String,
+
+    /// The log's public key (its server_public.key), to check its tree heads.
+    #[arg(long, env = "QS_WITNESS_LOG_PUBLIC_KEY")]
+    log_public_key: PathBuf,
+
+    /// Directory for the witness state and fork evidence (default: current directory).
+    #[arg(long, env = "QS_WITNESS_STATE_DIR", default_value = ".")]
+    state_dir: PathBuf,
+
+    /// Witness private key (default: <state-dir>/witness_private.key; generated with
+    /// witness_public.key next to it on first start).
+    #[arg(long, env = "QS_WITNESS_KEY")]
+    key: Option<PathBuf>,
+
+    /// Seconds between rounds.
+    #[arg(long, env = "QS_WITNESS_INTERVAL_SECS", default_value_t = 60)]
+    interval_secs: u64,
+
+    /// Run a single round and exit (e.g. from cron).
+    #[arg(long)]
+    once: bool,
+
+    /// Log filter (tracing env-filter syntax).
+    #[arg(long, env = "QS_LOG_LEVEL", default_value = "info")]
+    log_level: String,
+
+    #[command(flatten)]
+    tls: ClientTls,
+}
+
+/// Last head this witness cosigned, kept in `<state-dir>/witness_state.json`.
+#[derive(Serialize, Deserialize)]
+struct State {
+    tree_head: SignedTreeHead,
+    cosignature: Cosignature,
+    /// False until the log accepted the cosignature; it is resubmitted until then.
+    submitted: bool,
+}
+
+/// Two heads signed by the log that cannot both be true, kept in `<state-dir>/witness_fork.json`.
+#[derive(Serialize, Deserialize)]
+struct ForkEvidence {
+    detected_at: String,
+    reason: String,
+    cosigned: SignedTreeHead,
+    conflicting: SignedTreeHead,
+}
+
+struct Witness {
+    log_url: String,
+    log_public_key: PublicKey,
+    signer: FileSystemProvider,
+    witness_key_id: String,
+    state_path: PathBuf,
+    fork_path: PathBuf,
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Write JSON via a temporary file, so a crash never leaves a partial file.
+fn write_json(path: &Path, value: &impl Serialize) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(value)?)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+impl Witness {
+    /// One round: fetch and check the log's head, cosign it if it is new, submit the cosignature.
+    fn round(&self) -> anyhow::Result<()> {
+        let head = client::tree_head(&self.log_url)?;
+        head.verify(&self.log_public_key)?;
+        let state: Option<State> = read_json(&self.state_path)?;
+        if let Some(ref state) = state {
+            let old = &state.tree_head;
+            if *old == head {
+                if !state.submitted {
+                    self.submit(state)?;
+                }
+                return Ok(());
+            }
+            let (old_size, new_size) = (old.body.tree_size, head.body.tree_size);
+            if new_size < old_size {
+                return self.fork("log shrank below a cosigned tree head", old, head);
+            }
+            if new_size == old_size {
+                if !old.body.root_hash.eq_ignore_ascii_case(&head.body.root_hash) {
+                    return self.fork("two roots for the same tree size", old, head);
+                }
+            } else {
+                let proof = client::consistency(&self.log_url, old_size, new_size)?;
+                if let Err(e) = proof.verify(&old.body, &head.body) {
+                    return self.fork(&e.to_string(), old, head);
+                }
+            }
+        }
+        let body = CosignatureBody {
+            log_key_id: head.body.log_key_id.clone(),
+            tree_size: head.body.tree_size,
+            root_hash: head.body.root_hash.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            witness_key_id: self.witness_key_id.clone(),
+        };
+        let state = State {
+            cosignature: Cosignature::sign(body, &self.signer)?,
+            tree_head: head,
+            submitted: false,
+        };
+        // Saved before submitting: later heads are checked against this one either way.
+        write_json(&self.state_path, &state)?;
+        tracing::info!(tree_size = state.tree_head.body.tree_size, "tree head cosigned");
+        self.submit(&state)
+    }
+
+    fn submit(&self, state: &State) -> anyhow::Result<()> {
+        client::submit_cosignature(&self.log_url, &state.cosignature)?;
+        write_json(
+            &self.state_path,
+            &State {
+                tree_head: state.tree_head.clone(),
+                cosignature: state.cosignature.clone(),
+                submitted: true,
+            },
+        )?;
+        tracing::info!(tree_size = state.tree_head.body.tree_size, "cosignature submitted");
+        Ok(())
+    }
+
+    /// Record the evidence and fail: this witness never cosigns for the log again until the
+    /// evidence is reviewed and removed.
+    fn fork(
+        &self,
+        reason: &str,
+        cosigned: &SignedTreeHead,
+        conflicting: SignedTreeHead,
+    ) -> anyhow::Result<()> {
+        let evidence = ForkEvidence {
+            detected_at: chrono::Utc::now().to_rfc3339(),
+            reason: reason.to_string(),
+            cosigned: cosigned.clone(),
+            conflicting,
+        };
+        write_json(&self.fork_path, &evidence)?;
+        tracing::error!(
+            log = %self.log_url,
+            reason,
+            cosigned_size = evidence.cosigned.body.tree_size,
+            conflicting_size = evidence.conflicting.body.tree_size,
+            evidence = %self.fork_path.display(),
+            "FORK DETECTED: log is inconsistent with a head this witness cosigned"
+        );
+        Err(anyhow::anyhow!("fork detected: {}", reason))
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::try_new(&args.log_level)?)
+        .init();
+
+    client::configure_tls(&args.tls)?;
+    std::fs::create_dir_all(&args.state_dir).map_err(|e| {
+        anyhow::anyhow!("Failed to create state dir {}: {}", args.state_dir.display(), e)
+    })?;
+    let key = args
+        .key
+        .unwrap_or_else(|| args.state_dir.join("witness_private.key"));
+    let public_key = key_provider::public_key_path(&key)
+        .ok_or_else(|| anyhow::anyhow!("Invalid witness key path {}", key.display()))?;
+    if !key.exists() {
+        crypto::write_keypair(&public_key, &key)?;
+        tracing::info!(public_key = %public_key.display(), "generated witness key");
+    }
+    let witness_key_id = crypto::key_id(&crypto::load_public_key(&public_key)?);
+    let witness = Witness {
+        log_url: args.log_url,
+        log_public_key: crypto::load_public_key(&args.log_public_key)?,
+        signer: FileSystemProvider::new(&key),
+        witness_key_id,
+        state_path: args.state_dir.join("witness_state.json"),
+        fork_path: args.state_dir.join("witness_fork.json"),
+    };
+    if witness.fork_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Fork evidence in {}; review it and remove it before cosigning again",
+            witness.fork_path.display()
+        ));
+    }
+    tracing::info!(
+        key_id = %witness.witness_key_id,
+        log = %witness.log_url,
+        "qs_witness started"
+    );
+    loop {
+        match witness.round() {
+            Ok(()) => {}
+            Err(e) if witness.fork_path.exists() => return Err(e),
+            Err(e) if args.once => return Err(e),
+            Err(e) => tracing::warn!(error = %e, "witness round failed"),
+        }
+        if args.once {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_secs(args.interval_secs));
+    }
+}
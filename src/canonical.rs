@@ -0,0 +1,428 @@
+//! Canonical forms of signed content. `jcs` is the JSON Canonicalization Scheme (RFC 8785):
+//! object members sorted by the UTF-16 code units of their names, no insignificant whitespace,
+//! minimal string escaping and numbers serialized like ECMAScript `Number.prototype.toString`, so
+//! re-formatting or re-ordering a JSON document does not change its canonical bytes.
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Number, Value};
+use std::fmt::{self, Write};
+
+/// How the signed bytes are derived from the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Canonicalization {
+    /// RFC 8785 JSON Canonicalization Scheme (JSON documents only).
+    Jcs,
+}
+
+impl Canonicalization {
+    /// Name recorded in the signed attributes.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Canonicalization::Jcs => "jcs",
+        }
+    }
+
+    pub fn from_id(id: &str) -> anyhow::Result<Self> {
+        match id {
+            "jcs" => Ok(Canonicalization::Jcs),
+            _ => Err(anyhow::anyhow!("Unsupported canonicalization {:?}", id)),
+        }
+    }
+
+    /// Canonical form of `bytes`.
+    pub fn apply(&self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Canonicalization::Jcs => Ok(jcs(&parse_strict(bytes)?).into_bytes()),
+        }
+    }
+}
+
+/// JSON value whose objects must not repeat a member name (RFC 8785 section 3.1: a document with
+/// duplicate names has no canonical form).
+struct Strict(Value);
+
+impl<'de> Deserialize<'de> for Strict {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StrictVisitor).map(Strict)
+    }
+}
+
+struct StrictVisitor;
+
+impl<'de> Visitor<'de> for StrictVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Number::from_f64(v)
+            .map(Value::Number)
+            .ok_or_else(|| E::custom("number out of range"))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(Strict(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut members = Map::new();
+        while let Some(name) = map.next_key::<String>()? {
+            let Strict(value) = map.next_value()?;
+            if members.insert(name.clone(), value).is_some() {
+                return Err(de::Error::custom(format!("duplicate member {:?}", name)));
+            }
+        }
+        Ok(Value::Object(members))
+    }
+}
+
+/// Parse a JSON document, rejecting duplicate member names.
+pub(crate) fn parse_strict(bytes: &[u8]) -> anyhow::Result<Value> {
+    let bytes = bytes.strip_prefix("\u{feff}".as_bytes()).unwrap_or(bytes);
+    serde_json::from_slice::<Strict>(bytes)
+        .map(|s| s.0)
+        .map_err(|e| anyhow::anyhow!("Cannot canonicalize: invalid JSON: {}", e))
+}
+
+/// RFC 8785 serialization of `value`.
+pub fn jcs(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(out, n.as_f64().unwrap_or(0.0)),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+        Value::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, name);
+                out.push(':');
+                write_value(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Only `"`, `\` and control characters are escaped, with the short forms where JSON has them.
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// ECMAScript `Number.prototype.toString` for a finite double: the shortest digits that round-trip,
+/// in plain notation for exponents from -7 to 20 and in exponential notation otherwise.
+fn write_number(out: &mut String, v: f64) {
+    if v == 0.0 {
+        out.push('0');
+        return;
+    }
+    if v < 0.0 {
+        out.push('-');
+    }
+    // `{:e}` gives the shortest round-trip digits as `d[.ddd]e<exp>`.
+    let exp_form = format!("{:e}", v.abs());
+    let (mantissa, exp) = exp_form.split_once('e').unwrap_or((&exp_form, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    // Value is 0.<digits> * 10^n.
+    let n = exp.parse::<i32>().unwrap_or(0) + 1;
+    let digits = even_on_tie(digits, n, v.abs());
+    let k = digits.len() as i32;
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        out.push_str(&digits[..n as usize]);
+        out.push('.');
+        out.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat((-n) as usize));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+        if k > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        let _ = write!(out, "e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs());
+    }
+}
+
+/// ECMAScript picks the even last digit when two shortest digit strings are equally close to
+/// `v`; Rust's shortest formatting does not always. `digits` (0.<digits> * 10^n) ends in an odd
+/// digit only in that case if its even neighbour also round-trips and `v` lies exactly halfway.
+fn even_on_tie(digits: String, n: i32, v: f64) -> String {
+    let Some(last) = digits.bytes().last().map(|b| b - b'0') else {
+        return digits;
+    };
+    if last % 2 == 0 {
+        return digits;
+    }
+    // The exact decimal expansion of `v` (a double has fewer than 800 significant digits).
+    let exact = format!("{:.800e}", v);
+    let (mantissa, exp) = exact.split_once('e').unwrap_or((&exact, "0"));
+    if exp.parse::<i32>().ok() != Some(n - 1) {
+        return digits;
+    }
+    let exact: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exact = exact.trim_end_matches('0');
+    let prefix = &digits[..digits.len() - 1];
+    for (neighbour, low) in [(last - 1, last - 1), (last + 1, last)] {
+        if neighbour > 9 || exact != format!("{}{}5", prefix, low) {
+            continue;
+        }
+        let candidate = format!("{}{}", prefix, neighbour);
+        if format!("0.{}e{}", candidate, n).parse::<f64>() == Ok(v) {
+            return candidate;
+        }
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(json: &str) -> String {
+        String::from_utf8(Canonicalization::Jcs.apply(json.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn numbers_match_rfc_8785() {
+        for (input, expected) in [
+            ("1e21", "1e+21"),
+            ("1E-7", "1e-7"),
+            ("-0", "0"),
+            ("333333333.33333329", "333333333.3333333"),
+            ("1E30", "1e+30"),
+            ("4.50", "4.5"),
+            ("2e-3", "0.002"),
+            ("0.000000000000000000000000001", "1e-27"),
+        ] {
+            assert_eq!(canonical(input), expected, "{}", input);
+        }
+        // Appendix B: IEEE 754 bit patterns and their serialization.
+        for (bits, expected) in [
+            (0x0000000000000000, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ] {
+            let mut out = String::new();
+            write_number(&mut out, f64::from_bits(bits));
+            assert_eq!(out, expected, "{:016x}", bits);
+        }
+    }
+
+    #[test]
+    fn strings_and_literals_match_rfc_8785() {
+        // Section 3.2.2.
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        assert_eq!(
+            canonical(input),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+        assert_eq!(canonical(r#""\u0008\u0009\u000c\u001f\u007f""#), "\"\\b\\t\\f\\u001f\u{7f}\"");
+    }
+
+    #[test]
+    fn members_sort_by_utf16_code_units() {
+        // Section 3.2.3: U+1F600 (surrogates D83D DE00) sorts before U+FB33, unlike in UTF-8 or
+        // code point order.
+        let input = r#"{
+            "\u20ac": "Euro Sign",
+            "\r": "Carriage Return",
+            "\ufb33": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\ud83d\ude00": "Emoji: Grinning Face",
+            "\u0080": "Control",
+            "\u00f6": "Latin Small Letter O With Diaeresis"
+        }"#;
+        assert_eq!(
+            canonical(input),
+            concat!(
+                "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",",
+                "\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",",
+                "\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_members_are_rejected() {
+        for input in [
+            r#"{"a": 1, "a": 1}"#,
+            r#"{"outer": {"b": true, "c": null, "b": false}}"#,
+            r#"[{"x": 1}, {"y": 2, "y": 3}]"#,
+        ] {
+            let err = Canonicalization::Jcs.apply(input.as_bytes()).unwrap_err();
+            assert!(err.to_string().contains("duplicate member"), "{}: {}", input, err);
+        }
+        assert_eq!(canonical(r#"[{"a": 1}, {"a": 2}]"#), r#"[{"a":1},{"a":2}]"#);
+    }
+
+    #[test]
+    fn signature_survives_reformatting() {
+        use crate::ledger::Rotation;
+        use crate::sign::{self, Output, SignOptions};
+        use crate::{crypto, key_provider::FileSystemProvider, verify};
+
+        let dir = std::env::temp_dir().join(format!("qs_notary_jcs_roundtrip_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        crypto::generate_keypair(&dir).unwrap();
+        let sbom = dir.join("bom.json");
+        std::fs::write(
+            &sbom,
+            r#"{"bomFormat":"CycloneDX","specVersion":"1.6","version":1,"metadata":{"timestamp":"2024-05-01T12:00:00Z","component":{"type":"application","name":"caf\u00e9","version":"1.0"}},"components":[{"type":"library","name":"lib","version":"2.50"}]}"#,
+        )
+        .unwrap();
+        let opts = SignOptions {
+            ledger_path: &dir.join("ledger.json"),
+            rotation: &Rotation::default(),
+            tsa_url: None,
+            upload: None,
+        };
+        sign::run(
+            &sbom,
+            &FileSystemProvider::new(&dir.join("private.key")),
+            &opts,
+            false,
+            Output::Detached(Some(Canonicalization::Jcs)),
+        )
+        .unwrap();
+        let verify = || {
+            verify::run(
+                &sbom,
+                Some(&sign::sig_path_for(&sbom)),
+                &[],
+                &dir.join("public.key"),
+                None,
+                None,
+                None,
+            )
+        };
+
+        // As `jq .` would print it: indented, escapes decoded; members reordered as well.
+        std::fs::write(
+            &sbom,
+            r#"{
+  "specVersion": "1.6",
+  "bomFormat": "CycloneDX",
+  "version": 1,
+  "components": [
+    {
+      "version": "2.50",
+      "name": "lib",
+      "type": "library"
+    }
+  ],
+  "metadata": {
+    "component": {
+      "version": "1.0",
+      "name": "café",
+      "type": "application"
+    },
+    "timestamp": "2024-05-01T12:00:00Z"
+  }
+}
+"#,
+        )
+        .unwrap();
+        verify().unwrap();
+
+        let edited = std::fs::read_to_string(&sbom).unwrap().replace("2.50", "2.51");
+        std::fs::write(&sbom, edited).unwrap();
+        assert!(verify().is_err());
+    }
+}
@@ -0,0 +1,424 @@
+//! HTTP client for the qs_server transparency log.
+
+use crate::ledger::{self, EntryFilter};
+use crate::protocol::{
+    ConsistencyProof, Cosignature, CosignedTreeHead, CosignedTreeHeads, EntryPage, InclusionProof,
+    IndexedEntry, Receipt, RegistrySnapshot, SignRequest, SignatureEnvelope, SignedTreeHead,
+    SignedUpload, TimestampRequest, TimestampToken,
+};
+use crate::tls::ClientTls;
+use std::io::BufRead;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Per-request timeout, so a hung server cannot stall signing or `sync`.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest silence tolerated on the entry stream; the server sends a keep-alive every 15 seconds.
+const STREAM_READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// Attempts per upload while the server rate-limits it (429 with `Retry-After`).
+const RATE_LIMIT_ATTEMPTS: u32 = 5;
+/// Longest `Retry-After` the client waits for.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// TLS settings for every request of this process, set once by `configure_tls`.
+static TLS_CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
+
+/// Use a private CA and/or a client certificate for `https://` servers from now on. Without this,
+/// `https://` servers are checked against the built-in web roots.
+pub fn configure_tls(tls: &ClientTls) -> anyhow::Result<()> {
+    if let Some(config) = tls.client_config()? {
+        TLS_CONFIG
+            .set(Arc::new(config))
+            .map_err(|_| anyhow::anyhow!("Client TLS is already configured"))?;
+    }
+    Ok(())
+}
+
+/// An agent builder using `tls` for `https://` servers, or the built-in web roots without it.
+fn agent_builder(tls: Option<&Arc<rustls::ClientConfig>>) -> ureq::AgentBuilder {
+    let builder = ureq::AgentBuilder::new();
+    match tls {
+        Some(config) => builder.tls_config(config.clone()),
+        None => builder,
+    }
+}
+
+fn agent() -> ureq::Agent {
+    agent_builder(TLS_CONFIG.get()).timeout(REQUEST_TIMEOUT).build()
+}
+
+/// Agent for the entry stream: bounded connect time and silence, no overall deadline.
+fn stream_agent(tls: Option<&Arc<rustls::ClientConfig>>) -> ureq::Agent {
+    agent_builder(tls)
+        .timeout_connect(REQUEST_TIMEOUT)
+        .timeout_read(STREAM_READ_TIMEOUT)
+        .build()
+}
+
+/// The server refused a request outright (a 4xx status other than 408 or 429): sending the same
+/// request again cannot succeed.
+#[derive(Debug)]
+pub struct Rejected {
+    pub status: u16,
+    message: String,
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Normalize a server URL (no trailing slash).
+pub fn base_url(server_url: &str) -> String {
+    server_url.trim_end_matches('/').to_string()
+}
+
+/// POST one signed ledger entry to `/upload` and return the server's inclusion receipt.
+/// The server does not append entries it already holds; it returns a receipt for the original.
+/// When the server rate-limits the upload, waits as long as its `Retry-After` asks and tries again.
+/// An upload the server refuses outright fails with a [`Rejected`] error. Every attempt carries the
+/// entry's leaf hash as `Idempotency-Key`, so a retry whose first response was lost is not logged twice.
+pub fn upload(server_url: &str, upload: &SignedUpload) -> anyhow::Result<Receipt> {
+    let upload_url = format!("{}/upload", base_url(server_url));
+    let idempotency_key = hex::encode(ledger::leaf_hash(&upload.entry));
+    let mut attempt = 1;
+    let response = loop {
+        let request = agent().post(&upload_url).set("Idempotency-Key", &idempotency_key);
+        match request.send_json(upload) {
+            Err(ureq::Error::Status(429, response)) if attempt < RATE_LIMIT_ATTEMPTS => {
+                let Some(wait) = response
+                    .header("Retry-After")
+                    .and_then(|v| v.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                else {
+                    // 429 without Retry-After is a quota, not a rate limit: waiting will not help.
+                    break Err(ureq::Error::Status(429, response));
+                };
+                std::thread::sleep(wait.min(MAX_RETRY_AFTER));
+                attempt += 1;
+            }
+            result => break result,
+        }
+    };
+    response
+        .map_err(|e| {
+            let message = format!("Upload to {} failed: {}", upload_url, e);
+            match e {
+                ureq::Error::Status(status, _)
+                    if (400..500).contains(&status) && status != 408 && status != 429 =>
+                {
+                    anyhow::Error::new(Rejected { status, message })
+                }
+                _ => anyhow::anyhow!(message),
+            }
+        })?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid receipt from {}: {}", upload_url, e))
+}
+
+/// POST a digest to a timestamp authority's `/timestamp` and return the signed token.
+pub fn timestamp(tsa_url: &str, digest: &[u8]) -> anyhow::Result<TimestampToken> {
+    let url = format!("{}/timestamp", base_url(tsa_url));
+    agent()
+        .post(&url)
+        .send_json(TimestampRequest {
+            digest: hex::encode(digest),
+        })
+        .map_err(|e| anyhow::anyhow!("Timestamp request to {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid timestamp token from {}: {}", url, e))
+}
+
+/// POST an artifact digest to a signing service's `/sign`; returns the envelope for the logged entry.
+pub fn sign(
+    server_url: &str,
+    api_token: &str,
+    request: &SignRequest,
+) -> anyhow::Result<SignatureEnvelope> {
+    let url = format!("{}/sign", base_url(server_url));
+    agent()
+        .post(&url)
+        .set("Authorization", &format!("Bearer {}", api_token))
+        .send_json(request)
+        .map_err(|e| anyhow::anyhow!("Remote signing at {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid response from {}: {}", url, e))
+}
+
+/// Ask the server whether it holds the entry with this leaf hash (`GET /entries/by-leaf/{hex}`).
+pub fn has_entry(server_url: &str, leaf_hash: &str) -> anyhow::Result<bool> {
+    let url = format!("{}/entries/by-leaf/{}", base_url(server_url), leaf_hash);
+    match agent().get(&url).call() {
+        Ok(_) => Ok(true),
+        Err(ureq::Error::Status(404, _)) => Ok(false),
+        Err(e) => Err(anyhow::anyhow!("Lookup at {} failed: {}", url, e)),
+    }
+}
+
+/// `GET /entries` with the given filter and pagination.
+pub fn search(server_url: &str, filter: &EntryFilter) -> anyhow::Result<EntryPage> {
+    let url = format!("{}/entries", base_url(server_url));
+    let mut req = agent().get(&url);
+    let fields = [
+        ("file_name", filter.file_name.clone()),
+        ("key_id", filter.key_id.clone()),
+        ("digest", filter.digest.clone()),
+        ("since", filter.since.clone()),
+        ("until", filter.until.clone()),
+        ("from_index", filter.from_index.map(|i| i.to_string())),
+        ("offset", Some(filter.offset.to_string())),
+        ("limit", filter.limit.map(|l| l.to_string())),
+    ];
+    for (name, value) in fields {
+        if let Some(v) = value {
+            req = req.query(name, &v);
+        }
+    }
+    let resp = req
+        .call()
+        .map_err(|e| anyhow::anyhow!("Search at {} failed: {}", url, e))?;
+    resp.into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid response from {}: {}", url, e))
+}
+
+/// `GET /entries/{index}`; `None` if the log has no such entry.
+pub fn entry(server_url: &str, index: u64) -> anyhow::Result<Option<IndexedEntry>> {
+    let url = format!("{}/entries/{}", base_url(server_url), index);
+    match agent().get(&url).call() {
+        Ok(resp) => resp
+            .into_json()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid response from {}: {}", url, e)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Fetch at {} failed: {}", url, e)),
+    }
+}
+
+/// `GET /keys`: the server's key registry, including revocations.
+pub fn registry(server_url: &str) -> anyhow::Result<RegistrySnapshot> {
+    let url = format!("{}/keys", base_url(server_url));
+    agent()
+        .get(&url)
+        .call()
+        .map_err(|e| anyhow::anyhow!("Fetching key registry from {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid response from {}: {}", url, e))
+}
+
+/// `GET /tree-head`: the log's current signed tree head.
+pub fn tree_head(server_url: &str) -> anyhow::Result<SignedTreeHead> {
+    let url = format!("{}/tree-head", base_url(server_url));
+    agent()
+        .get(&url)
+        .call()
+        .map_err(|e| anyhow::anyhow!("Fetching tree head from {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid tree head from {}: {}", url, e))
+}
+
+/// `GET /tree-head/consistency`: proof that the tree of `first` entries is a prefix of the tree of
+/// `second` entries.
+pub fn consistency(server_url: &str, first: u64, second: u64) -> anyhow::Result<ConsistencyProof> {
+    let url = format!("{}/tree-head/consistency", base_url(server_url));
+    agent()
+        .get(&url)
+        .query("first", &first.to_string())
+        .query("second", &second.to_string())
+        .call()
+        .map_err(|e| anyhow::anyhow!("Fetching consistency proof from {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid consistency proof from {}: {}", url, e))
+}
+
+/// `GET /entries/{index}/proof`: inclusion proof for entry `index` in the tree of `tree_size` entries.
+pub fn inclusion_proof(server_url: &str, index: u64, tree_size: u64) -> anyhow::Result<InclusionProof> {
+    let url = format!("{}/entries/{}/proof", base_url(server_url), index);
+    agent()
+        .get(&url)
+        .query("tree_size", &tree_size.to_string())
+        .call()
+        .map_err(|e| anyhow::anyhow!("Fetching inclusion proof from {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid inclusion proof from {}: {}", url, e))
+}
+
+/// `GET /tree-head/cosigned`: recently cosigned tree heads, largest first.
+pub fn cosigned_tree_heads(server_url: &str) -> anyhow::Result<Vec<CosignedTreeHead>> {
+    let url = format!("{}/tree-head/cosigned", base_url(server_url));
+    let heads: CosignedTreeHeads = agent()
+        .get(&url)
+        .call()
+        .map_err(|e| anyhow::anyhow!("Fetching cosigned tree heads from {} failed: {}", url, e))?
+        .into_json()
+        .map_err(|e| anyhow::anyhow!("Invalid response from {}: {}", url, e))?;
+    Ok(heads.tree_heads)
+}
+
+/// POST a witness cosignature to `/tree-head/cosignatures`.
+pub fn submit_cosignature(server_url: &str, cosignature: &Cosignature) -> anyhow::Result<()> {
+    let url = format!("{}/tree-head/cosignatures", base_url(server_url));
+    agent()
+        .post(&url)
+        .send_json(cosignature)
+        .map_err(|e| anyhow::anyhow!("Submitting cosignature to {} failed: {}", url, e))?;
+    Ok(())
+}
+
+/// Follow `GET /entries/stream`, calling `on_entry` for each entry in log order. With `after`,
+/// entries after that index are replayed first. Returns when the server closes the stream; a
+/// dropped or silent connection is an error. Errors from `on_entry` stop the stream.
+pub fn watch(
+    server_url: &str,
+    key_id: Option<&str>,
+    after: Option<u64>,
+    on_entry: impl FnMut(IndexedEntry) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    watch_with(&stream_agent(TLS_CONFIG.get()), server_url, key_id, after, on_entry)
+}
+
+fn watch_with(
+    agent: &ureq::Agent,
+    server_url: &str,
+    key_id: Option<&str>,
+    after: Option<u64>,
+    mut on_entry: impl FnMut(IndexedEntry) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let url = format!("{}/entries/stream", base_url(server_url));
+    let mut req = agent.get(&url).set("Accept", "text/event-stream");
+    if let Some(key_id) = key_id {
+        req = req.query("key_id", key_id);
+    }
+    if let Some(after) = after {
+        req = req.query("after", &after.to_string());
+    }
+    let resp = req
+        .call()
+        .map_err(|e| anyhow::anyhow!("Connecting to {} failed: {}", url, e))?;
+    // Server-sent events: `field: value` lines, a blank line ends an event, `:` lines are comments.
+    let mut event = String::new();
+    let mut data = String::new();
+    for line in std::io::BufReader::new(resp.into_reader()).lines() {
+        let line = line.map_err(|e| anyhow::anyhow!("Stream from {} broke: {}", url, e))?;
+        if line.is_empty() {
+            if event == "entry" && !data.is_empty() {
+                let entry = serde_json::from_str(&data)
+                    .map_err(|e| anyhow::anyhow!("Invalid entry from {}: {}", url, e))?;
+                on_entry(entry)?;
+            }
+            event.clear();
+            data.clear();
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((&line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = value.to_string(),
+            "data" => {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, StubLog, TestCa};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn upload_carries_the_leaf_hash_as_idempotency_key() {
+        let log = Arc::new(StubLog::default());
+        let url = testing::serve(log.clone());
+        let key = testing::key(&testing::scratch_dir("client_idempotency"));
+        let signed = SignedUpload::sign(testing::entry("a.json"), &key).unwrap();
+        let leaf = hex::encode(ledger::leaf_hash(&signed.entry));
+
+        let receipt = upload(&url, &signed).unwrap();
+        assert_eq!(receipt.body.leaf_hash, leaf);
+        upload(&url, &signed).unwrap();
+        assert_eq!(*log.idempotency_keys.lock().unwrap(), [Some(leaf.clone()), Some(leaf)]);
+    }
+
+    #[test]
+    fn refusals_are_rejected_but_server_errors_are_not() {
+        let log = Arc::new(StubLog::default());
+        log.refuse("a.json", axum::http::StatusCode::FORBIDDEN);
+        log.refuse("b.json", axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        let url = testing::serve(log);
+        let key = testing::key(&testing::scratch_dir("client_rejected"));
+        let refused = |name| upload(&url, &SignedUpload::sign(testing::entry(name), &key).unwrap()).unwrap_err();
+
+        let e = refused("a.json");
+        assert_eq!(e.downcast_ref::<Rejected>().unwrap().status, 403);
+        assert!(!refused("b.json").is::<Rejected>());
+    }
+
+    /// A stub log holding one entry.
+    fn log_with_entry() -> Arc<StubLog> {
+        let log = Arc::new(StubLog::default());
+        log.received.lock().unwrap().push(SignedUpload {
+            entry: testing::entry("a.json"),
+            key_id: String::new(),
+            signature: String::new(),
+        });
+        log
+    }
+
+    fn client_tls(ca: &Path, cert: Option<&(PathBuf, PathBuf)>) -> Arc<rustls::ClientConfig> {
+        let tls = ClientTls {
+            ca_cert: Some(ca.to_path_buf()),
+            client_cert: cert.map(|(cert, _)| cert.clone()),
+            client_key: cert.map(|(_, key)| key.clone()),
+        };
+        Arc::new(tls.client_config().unwrap().unwrap())
+    }
+
+    fn watched(tls: Option<&Arc<rustls::ClientConfig>>, url: &str) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        watch_with(&stream_agent(tls), url, None, None, |e| {
+            names.push(e.entry.file_name);
+            Ok(())
+        })?;
+        Ok(names)
+    }
+
+    #[test]
+    fn watch_over_https_trusts_the_configured_ca_only() {
+        let dir = testing::scratch_dir("client_https");
+        let ca = TestCa::new(&dir, "server-ca");
+        let other = TestCa::new(&dir, "other-ca");
+        let (cert, key) = ca.issue("server");
+        let url = testing::serve_tls(log_with_entry(), &cert, &key, None);
+
+        assert_eq!(watched(Some(&client_tls(&ca.cert_path, None)), &url).unwrap(), ["a.json"]);
+        assert!(watched(Some(&client_tls(&other.cert_path, None)), &url).is_err());
+        // The built-in web roots do not include a private CA.
+        assert!(watched(None, &url).is_err());
+    }
+
+    #[test]
+    fn watch_over_mutual_tls_needs_a_client_certificate_from_the_client_ca() {
+        let dir = testing::scratch_dir("client_mtls");
+        let server_ca = TestCa::new(&dir, "server-ca");
+        let client_ca = TestCa::new(&dir, "client-ca");
+        let other = TestCa::new(&dir, "other-ca");
+        let (cert, key) = server_ca.issue("server");
+        let url = testing::serve_tls(log_with_entry(), &cert, &key, Some(&client_ca.cert_path));
+
+        let client = client_ca.issue("client");
+        let tls = client_tls(&server_ca.cert_path, Some(&client));
+        assert_eq!(watched(Some(&tls), &url).unwrap(), ["a.json"]);
+        assert!(watched(Some(&client_tls(&server_ca.cert_path, None)), &url).is_err());
+        let stranger = other.issue("stranger");
+        assert!(watched(Some(&client_tls(&server_ca.cert_path, Some(&stranger))), &url).is_err());
+    }
+}
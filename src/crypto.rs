@@ -4,16 +4,20 @@ use pqcrypto_dilithium::dilithium5::{
     detached_sign, keypair, verify_detached_signature, DetachedSignature, PublicKey, SecretKey,
 };
 use pqcrypto_traits::sign::{DetachedSignature as DetachedSignatureTrait, PublicKey as PublicKeyTrait, SecretKey as SecretKeyTrait};
+use sha3::{Digest, Sha3_256};
 use std::path::Path;
 
 /// Generate a Dilithium5 keypair and save to `public.key` and `private.key` in the given directory.
 pub fn generate_keypair(out_dir: &Path) -> anyhow::Result<()> {
+    write_keypair(&out_dir.join("public.key"), &out_dir.join("private.key"))
+}
+
+/// Generate a Dilithium5 keypair and save it to the given paths.
+pub fn write_keypair(public_path: &Path, private_path: &Path) -> anyhow::Result<()> {
     let (pk, sk) = keypair();
-    let public_path = out_dir.join("public.key");
-    let private_path = out_dir.join("private.key");
-    std::fs::write(&public_path, pk.as_bytes())
+    std::fs::write(public_path, pk.as_bytes())
         .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", public_path.display(), e))?;
-    std::fs::write(&private_path, sk.as_bytes())
+    std::fs::write(private_path, sk.as_bytes())
         .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", private_path.display(), e))?;
     Ok(())
 }
@@ -49,3 +53,8 @@ pub fn verify_signature(sig: &DetachedSignature, hash: &[u8], pk: &PublicKey) ->
     verify_detached_signature(sig, hash, pk)
         .map_err(|e| anyhow::anyhow!("Verification failed: {:?}", e))
 }
+
+/// Key id: hex-encoded SHA3-256 of the raw public key bytes.
+pub fn key_id(pk: &PublicKey) -> String {
+    hex::encode(Sha3_256::digest(pk.as_bytes()))
+}
@@ -0,0 +1,212 @@
+//! Embedded CycloneDX signatures in JSON Signature Format (JSF): a top-level `signature` object
+//! `{ "algorithm", "keyId", "value" }` in the BOM itself. The signed data is the RFC 8785
+//! canonical form of the whole BOM with the signature object in place but without its `value`.
+
+use crate::canonical::{jcs, parse_strict};
+use anyhow::anyhow;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use sha3::{Digest, Sha3_256};
+
+/// JSF algorithm identifier: Dilithium5 over the SHA3-256 of the canonical BOM. JSF has no
+/// registered name for post-quantum algorithms, and CycloneDX accepts a URI in their place.
+pub const ALGORITHM: &str = "urn:qs-notary:alg:dilithium5-sha3-256";
+
+/// The signature object to embed, without `value`, and the digest to sign for it.
+pub struct Template {
+    pub signature: Map<String, Value>,
+    pub digest: [u8; 32],
+}
+
+/// A signature found in a BOM, with the digest it must be over.
+pub struct Embedded {
+    pub signature: Vec<u8>,
+    pub key_id: Option<String>,
+    pub digest: [u8; 32],
+}
+
+fn root_object(bytes: &[u8]) -> anyhow::Result<Map<String, Value>> {
+    match parse_strict(bytes)? {
+        Value::Object(members) => Ok(members),
+        _ => Err(anyhow!("SBOM root must be a JSON object")),
+    }
+}
+
+/// SHA3-256 of the canonical BOM with `signature` (which has no `value`) as its signature.
+fn signed_digest(mut bom: Map<String, Value>, signature: &Map<String, Value>) -> [u8; 32] {
+    bom.insert("signature".to_string(), Value::Object(signature.clone()));
+    Sha3_256::digest(jcs(&Value::Object(bom))).into()
+}
+
+/// Prepare to sign the BOM `bytes` with the key `key_id` (recorded as `keyId` when known).
+pub fn template(bytes: &[u8], key_id: Option<String>) -> anyhow::Result<Template> {
+    let bom = root_object(bytes)?;
+    if bom.contains_key("signature") {
+        return Err(anyhow!("SBOM already has an embedded signature"));
+    }
+    let mut signature = Map::new();
+    signature.insert("algorithm".to_string(), json!(ALGORITHM));
+    if let Some(key_id) = key_id {
+        signature.insert("keyId".to_string(), json!(key_id));
+    }
+    let digest = signed_digest(bom, &signature);
+    Ok(Template { signature, digest })
+}
+
+/// The BOM `bytes` with the signature object (the template's plus `value`) added as its last
+/// member. The rest of the document is kept byte for byte.
+pub fn embed(bytes: &[u8], mut signature: Map<String, Value>, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+    signature.insert("value".to_string(), json!(URL_SAFE_NO_PAD.encode(value)));
+    let text = std::str::from_utf8(bytes).map_err(|e| anyhow!("SBOM is not UTF-8: {}", e))?;
+    let end = text.trim_end().len();
+    let body = text[..end]
+        .strip_suffix('}')
+        .ok_or_else(|| anyhow!("SBOM root must be a JSON object"))?
+        .trim_end();
+    let separator = if body.ends_with('{') { "" } else { "," };
+    // Pretty-printed documents get the member on its own line with the indentation of the first.
+    let indent = text
+        .split_once('{')
+        .and_then(|(_, rest)| rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")))
+        .map(|rest| &rest[..rest.len() - rest.trim_start_matches([' ', '\t']).len()]);
+    let member = serde_json::to_string(&Value::Object(signature))?;
+    let out = match indent {
+        Some(indent) => format!("{}{}\n{}\"signature\": {}\n}}{}", body, separator, indent, member, &text[end..]),
+        None => format!("{}{}\"signature\":{}}}{}", body, separator, member, &text[end..]),
+    };
+    Ok(out.into_bytes())
+}
+
+/// Take the embedded signature out of the BOM `bytes` and compute the digest it must be over.
+pub fn extract(bytes: &[u8]) -> anyhow::Result<Embedded> {
+    let mut bom = root_object(bytes)?;
+    let Some(Value::Object(mut signature)) = bom.remove("signature") else {
+        return Err(anyhow!("SBOM has no embedded signature object"));
+    };
+    let algorithm = signature.get("algorithm").and_then(Value::as_str);
+    if algorithm != Some(ALGORITHM) {
+        return Err(anyhow!(
+            "Embedded signature algorithm {} is not {}",
+            algorithm.unwrap_or("(none)"),
+            ALGORITHM
+        ));
+    }
+    let value = match signature.remove("value") {
+        Some(Value::String(value)) => URL_SAFE_NO_PAD
+            .decode(value.trim_end_matches('='))
+            .map_err(|e| anyhow!("Invalid embedded signature value: {}", e))?,
+        _ => return Err(anyhow!("Embedded signature has no value")),
+    };
+    let key_id = signature.get("keyId").and_then(Value::as_str).map(str::to_string);
+    Ok(Embedded {
+        signature: value,
+        key_id,
+        digest: signed_digest(bom, &signature),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRETTY: &str = "{\n  \"bomFormat\": \"CycloneDX\",\n  \"specVersion\": \"1.6\",\n  \"version\": 1,\n  \"components\": [\n    {\n      \"type\": \"library\",\n      \"name\": \"lib\"\n    }\n  ]\n}\n";
+    const MINIFIED: &str = r#"{"bomFormat":"CycloneDX","specVersion":"1.6","version":1,"components":[{"type":"library","name":"lib"}]}"#;
+    const LAST_MEMBER_OBJECT: &str = r#"{"bomFormat":"CycloneDX","specVersion":"1.6","metadata":{"component":{"type":"application","name":"app","properties":{}}}}"#;
+
+    /// Embed a signature into `bom` and take it out again.
+    fn round_trip(bom: &str) -> (String, Embedded, [u8; 32]) {
+        let template = template(bom.as_bytes(), Some("ab".repeat(32))).unwrap();
+        let signed = embed(bom.as_bytes(), template.signature, b"signature bytes").unwrap();
+        let signed = String::from_utf8(signed).unwrap();
+        let embedded = extract(signed.as_bytes()).unwrap();
+        (signed, embedded, template.digest)
+    }
+
+    #[test]
+    fn embed_and_extract_round_trip() {
+        for bom in [PRETTY, MINIFIED, LAST_MEMBER_OBJECT, "{}", "{\r\n  \"version\": 1\r\n}\r\n"] {
+            let (signed, embedded, digest) = round_trip(bom);
+            assert_eq!(embedded.signature, b"signature bytes");
+            assert_eq!(embedded.key_id, Some("ab".repeat(32)));
+            assert_eq!(embedded.digest, digest, "{}", signed);
+            // The signature is the last member; the other members are the original document's.
+            let tail = &signed[signed.rfind("\"signature\"").unwrap()..];
+            let member = tail[tail.find('{').unwrap()..].trim_end().strip_suffix('}').unwrap();
+            assert!(serde_json::from_str::<Value>(member).unwrap().is_object(), "{}", signed);
+            let mut parsed = root_object(signed.as_bytes()).unwrap();
+            parsed.remove("signature");
+            assert_eq!(parsed, root_object(bom.as_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn embedding_keeps_the_layout() {
+        let (signed, _, _) = round_trip(PRETTY);
+        let (head, signature) = signed.split_once("\n  \"signature\": ").unwrap();
+        assert_eq!(format!("{}\n}}\n", head.strip_suffix(',').unwrap()), PRETTY);
+        assert!(signature.ends_with("}\n}\n"));
+
+        let (signed, _, _) = round_trip(MINIFIED);
+        assert!(signed.starts_with(MINIFIED.strip_suffix('}').unwrap()));
+        assert!(!signed.contains(['\n', ' ']));
+    }
+
+    #[test]
+    fn bom_with_signature_is_rejected() {
+        let (signed, _, _) = round_trip(MINIFIED);
+        let err = template(signed.as_bytes(), None).err().unwrap();
+        assert!(err.to_string().contains("already has an embedded signature"), "{}", err);
+        assert!(template(br#"{"signature":null}"#, None).is_err());
+    }
+
+    #[test]
+    fn edited_bom_fails_embedded_verification() {
+        use crate::ledger::Rotation;
+        use crate::sign::{self, Output, SignOptions};
+        use crate::{crypto, key_provider::FileSystemProvider, verify};
+
+        let dir = std::env::temp_dir().join(format!("qs_notary_jsf_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        crypto::generate_keypair(&dir).unwrap();
+        let sbom = dir.join("bom.json");
+        std::fs::write(&sbom, PRETTY).unwrap();
+        let opts = SignOptions {
+            ledger_path: &dir.join("ledger.json"),
+            rotation: &Rotation::default(),
+            tsa_url: None,
+            upload: None,
+        };
+        let provider = FileSystemProvider::new(&dir.join("private.key"));
+        sign::run(&sbom, &provider, &opts, false, Output::Embedded).unwrap();
+        let verify = || verify::run(&sbom, None, &[], &dir.join("public.key"), None, None, None);
+        verify().unwrap();
+        let signed = std::fs::read_to_string(&sbom).unwrap();
+
+        // Signing the signed BOM again is refused.
+        assert!(sign::run(&sbom, &provider, &opts, false, Output::Embedded).is_err());
+
+        let edits = [
+            ("\"specVersion\": \"1.6\"", "\"specVersion\": \"1.5\""),
+            ("\"version\": 1", "\"version\": 2"),
+            ("\"name\": \"lib\"", "\"name\": \"lib2\""),
+            ("\"type\": \"library\",", "\"type\": \"library\", \"scope\": \"optional\","),
+        ];
+        for (from, to) in edits {
+            let edited = signed.replacen(from, to, 1);
+            assert_ne!(edited, signed);
+            std::fs::write(&sbom, &edited).unwrap();
+            assert!(verify().is_err(), "{} -> {} still verifies", from, to);
+        }
+        let mut doc: Value = serde_json::from_str(&signed).unwrap();
+        doc["signature"]["keyId"] = json!("cd".repeat(32));
+        std::fs::write(&sbom, serde_json::to_vec_pretty(&doc).unwrap()).unwrap();
+        assert!(verify().is_err());
+
+        // Re-formatting alone keeps it valid: the signed data is the canonical form.
+        let doc: Value = serde_json::from_str(&signed).unwrap();
+        std::fs::write(&sbom, serde_json::to_vec(&doc).unwrap()).unwrap();
+        verify().unwrap();
+    }
+}
@@ -1,14 +1,43 @@
-//! Key provider abstraction: local filesystem vs mock KMS.
+//! Key provider abstraction: local filesystem, mock KMS or a remote signing service.
 
-use crate::crypto::{load_secret_key, sign_hash};
-use pqcrypto_dilithium::dilithium5::{keypair, SecretKey};
+use crate::client;
+use crate::crypto::{key_id, load_public_key, load_secret_key, sign_hash};
+use crate::ledger::LedgerEntry;
+use crate::protocol::{artifact_message, Receipt, SignRequest, SignedAttributes};
+use pqcrypto_dilithium::dilithium5::{keypair, PublicKey, SecretKey};
 use std::path::Path;
 use std::sync::OnceLock;
 use std::time::Duration;
 
-/// Provider of signing capability (local key file or mock remote KMS).
+/// Signature over an artifact digest; `logged` is set when the provider logged the entry itself.
+pub struct ArtifactSignature {
+    pub signature: Vec<u8>,
+    pub logged: Option<(LedgerEntry, Receipt)>,
+}
+
+/// Provider of signing capability (local key file, mock remote KMS or signing service).
 pub trait KeyProvider {
     fn sign(&self, data: &[u8]) -> anyhow::Result<Vec<u8>>;
+
+    /// Key id of the signing key (see `crypto::key_id`), if the matching public key is known.
+    fn key_id(&self) -> Option<String> {
+        None
+    }
+
+    /// Sign the SHA3-256 digest of the artifact `file_name`, with `attributes` if given (see
+    /// `protocol::artifact_message`). Signing services log the entry as part of signing and return
+    /// it with the log's receipt.
+    fn sign_artifact(
+        &self,
+        digest: &[u8],
+        _file_name: &str,
+        attributes: Option<&SignedAttributes>,
+    ) -> anyhow::Result<ArtifactSignature> {
+        Ok(ArtifactSignature {
+            signature: self.sign(&artifact_message(digest, attributes)?)?,
+            logged: None,
+        })
+    }
 }
 
 /// Signs using a private key loaded from the filesystem (current default behavior).
@@ -29,17 +58,28 @@ impl KeyProvider for FileSystemProvider {
         let sk = load_secret_key(&self.private_key_path)?;
         Ok(sign_hash(data, &sk))
     }
+
+    /// Derived from the matching public key next to the private key, if present (see `public_key_path`).
+    fn key_id(&self) -> Option<String> {
+        load_public_key(&public_key_path(&self.private_key_path)?)
+            .ok()
+            .map(|pk| key_id(&pk))
+    }
+}
+
+/// Where the public key matching a private key file lives: the same directory, with `private` in
+/// the file name replaced by `public` (`private.key` -> `public.key`, as written by `generate-keys`).
+pub fn public_key_path(private_key_path: &Path) -> Option<std::path::PathBuf> {
+    let name = private_key_path.file_name()?.to_str()?.replace("private", "public");
+    Some(private_key_path.with_file_name(name))
 }
 
 /// Fixed in-memory key for testing. Simulates a remote KMS with a 100ms delay.
 /// Use the matching public key (e.g. from a one-time export of this mock) for verification.
-static MOCK_KMS_KEY: OnceLock<SecretKey> = OnceLock::new();
+static MOCK_KMS_KEY: OnceLock<(PublicKey, SecretKey)> = OnceLock::new();
 
-fn mock_kms_secret_key() -> &'static SecretKey {
-    MOCK_KMS_KEY.get_or_init(|| {
-        let (_pk, sk) = keypair();
-        sk
-    })
+fn mock_kms_keypair() -> &'static (PublicKey, SecretKey) {
+    MOCK_KMS_KEY.get_or_init(keypair)
 }
 
 /// Mock KMS: same in-memory key every time, 100ms delay to simulate network.
@@ -54,9 +94,13 @@ impl MockKmsProvider {
 impl KeyProvider for MockKmsProvider {
     fn sign(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
         std::thread::sleep(Duration::from_millis(100));
-        let sk = mock_kms_secret_key();
+        let (_pk, sk) = mock_kms_keypair();
         Ok(sign_hash(data, sk))
     }
+
+    fn key_id(&self) -> Option<String> {
+        Some(key_id(&mock_kms_keypair().0))
+    }
 }
 
 impl Default for MockKmsProvider {
@@ -64,3 +108,154 @@ impl Default for MockKmsProvider {
         Self::new()
     }
 }
+
+/// Key held by a qs_server signing service (`POST /sign`), addressed as
+/// `remote://<host[:port]>/<key name>` (`remotes://` for HTTPS). Requests carry the team's API
+/// token.
+pub struct RemoteProvider {
+    server_url: String,
+    key_name: String,
+    api_token: String,
+}
+
+impl RemoteProvider {
+    pub fn from_uri(uri: &str, api_token: String) -> anyhow::Result<Self> {
+        let (scheme, rest) = match uri.strip_prefix("remotes://") {
+            Some(rest) => ("https", rest),
+            None => (
+                "http",
+                uri.strip_prefix("remote://").ok_or_else(|| {
+                    anyhow::anyhow!("Remote key must look like remote://server/keyname")
+                })?,
+            ),
+        };
+        let (server, key_name) = rest
+            .split_once('/')
+            .filter(|(server, name)| !server.is_empty() && !name.is_empty() && !name.contains('/'))
+            .ok_or_else(|| anyhow::anyhow!("Remote key must look like remote://server/keyname"))?;
+        Ok(Self {
+            server_url: format!("{}://{}", scheme, server),
+            key_name: key_name.to_string(),
+            api_token,
+        })
+    }
+}
+
+impl KeyProvider for RemoteProvider {
+    /// The service only signs artifact digests it logs, so uploads, ledger seals and DSSE
+    /// attestations need a local key.
+    fn sign(&self, _data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Err(anyhow::anyhow!(
+            "Remote key {} can only sign artifact digests (uploads, ledger seals and DSSE attestations need a local key)",
+            self.key_name
+        ))
+    }
+
+    fn sign_artifact(
+        &self,
+        digest: &[u8],
+        file_name: &str,
+        attributes: Option<&SignedAttributes>,
+    ) -> anyhow::Result<ArtifactSignature> {
+        let request = SignRequest {
+            key: self.key_name.clone(),
+            digest: hex::encode(digest),
+            file_name: file_name.to_string(),
+            attributes: attributes.cloned(),
+        };
+        let envelope = client::sign(&self.server_url, &self.api_token, &request)?;
+        let (Some(entry), Some(receipt)) = (envelope.log_entry, envelope.receipt) else {
+            return Err(anyhow::anyhow!("Signing service returned no log entry and receipt"));
+        };
+        if entry.signature_hash != envelope.signature
+            || !entry
+                .digest
+                .as_deref()
+                .is_some_and(|d| d.eq_ignore_ascii_case(&request.digest))
+            || entry.attributes != request.attributes
+        {
+            return Err(anyhow::anyhow!(
+                "Signing service logged an entry for a different signature, digest or attributes"
+            ));
+        }
+        let signature = hex::decode(&envelope.signature)
+            .map_err(|e| anyhow::anyhow!("Invalid signature hex from signing service: {}", e))?;
+        Ok(ArtifactSignature {
+            signature,
+            logged: Some((entry, receipt)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ReceiptBody, SignatureEnvelope};
+    use crate::testing::{self, StubLog};
+    use sha3::{Digest, Sha3_256};
+    use std::sync::Arc;
+
+    fn attributes() -> SignedAttributes {
+        SignedAttributes {
+            sbom_format: "CycloneDX".to_string(),
+            spec_version: "1.6".to_string(),
+            encoding: "json".to_string(),
+            canonicalization: None,
+        }
+    }
+
+    /// What a signing service answers for `digest` with `attributes`: the signature and its
+    /// logged entry with a receipt.
+    fn signed(digest: &[u8], attributes: Option<SignedAttributes>) -> SignatureEnvelope {
+        let entry = LedgerEntry {
+            digest: Some(hex::encode(digest)),
+            signature_hash: "ab".repeat(8),
+            attributes,
+            ..testing::entry("bom.json")
+        };
+        SignatureEnvelope {
+            signature: entry.signature_hash.clone(),
+            receipt: Some(Receipt {
+                body: ReceiptBody {
+                    log_index: 0,
+                    integrated_time: entry.timestamp.clone(),
+                    leaf_hash: hex::encode(crate::ledger::leaf_hash(&entry)),
+                    log_key_id: String::new(),
+                },
+                signature: String::new(),
+            }),
+            log_entry: Some(entry),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn remote_signature_must_be_the_logged_one() {
+        let log = Arc::new(StubLog::default());
+        let url = testing::serve(log.clone());
+        let uri = format!("{}/release", url.replace("http://", "remote://"));
+        let provider = RemoteProvider::from_uri(&uri, "token".to_string()).unwrap();
+        let digest = Sha3_256::digest(b"bom");
+        let sign = || provider.sign_artifact(&digest, "bom.json", Some(&attributes()));
+
+        *log.signed.lock().unwrap() = Some(signed(&digest, Some(attributes())));
+        let signature = sign().unwrap();
+        assert_eq!(signature.signature, hex::decode("ab".repeat(8)).unwrap());
+        assert!(signature.logged.is_some());
+
+        let mut other_signature = signed(&digest, Some(attributes()));
+        other_signature.signature = "cd".repeat(8);
+        let other_digest = signed(&Sha3_256::digest(b"other"), Some(attributes()));
+        let no_attributes = signed(&digest, None);
+        for envelope in [other_signature, other_digest, no_attributes] {
+            *log.signed.lock().unwrap() = Some(envelope);
+            let err = sign().err().unwrap().to_string();
+            assert!(err.contains("logged an entry for a different signature"), "{}", err);
+        }
+        let mut unlogged = signed(&digest, Some(attributes()));
+        unlogged.receipt = None;
+        *log.signed.lock().unwrap() = Some(unlogged);
+        let err = sign().err().unwrap().to_string();
+        assert!(err.contains("returned no log entry and receipt"), "{}", err);
+    }
+}
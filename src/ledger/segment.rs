@@ -0,0 +1,477 @@
+//! Segment rotation for JSONL ledgers: the active file is sealed with a signed trailer and archived
+//! as `<ledger>.000001`, `<ledger>.000002`, ...; the new active file starts with a header that
+//! references the previous seal, so the hash chain continues across segments.
+
+use super::{leaf_hash, LedgerEntry};
+use crate::crypto::{key_id, load_signature, verify_signature};
+use crate::key_provider::KeyProvider;
+use crate::protocol::seal_message;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// When to seal the active segment. Both limits may be set; either one triggers rotation.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct Rotation {
+    /// Seal and rotate the ledger once the active segment reaches this many bytes.
+    #[arg(long)]
+    pub rotate_max_bytes: Option<u64>,
+
+    /// Seal and rotate the ledger once the oldest entry in the active segment is this many days old.
+    #[arg(long)]
+    pub rotate_max_days: Option<u32>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.rotate_max_bytes.is_some() || self.rotate_max_days.is_some()
+    }
+}
+
+/// Signed part of a segment trailer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SealBody {
+    /// 1-based segment number (matches the archive suffix).
+    pub segment: u64,
+    pub entry_count: u64,
+    /// Chain hash after the last entry of the segment.
+    pub chain_hash: String,
+    /// Hash of the previous segment's seal, if any.
+    pub prev_seal: Option<String>,
+    pub sealed_at: String,
+    pub key_id: Option<String>,
+}
+
+/// Segment trailer: the body plus a Dilithium5 signature over it (see `protocol::seal_message`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Seal {
+    #[serde(flatten)]
+    pub body: SealBody,
+    pub signature: String,
+}
+
+impl Seal {
+    /// Hash referenced by the next segment's header.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha3_256::digest(serde_json::to_vec(self).unwrap_or_default()))
+    }
+}
+
+/// One line of a JSONL segment.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SegmentLine {
+    Seal { seal: Seal },
+    Header { prev_seal: String },
+    Entry(LedgerEntry),
+}
+
+/// Parsed segment: optional header reference, entries in order, optional trailer.
+pub(crate) struct Segment {
+    pub path: PathBuf,
+    pub prev_seal: Option<String>,
+    pub entries: Vec<LedgerEntry>,
+    pub seal: Option<Seal>,
+}
+
+/// Next chain hash: SHA3-256(previous chain hash || leaf hash).
+pub fn chain_next(prev: &[u8; 32], leaf: &[u8; 32]) -> [u8; 32] {
+    let mut h = Sha3_256::new();
+    h.update(prev);
+    h.update(leaf);
+    h.finalize().into()
+}
+
+/// Starting chain value for a segment: the previous seal hash, or all zeros for the first segment.
+fn chain_start(prev_seal: Option<&str>) -> anyhow::Result<[u8; 32]> {
+    match prev_seal {
+        None => Ok([0u8; 32]),
+        Some(h) => hex::decode(h)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid seal hash in segment header: {}", h)),
+    }
+}
+
+fn chain_over(prev_seal: Option<&str>, entries: &[LedgerEntry]) -> anyhow::Result<[u8; 32]> {
+    let mut chain = chain_start(prev_seal)?;
+    for entry in entries {
+        chain = chain_next(&chain, &leaf_hash(entry));
+    }
+    Ok(chain)
+}
+
+fn archive_path(ledger_path: &Path, segment: u64) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_owned();
+    name.push(format!(".{:06}", segment));
+    PathBuf::from(name)
+}
+
+/// Sealed archives of `ledger_path` in segment order.
+pub(crate) fn archive_paths(ledger_path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|n| archive_path(ledger_path, n))
+        .take_while(|p| p.exists())
+        .collect()
+}
+
+/// Exclusive lock on `<ledger>.lock`, held by appends and rotation so no entry lands in a segment
+/// that is being sealed. Released when the returned file is dropped.
+pub(crate) fn lock_ledger(ledger_path: &Path) -> anyhow::Result<File> {
+    let mut name = ledger_path.as_os_str().to_owned();
+    name.push(".lock");
+    let f = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PathBuf::from(&name))
+        .map_err(|e| anyhow::anyhow!("Failed to open ledger lock {:?}: {}", name, e))?;
+    f.lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock ledger {}: {}", ledger_path.display(), e))?;
+    Ok(f)
+}
+
+pub(crate) fn read_segment(path: &Path) -> anyhow::Result<Segment> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read ledger {}: {}", path.display(), e)),
+    };
+    let mut segment = Segment {
+        path: path.to_path_buf(),
+        prev_seal: None,
+        entries: Vec::new(),
+        seal: None,
+    };
+    // Ignore a trailing line without newline: a concurrent writer is still appending it.
+    let complete = &content[..content.rfind('\n').map_or(0, |i| i + 1)];
+    for (i, line) in complete.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        if segment.seal.is_some() {
+            return Err(anyhow::anyhow!(
+                "Ledger {} has data after its seal (line {})",
+                path.display(),
+                i + 1
+            ));
+        }
+        let parsed: SegmentLine = serde_json::from_str(line).map_err(|e| {
+            anyhow::anyhow!("Invalid ledger line {} in {}: {}", i + 1, path.display(), e)
+        })?;
+        match parsed {
+            SegmentLine::Header { prev_seal } if i == 0 => segment.prev_seal = Some(prev_seal),
+            SegmentLine::Header { .. } => {
+                return Err(anyhow::anyhow!(
+                    "Segment header not on the first line of {} (line {})",
+                    path.display(),
+                    i + 1
+                ))
+            }
+            SegmentLine::Seal { seal } => segment.seal = Some(seal),
+            SegmentLine::Entry(entry) => segment.entries.push(entry),
+        }
+    }
+    Ok(segment)
+}
+
+/// All segments of a ledger: sealed archives first, then the active file.
+pub(crate) fn read_all_segments(ledger_path: &Path) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = archive_paths(ledger_path)
+        .iter()
+        .map(|p| read_segment(p))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    segments.push(read_segment(ledger_path)?);
+    Ok(segments)
+}
+
+fn needs_rotation(ledger_path: &Path, active: &Segment, rotation: &Rotation) -> anyhow::Result<bool> {
+    let Some(first) = active.entries.first() else {
+        return Ok(false);
+    };
+    if let Some(max_bytes) = rotation.rotate_max_bytes {
+        let len = std::fs::metadata(ledger_path).map(|m| m.len()).unwrap_or(0);
+        if len >= max_bytes {
+            return Ok(true);
+        }
+    }
+    if let Some(max_days) = rotation.rotate_max_days {
+        let t = chrono::DateTime::parse_from_rfc3339(&first.timestamp)
+            .map_err(|e| anyhow::anyhow!("Invalid timestamp in ledger entry: {}", e))?;
+        let age_days = (chrono::Utc::now() - t.with_timezone(&chrono::Utc)).num_days();
+        if age_days >= max_days as i64 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Seal and archive the active segment of a JSONL ledger if the rotation policy says so.
+/// The trailer is signed with `signer`. Returns the seal when a rotation happened.
+pub fn rotate_if_needed(
+    ledger_path: &Path,
+    rotation: &Rotation,
+    signer: &dyn KeyProvider,
+) -> anyhow::Result<Option<Seal>> {
+    if !rotation.is_enabled() {
+        return Ok(None);
+    }
+    if super::LedgerBackend::from_path(ledger_path) != super::LedgerBackend::Jsonl {
+        return Err(anyhow::anyhow!("Ledger rotation is only supported for JSONL ledgers"));
+    }
+    let _lock = lock_ledger(ledger_path)?;
+    let active = read_segment(ledger_path)?;
+    if !needs_rotation(ledger_path, &active, rotation)? {
+        return Ok(None);
+    }
+    let segment = archive_paths(ledger_path).len() as u64 + 1;
+    let chain = chain_over(active.prev_seal.as_deref(), &active.entries)?;
+    let body = SealBody {
+        segment,
+        entry_count: active.entries.len() as u64,
+        chain_hash: hex::encode(chain),
+        prev_seal: active.prev_seal.clone(),
+        sealed_at: chrono::Utc::now().to_rfc3339(),
+        key_id: signer.key_id(),
+    };
+    let seal = Seal {
+        signature: hex::encode(signer.sign(&seal_message(&body)?)?),
+        body,
+    };
+
+    let mut trailer = serde_json::to_string(&serde_json::json!({ "seal": &seal }))?;
+    trailer.push('\n');
+    super::append_line(ledger_path, trailer.as_bytes())?;
+    let archive = archive_path(ledger_path, segment);
+    std::fs::rename(ledger_path, &archive).map_err(|e| {
+        anyhow::anyhow!("Failed to archive ledger segment to {}: {}", archive.display(), e)
+    })?;
+    let mut header = serde_json::to_string(&serde_json::json!({ "prev_seal": seal.hash() }))?;
+    header.push('\n');
+    super::append_line(ledger_path, header.as_bytes())?;
+    Ok(Some(seal))
+}
+
+/// Result of walking every segment of a ledger.
+#[derive(Debug)]
+pub struct ChainReport {
+    pub sealed_segments: usize,
+    pub total_entries: usize,
+    /// Chain hash after the last entry of the active segment.
+    pub head: String,
+}
+
+/// Verify the hash chain across all segments: each header must reference the previous seal, each
+/// seal must match its segment's entry count and chain hash, and — when `public_keys` is non-empty —
+/// each seal signature must verify under the key whose id it names.
+pub fn verify_chain(ledger_path: &Path, public_keys: &[PublicKey]) -> anyhow::Result<ChainReport> {
+    let segments = read_all_segments(ledger_path)?;
+    let mut prev_seal: Option<String> = None;
+    let mut total_entries = 0;
+    let mut head = [0u8; 32];
+    let mut sealed_segments = 0;
+    for (i, segment) in segments.iter().enumerate() {
+        if segment.prev_seal != prev_seal {
+            return Err(anyhow::anyhow!(
+                "Segment {} does not reference the previous seal",
+                segment.path.display()
+            ));
+        }
+        head = chain_over(segment.prev_seal.as_deref(), &segment.entries)?;
+        total_entries += segment.entries.len();
+        let is_active = i + 1 == segments.len();
+        let seal = match (&segment.seal, is_active) {
+            (Some(seal), false) => seal,
+            (None, true) => break,
+            (None, false) => {
+                return Err(anyhow::anyhow!("Archived segment {} is not sealed", segment.path.display()))
+            }
+            (Some(_), true) => {
+                return Err(anyhow::anyhow!("Active ledger {} is sealed", segment.path.display()))
+            }
+        };
+        let body = &seal.body;
+        if body.segment != (i + 1) as u64
+            || body.entry_count != segment.entries.len() as u64
+            || body.chain_hash != hex::encode(head)
+            || body.prev_seal != segment.prev_seal
+        {
+            return Err(anyhow::anyhow!(
+                "Seal of {} does not match its entries",
+                segment.path.display()
+            ));
+        }
+        if !public_keys.is_empty() {
+            let pk = public_keys
+                .iter()
+                .find(|pk| Some(key_id(pk)) == body.key_id)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Seal of {} is signed by an unknown key", segment.path.display())
+                })?;
+            let sig_bytes = hex::decode(&seal.signature)
+                .map_err(|e| anyhow::anyhow!("Invalid seal signature hex: {}", e))?;
+            verify_signature(&load_signature(&sig_bytes)?, &seal_message(body)?, pk).map_err(|_| {
+                anyhow::anyhow!("Seal signature of {} is invalid", segment.path.display())
+            })?;
+        }
+        prev_seal = Some(seal.hash());
+        sealed_segments += 1;
+    }
+    Ok(ChainReport {
+        sealed_segments,
+        total_entries,
+        head: hex::encode(head),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_keypair, load_public_key};
+    use crate::key_provider::FileSystemProvider;
+    use crate::ledger::{JsonlStore, LedgerStore};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("qs_notary_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entry(i: usize) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            file_name: format!("sbom_{}.json", i),
+            signature_hash: format!("{:064x}", i),
+            digest: Some(format!("{:064x}", i + 1000)),
+            key_id: None,
+            revocation: None,
+            attributes: None,
+        }
+    }
+
+    /// A ledger signed by a fresh key, rotated every `per_segment` entries (by size), with
+    /// `segments` sealed archives and `per_segment` entries in the active file.
+    fn rotated_ledger(name: &str, segments: usize, per_segment: usize) -> (PathBuf, PublicKey) {
+        let dir = scratch_dir(name);
+        generate_keypair(&dir).unwrap();
+        let signer = FileSystemProvider::new(&dir.join("private.key"));
+        let ledger = dir.join("ledger.json");
+        let mut store = JsonlStore::new(&ledger);
+        let line_len = serde_json::to_string(&entry(0)).unwrap().len() as u64 + 1;
+        let rotation = Rotation {
+            rotate_max_bytes: Some(line_len * per_segment as u64),
+            rotate_max_days: None,
+        };
+        for i in 0..(segments + 1) * per_segment {
+            // As `sign` does: rotate when due, then append.
+            let sealed = rotate_if_needed(&ledger, &rotation, &signer).unwrap();
+            assert_eq!(sealed.is_some(), i > 0 && i % per_segment == 0, "entry {}", i);
+            store.append(&entry(i)).unwrap();
+        }
+        (ledger, load_public_key(&dir.join("public.key")).unwrap())
+    }
+
+    #[test]
+    fn rotation_at_threshold_keeps_chain_valid() {
+        let (ledger, pk) = rotated_ledger("segment_rotate", 3, 4);
+        assert_eq!(archive_paths(&ledger).len(), 3);
+        let report = verify_chain(&ledger, &[pk]).unwrap();
+        assert_eq!(report.sealed_segments, 3);
+        assert_eq!(report.total_entries, 16);
+        let entries = JsonlStore::new(&ledger).entries().unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.file_name.clone()).collect();
+        let expected: Vec<_> = (0..16).map(|i| format!("sbom_{}.json", i)).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn no_rotation_below_threshold_or_when_disabled() {
+        let dir = scratch_dir("segment_below");
+        generate_keypair(&dir).unwrap();
+        let signer = FileSystemProvider::new(&dir.join("private.key"));
+        let ledger = dir.join("ledger.json");
+        JsonlStore::new(&ledger).append(&entry(0)).unwrap();
+        let large = Rotation { rotate_max_bytes: Some(1 << 20), rotate_max_days: Some(1) };
+        assert!(rotate_if_needed(&ledger, &large, &signer).unwrap().is_none());
+        assert!(rotate_if_needed(&ledger, &Rotation::default(), &signer).unwrap().is_none());
+        assert!(archive_paths(&ledger).is_empty());
+    }
+
+    /// Flip one hex digit of `field` in the first line of `path` that has it, keeping the JSON valid.
+    fn flip_byte(path: &Path, field: &str) {
+        let mut bytes = std::fs::read(path).unwrap();
+        let needle = format!("\"{}\":\"", field);
+        let at = bytes
+            .windows(needle.len())
+            .position(|w| w == needle.as_bytes())
+            .unwrap()
+            + needle.len();
+        bytes[at] = if bytes[at] == b'0' { b'1' } else { b'0' };
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn tampered_sealed_entry_fails() {
+        let (ledger, pk) = rotated_ledger("segment_tamper_entry", 2, 3);
+        flip_byte(&archive_path(&ledger, 1), "signature_hash");
+        let err = verify_chain(&ledger, &[pk]).unwrap_err().to_string();
+        assert!(err.contains("does not match its entries"), "{}", err);
+    }
+
+    #[test]
+    fn tampered_seal_signature_fails() {
+        let (ledger, pk) = rotated_ledger("segment_tamper_seal", 2, 3);
+        let archive = archive_path(&ledger, 2);
+        let content = std::fs::read_to_string(&archive).unwrap();
+        let at = content.rfind("\"signature\":\"").unwrap() + "\"signature\":\"".len() + 10;
+        let mut bytes = content.into_bytes();
+        bytes[at] = if bytes[at] == b'0' { b'1' } else { b'0' };
+        std::fs::write(&archive, bytes).unwrap();
+        // The next segment's header references the seal's hash, which covers the signature, so the
+        // change is caught even without the signer's key.
+        assert!(verify_chain(&ledger, &[pk]).is_err());
+        assert!(verify_chain(&ledger, &[]).is_err());
+    }
+
+    #[test]
+    fn seal_signed_over_bare_body_digest_fails() {
+        let (ledger, pk) = rotated_ledger("segment_bare_digest", 1, 2);
+        let signer = FileSystemProvider::new(&ledger.with_file_name("private.key"));
+        // Re-sign the seal over the SHA3-256 of its body, as a signer of bare digests would, and
+        // point the active segment at the new seal so the hash chain itself stays intact.
+        let archive = archive_path(&ledger, 1);
+        let content = std::fs::read_to_string(&archive).unwrap();
+        let (entries, trailer) = content.trim_end().rsplit_once('\n').unwrap();
+        let SegmentLine::Seal { mut seal } = serde_json::from_str(trailer).unwrap() else {
+            panic!("no seal trailer");
+        };
+        let digest = Sha3_256::digest(serde_json::to_vec(&seal.body).unwrap());
+        seal.signature = hex::encode(signer.sign(&digest).unwrap());
+        let trailer = serde_json::json!({ "seal": &seal });
+        std::fs::write(&archive, format!("{}\n{}\n", entries, trailer)).unwrap();
+        let active = std::fs::read_to_string(&ledger).unwrap();
+        let (_, rest) = active.split_once('\n').unwrap();
+        let header = serde_json::json!({ "prev_seal": seal.hash() });
+        std::fs::write(&ledger, format!("{}\n{}", header, rest)).unwrap();
+
+        assert!(verify_chain(&ledger, &[]).is_ok());
+        let err = verify_chain(&ledger, &[pk]).unwrap_err().to_string();
+        assert!(err.contains("Seal signature"), "{}", err);
+    }
+
+    #[test]
+    fn deleted_middle_segment_fails() {
+        let (ledger, pk) = rotated_ledger("segment_delete", 3, 2);
+        std::fs::remove_file(archive_path(&ledger, 2)).unwrap();
+        assert!(verify_chain(&ledger, &[pk]).is_err());
+        assert!(verify_chain(&ledger, &[]).is_err());
+    }
+
+    #[test]
+    fn seal_by_unknown_key_fails() {
+        let (ledger, _) = rotated_ledger("segment_other_key", 1, 2);
+        let (_, other) = rotated_ledger("segment_other_key_2", 0, 1);
+        let err = verify_chain(&ledger, &[other]).unwrap_err().to_string();
+        assert!(err.contains("unknown key"), "{}", err);
+    }
+}
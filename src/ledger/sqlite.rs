@@ -0,0 +1,181 @@
+//! SQLite ledger backend: one row per entry, indexed on digest, key id and time.
+
+use super::{EntryFilter, LedgerEntry, LedgerStore};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use std::path::Path;
+use std::time::Duration;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entries (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp      TEXT NOT NULL,
+    file_name      TEXT NOT NULL,
+    signature_hash TEXT NOT NULL,
+    digest         TEXT,
+    key_id         TEXT,
+    revocation     TEXT,
+    attributes     TEXT
+);
+CREATE INDEX IF NOT EXISTS entries_digest ON entries(digest);
+CREATE INDEX IF NOT EXISTS entries_key_id ON entries(key_id);
+CREATE INDEX IF NOT EXISTS entries_digest_nocase ON entries(digest COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS entries_key_id_nocase ON entries(key_id COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS entries_timestamp ON entries(timestamp);
+CREATE INDEX IF NOT EXISTS entries_time ON entries(julianday(timestamp));
+";
+
+const COLUMNS: &str =
+    "id, timestamp, file_name, signature_hash, digest, key_id, revocation, attributes";
+
+/// Columns added after the first release, with the ALTER that adds them to older databases.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("revocation", "ALTER TABLE entries ADD COLUMN revocation TEXT"),
+    ("attributes", "ALTER TABLE entries ADD COLUMN attributes TEXT"),
+];
+
+/// Optional column holding a JSON value.
+fn json_column<T: serde::de::DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<Option<T>> {
+    let text: Option<String> = row.get(index)?;
+    text.map(|t| serde_json::from_str(&t))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+/// Row to (0-based log index, entry); `id` starts at 1 and rows are never deleted.
+fn row_to_entry(row: &Row) -> rusqlite::Result<(u64, LedgerEntry)> {
+    let id: i64 = row.get(0)?;
+    Ok((
+        (id - 1) as u64,
+        LedgerEntry {
+            timestamp: row.get(1)?,
+            file_name: row.get(2)?,
+            signature_hash: row.get(3)?,
+            digest: row.get(4)?,
+            key_id: row.get(5)?,
+            revocation: json_column(row, 6)?,
+            attributes: json_column(row, 7)?,
+        },
+    ))
+}
+
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database and ensure the schema exists.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open ledger {}: {}", path.display(), e))?;
+        // Concurrent signers wait for the write lock instead of failing with SQLITE_BUSY.
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| anyhow::anyhow!("Failed to initialize ledger {}: {}", path.display(), e))?;
+        // Databases created by older versions lack the columns added since.
+        for (column, alter) in ADDED_COLUMNS {
+            let present: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('entries') WHERE name = ?1",
+                [column],
+                |row| row.get(0),
+            )?;
+            if !present {
+                conn.execute_batch(alter)
+                    .map_err(|e| anyhow::anyhow!("Failed to upgrade ledger {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+}
+
+impl LedgerStore for SqliteStore {
+    fn append(&mut self, entry: &LedgerEntry) -> anyhow::Result<()> {
+        self.conn
+            .execute(
+                "INSERT INTO entries (timestamp, file_name, signature_hash, digest, key_id, revocation, attributes)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    entry.timestamp,
+                    entry.file_name,
+                    entry.signature_hash,
+                    entry.digest,
+                    entry.key_id,
+                    entry
+                        .revocation
+                        .as_ref()
+                        .map(serde_json::to_string)
+                        .transpose()?,
+                    entry
+                        .attributes
+                        .as_ref()
+                        .map(serde_json::to_string)
+                        .transpose()?
+                ],
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to write ledger: {}", e))?;
+        Ok(())
+    }
+
+    fn entries(&self) -> anyhow::Result<Vec<LedgerEntry>> {
+        Ok(self
+            .query(&EntryFilter::default())?
+            .into_iter()
+            .map(|(_, e)| e)
+            .collect())
+    }
+
+    fn query(&self, filter: &EntryFilter) -> anyhow::Result<Vec<(u64, LedgerEntry)>> {
+        let mut clauses = Vec::new();
+        let mut args: Vec<String> = Vec::new();
+        if let Some(f) = &filter.file_name {
+            clauses.push("file_name = ?");
+            args.push(f.clone());
+        }
+        // Hex ids match case-insensitively, as in `EntryFilter::matches`.
+        if let Some(k) = &filter.key_id {
+            clauses.push("key_id = ? COLLATE NOCASE");
+            args.push(k.clone());
+        }
+        if let Some(d) = &filter.digest {
+            clauses.push("digest = ? COLLATE NOCASE");
+            args.push(d.clone());
+        }
+        if let Some(t) = &filter.since {
+            clauses.push("julianday(timestamp) >= julianday(?)");
+            args.push(t.clone());
+        }
+        if let Some(t) = &filter.until {
+            clauses.push("julianday(timestamp) <= julianday(?)");
+            args.push(t.clone());
+        }
+        let from_id;
+        if let Some(index) = filter.from_index {
+            from_id = format!("id > {}", index);
+            clauses.push(&from_id);
+        }
+        let where_clause = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+        let limit = filter.limit.map_or(-1, |l| l as i64);
+        let sql = format!(
+            "SELECT {} FROM entries {} ORDER BY id LIMIT {} OFFSET {}",
+            COLUMNS, where_clause, limit, filter.offset
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args), row_to_entry)?;
+        rows.collect::<Result<_, _>>()
+            .map_err(|e| anyhow::anyhow!("Failed to read ledger: {}", e))
+    }
+
+    fn get(&self, index: u64) -> anyhow::Result<Option<LedgerEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM entries WHERE id = ?1", COLUMNS))?;
+        let mut rows = stmt.query_map(params![index as i64 + 1], row_to_entry)?;
+        rows.next()
+            .transpose()
+            .map(|r| r.map(|(_, e)| e))
+            .map_err(|e| anyhow::anyhow!("Failed to read ledger: {}", e))
+    }
+}
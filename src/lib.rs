@@ -0,0 +1,23 @@
+//! qs_notary library: modules shared by the `qs_notary` CLI and the `qs_server` binary.
+
+pub mod attestation;
+pub mod canonical;
+pub mod client;
+pub mod crypto;
+pub mod key_provider;
+pub mod jsf;
+pub mod ledger;
+pub mod log;
+pub mod merkle;
+pub mod outbox;
+pub mod policy;
+pub mod protocol;
+pub mod push;
+pub mod sbom;
+pub mod schema;
+pub mod sign;
+pub mod sign_all;
+#[cfg(test)]
+mod testing;
+pub mod tls;
+pub mod verify;
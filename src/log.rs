@@ -0,0 +1,496 @@
+//! log commands: query the central transparency log over HTTP, follow it, and fetch witnessed
+//! inclusion proofs for signatures.
+
+use crate::attestation::{self, DsseEnvelope};
+use crate::client;
+use crate::jsf;
+use crate::ledger::{EntryFilter, LedgerEntry};
+use crate::protocol::{IndexedEntry, SignatureEnvelope, WitnessedInclusion};
+use crate::verify;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use sha3::{Digest, Sha3_256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Reconnect delay after the stream drops; doubles up to `MAX_RECONNECT_DELAY`.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Print one entry per line: index, time, file name, digest, key id.
+fn print_entry(e: &IndexedEntry) {
+    println!(
+        "{:>8}  {}  {}  digest={}  key={}",
+        e.index,
+        e.entry.timestamp,
+        e.entry.file_name,
+        e.entry.digest.as_deref().unwrap_or("-"),
+        e.entry.key_id.as_deref().unwrap_or("-"),
+    );
+}
+
+/// Entries for `log search`: the one at `index`, or a filtered page (all pages with `all`), and
+/// the offset of the page after the last one fetched, if there are more results.
+fn find(
+    server_url: &str,
+    index: Option<u64>,
+    mut filter: EntryFilter,
+    all: bool,
+) -> anyhow::Result<(Vec<IndexedEntry>, Option<u64>)> {
+    filter.validate()?;
+    if let Some(index) = index {
+        let entry = client::entry(server_url, index)?
+            .ok_or_else(|| anyhow::anyhow!("No entry at index {}", index))?;
+        return Ok((vec![entry], None));
+    }
+    let mut found = Vec::new();
+    loop {
+        let page = client::search(server_url, &filter)?;
+        found.extend(page.entries);
+        match page.next_offset {
+            Some(next) if all => filter.offset = next,
+            next => return Ok((found, next)),
+        }
+    }
+}
+
+/// Run `log search`: a single entry by index, or a filtered page (all pages with `all`).
+pub fn search(
+    server_url: &str,
+    index: Option<u64>,
+    filter: EntryFilter,
+    all: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let (found, next) = find(server_url, index, filter, all)?;
+    if let Some(next) = next {
+        eprintln!("(more results: use --offset {} or --all)", next);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&found)?);
+    } else {
+        found.iter().for_each(print_entry);
+        eprintln!("{} entries", found.len());
+    }
+    Ok(())
+}
+
+/// Options for `log watch`.
+pub struct WatchOptions<'a> {
+    pub key_id: Option<&'a str>,
+    /// Start after this log index (overrides the state file).
+    pub after: Option<u64>,
+    /// Shell command run for each entry instead of printing it.
+    pub exec: Option<&'a str>,
+    /// File holding the last seen index, read on start and updated after each entry.
+    pub state_file: Option<&'a Path>,
+    pub json: bool,
+}
+
+/// Run `log watch`: follow the server's entry stream and print (or hand to a hook) each matching
+/// entry. After a disconnect it reconnects with backoff and resumes after the last seen index;
+/// with a state file, the same holds across restarts. Runs until interrupted.
+pub fn watch(server_url: &str, opts: &WatchOptions) -> anyhow::Result<()> {
+    let mut last = match opts.after {
+        Some(after) => Some(after),
+        None => opts.state_file.map(read_state).transpose()?.flatten(),
+    };
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let mut fatal = None;
+        let result = client::watch(server_url, opts.key_id, last, |e| {
+            let index = e.index;
+            handle_entry(&e, opts);
+            last = Some(index);
+            delay = RECONNECT_DELAY;
+            if let Some(path) = opts.state_file {
+                if let Err(err) = write_state(path, index) {
+                    fatal = Some(err);
+                    return Err(anyhow::anyhow!("watch state not saved"));
+                }
+            }
+            Ok(())
+        });
+        if let Some(err) = fatal {
+            return Err(err);
+        }
+        match result {
+            Ok(()) => eprintln!("Stream closed by server; reconnecting in {:?}", delay),
+            Err(e) => eprintln!("Warning: {}; reconnecting in {:?}", e, delay),
+        }
+        std::thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Print the entry, or run the hook with the entry JSON on stdin and its fields in `QS_*`
+/// variables. A failing hook is reported but does not stop the watch.
+fn handle_entry(e: &IndexedEntry, opts: &WatchOptions) {
+    let Some(cmd) = opts.exec else {
+        if opts.json {
+            println!("{}", serde_json::to_string(e).unwrap_or_default());
+        } else {
+            print_entry(e);
+        }
+        return;
+    };
+    if let Err(err) = run_hook(cmd, e) {
+        eprintln!("Warning: hook failed for entry {}: {}", e.index, err);
+    }
+}
+
+fn run_hook(cmd: &str, e: &IndexedEntry) -> anyhow::Result<()> {
+    let mut command = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    };
+    let mut child = command
+        .env("QS_LOG_INDEX", e.index.to_string())
+        .env("QS_FILE_NAME", &e.entry.file_name)
+        .env("QS_TIMESTAMP", &e.entry.timestamp)
+        .env("QS_DIGEST", e.entry.digest.as_deref().unwrap_or(""))
+        .env("QS_KEY_ID", e.entry.key_id.as_deref().unwrap_or(""))
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its input is fine.
+        let _ = stdin.write_all(serde_json::to_string(e)?.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{}", status));
+    }
+    Ok(())
+}
+
+/// Last seen index from the state file; `None` if the file does not exist yet.
+fn read_state(path: &Path) -> anyhow::Result<Option<u64>> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow::anyhow!("Invalid watch state file {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Record the last seen index, replacing the state file atomically.
+fn write_state(path: &Path, index: u64) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, format!("{}\n", index))
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| anyhow::anyhow!("Failed to write watch state {}: {}", path.display(), e))
+}
+
+/// A signature to prove, as `sign` logged it: the envelope that gets the proof and where it is
+/// written, the digest its entry records (derived as `verify` derives it) and the signatures the
+/// entry may record.
+struct Logged {
+    envelope: SignatureEnvelope,
+    path: PathBuf,
+    digest: String,
+    signatures: Vec<String>,
+}
+
+/// The signature at `sig_path`: a `.sig` (over the SBOM or its canonical form), which gets the
+/// proof itself, or a DSSE attestation (over its PAE). Without a file at `sig_path`, the JSF
+/// signature embedded in the SBOM (over its template digest). An attestation or embedded signature
+/// cannot hold the proof, so it goes into `verify::proof_path_for` of that file.
+fn logged_signature(sbom_path: &Path, sig_path: &Path) -> anyhow::Result<Logged> {
+    let sbom_bytes = std::fs::read(sbom_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read SBOM {}: {}", sbom_path.display(), e))?;
+    let content = match std::fs::read(sig_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && jsf::extract(&sbom_bytes).is_ok() => {
+            let embedded = jsf::extract(&sbom_bytes)?;
+            let path = verify::proof_path_for(sbom_path);
+            return Ok(Logged {
+                envelope: proof_envelope(&path)?,
+                path,
+                digest: hex::encode(embedded.digest),
+                signatures: vec![hex::encode(&embedded.signature)],
+            });
+        }
+        Err(e) => {
+            return Err(anyhow::anyhow!("Failed to read signature {}: {}", sig_path.display(), e))
+        }
+    };
+    if attestation::is_dsse(&content) {
+        let dsse: DsseEnvelope = serde_json::from_slice(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid DSSE envelope: {}", e))?;
+        let path = verify::proof_path_for(sig_path);
+        return Ok(Logged {
+            envelope: proof_envelope(&path)?,
+            path,
+            digest: hex::encode(Sha3_256::digest(dsse.signed_message()?)),
+            signatures: dsse.signatures()?.iter().map(hex::encode).collect(),
+        });
+    }
+    let envelope = verify::load_signature_file(sig_path)?;
+    Ok(Logged {
+        digest: hex::encode(verify::artifact_digest(&sbom_bytes, &envelope)?),
+        signatures: vec![envelope.signature.clone()],
+        envelope,
+        path: sig_path.to_path_buf(),
+    })
+}
+
+/// The proof file of an earlier `prove`, or an empty envelope.
+fn proof_envelope(path: &Path) -> anyhow::Result<SignatureEnvelope> {
+    if path.exists() {
+        verify::load_signature_file(path)
+    } else {
+        Ok(SignatureEnvelope::default())
+    }
+}
+
+/// Find the entry recording one of `signatures` over `digest` in the log.
+fn find_entry(server_url: &str, digest: &str, signatures: &[String]) -> anyhow::Result<(u64, LedgerEntry)> {
+    let mut filter = EntryFilter {
+        digest: Some(digest.to_string()),
+        ..Default::default()
+    };
+    loop {
+        let page = client::search(server_url, &filter)?;
+        if let Some(found) = page
+            .entries
+            .into_iter()
+            .find(|e| {
+                signatures
+                    .iter()
+                    .any(|s| e.entry.signature_hash.eq_ignore_ascii_case(s))
+            })
+        {
+            return Ok((found.index, found.entry));
+        }
+        match page.next_offset {
+            Some(next) => filter.offset = next,
+            None => return Err(anyhow::anyhow!("Signature is not in the log at {}", server_url)),
+        }
+    }
+}
+
+/// Run `log prove`: add to the `.sig` an inclusion proof into the cosigned tree head that covers
+/// its entry, picking the head with the most cosignatures (the largest on a tie). A `.sig` without
+/// a receipt gets its entry from the log, found by digest and signature. A DSSE attestation or a
+/// signature embedded in the SBOM gets the proof in a file next to it (see `logged_signature`).
+/// With `log_pk`, the tree head's signature is checked before anything is written.
+pub fn prove(
+    server_url: &str,
+    sbom_path: &Path,
+    sig_path: &Path,
+    log_pk: Option<&PublicKey>,
+) -> anyhow::Result<()> {
+    let Logged {
+        mut envelope,
+        path,
+        digest,
+        signatures,
+    } = logged_signature(sbom_path, sig_path)?;
+    let (index, entry) = match (&envelope.log_entry, &envelope.receipt) {
+        (Some(entry), Some(receipt)) => (receipt.body.log_index, entry.clone()),
+        _ => find_entry(server_url, &digest, &signatures)?,
+    };
+    let head = client::cosigned_tree_heads(server_url)?
+        .into_iter()
+        .filter(|h| h.tree_head.body.tree_size > index)
+        .max_by_key(|h| (h.cosignatures.len(), h.tree_head.body.tree_size))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No cosigned tree head covers log index {} yet; try again after the witnesses' next round",
+                index
+            )
+        })?;
+    if let Some(log_pk) = log_pk {
+        head.tree_head.verify(log_pk)?;
+    }
+    let proof = client::inclusion_proof(server_url, index, head.tree_head.body.tree_size)?;
+    proof.verify(&entry, &head.tree_head.body)?;
+    println!(
+        "Entry {} is in the tree head of size {} with {} cosignature(s)",
+        index,
+        head.tree_head.body.tree_size,
+        head.cosignatures.len()
+    );
+    envelope.signature = entry.signature_hash.clone();
+    envelope.log_entry = Some(entry);
+    envelope.inclusion_proof = Some(WitnessedInclusion {
+        proof,
+        tree_head: head,
+    });
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string(&envelope)?)
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(|e| anyhow::anyhow!("Failed to write signature {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canonical::Canonicalization;
+    use crate::ledger::{self, Rotation};
+    use crate::protocol::SignedUpload;
+    use crate::sign::{self, Output, SignOptions};
+    use crate::testing::{self, StubLog};
+    use std::sync::Arc;
+
+    /// A CycloneDX SBOM (of an artifact with a hash, for attestations) whose canonical form
+    /// differs from its bytes.
+    const SBOM: &str = r#"{
+  "specVersion": "1.6",
+  "bomFormat": "CycloneDX",
+  "version": 1,
+  "metadata": {
+    "component": {
+      "type": "application",
+      "name": "app.tar.gz",
+      "hashes": [ { "alg": "SHA-256", "content": "5f6c8b7d0e0f4a3c9b2e1d7a6c5b4a3928171615141312111009080706050403" } ]
+    }
+  },
+  "components": [ { "type": "library", "name": "lib", "version": "1.0" } ]
+}"#;
+
+    /// Sign a fresh SBOM with `output`, returning it and the entry `sign` put in the ledger.
+    fn signed(name: &str, output: Output) -> (PathBuf, LedgerEntry) {
+        let dir = testing::scratch_dir(name);
+        let sbom = dir.join("bom.json");
+        std::fs::write(&sbom, SBOM).unwrap();
+        let ledger_path = dir.join("ledger.json");
+        let opts = SignOptions {
+            ledger_path: &ledger_path,
+            rotation: &Rotation::default(),
+            tsa_url: None,
+            upload: None,
+        };
+        sign::run(&sbom, &testing::key(&dir), &opts, false, output).unwrap();
+        let mut entries = ledger::open_store(&ledger_path, None).unwrap().entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        (sbom, entries.remove(0))
+    }
+
+    fn assert_finds(logged: &Logged, entry: &LedgerEntry) {
+        assert_eq!(Some(&logged.digest), entry.digest.as_ref());
+        assert!(logged
+            .signatures
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(&entry.signature_hash)));
+    }
+
+    #[test]
+    fn detached_signature_is_found_by_the_file_digest() {
+        let (sbom, entry) = signed("prove_detached", Output::Detached(None));
+        let sig_path = sign::sig_path_for(&sbom);
+        let logged = logged_signature(&sbom, &sig_path).unwrap();
+        assert_eq!(logged.digest, hex::encode(Sha3_256::digest(SBOM)));
+        assert_finds(&logged, &entry);
+        assert_eq!(logged.path, sig_path);
+    }
+
+    #[test]
+    fn canonical_signature_is_found_by_the_canonical_digest() {
+        let (sbom, entry) = signed("prove_jcs", Output::Detached(Some(Canonicalization::Jcs)));
+        let sig_path = sign::sig_path_for(&sbom);
+        let logged = logged_signature(&sbom, &sig_path).unwrap();
+        assert_ne!(logged.digest, hex::encode(Sha3_256::digest(SBOM)));
+        assert_finds(&logged, &entry);
+        assert_eq!(logged.path, sig_path);
+    }
+
+    #[test]
+    fn embedded_signature_is_found_by_the_template_digest() {
+        let (sbom, entry) = signed("prove_embedded", Output::Embedded);
+        let logged = logged_signature(&sbom, &sign::sig_path_for(&sbom)).unwrap();
+        assert_ne!(logged.digest, hex::encode(Sha3_256::digest(std::fs::read(&sbom).unwrap())));
+        assert_finds(&logged, &entry);
+        assert_eq!(logged.path, verify::proof_path_for(&sbom));
+    }
+
+    #[test]
+    fn attestation_is_found_by_the_digest_of_its_pae() {
+        let (sbom, entry) = signed("prove_dsse", Output::Attestation(Vec::new()));
+        let attestation = sign::attestation_path_for(&sbom);
+        let logged = logged_signature(&sbom, &attestation).unwrap();
+        let dsse: DsseEnvelope = serde_json::from_slice(&std::fs::read(&attestation).unwrap()).unwrap();
+        assert_eq!(logged.digest, hex::encode(Sha3_256::digest(dsse.signed_message().unwrap())));
+        assert_finds(&logged, &entry);
+        assert_eq!(logged.path, verify::proof_path_for(&attestation));
+    }
+
+    /// A stub log holding entries named `names`, alternating between two key ids.
+    fn stub_log(names: &[&str]) -> String {
+        let log = Arc::new(StubLog::default());
+        for (i, name) in names.iter().enumerate() {
+            let mut entry = testing::entry(name);
+            entry.key_id = Some(if i % 2 == 0 { "aa" } else { "bb" }.repeat(32));
+            log.received.lock().unwrap().push(SignedUpload {
+                entry,
+                key_id: String::new(),
+                signature: String::new(),
+            });
+        }
+        testing::serve(log)
+    }
+
+    fn found(entries: &[IndexedEntry]) -> Vec<(u64, &str)> {
+        entries.iter().map(|e| (e.index, e.entry.file_name.as_str())).collect()
+    }
+
+    #[test]
+    fn search_follows_pages_only_with_all() {
+        let url = stub_log(&["a.json", "b.json", "c.json", "d.json", "e.json"]);
+        let filter = EntryFilter {
+            key_id: Some("AA".repeat(32)),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let (entries, next) = find(&url, None, filter.clone(), false).unwrap();
+        assert_eq!((found(&entries), next), (vec![(0, "a.json")], Some(1)));
+        let (entries, next) = find(&url, None, filter.clone(), true).unwrap();
+        assert_eq!(
+            (found(&entries), next),
+            (vec![(0, "a.json"), (2, "c.json"), (4, "e.json")], None)
+        );
+        let from_index = EntryFilter {
+            from_index: Some(1),
+            offset: 1,
+            ..filter
+        };
+        let (entries, _) = find(&url, None, from_index, true).unwrap();
+        assert_eq!(found(&entries), [(4, "e.json")]);
+        let by_name = EntryFilter {
+            file_name: Some("d.json".to_string()),
+            ..Default::default()
+        };
+        let (entries, next) = find(&url, None, by_name, false).unwrap();
+        assert_eq!((found(&entries), next), (vec![(3, "d.json")], None));
+    }
+
+    #[test]
+    fn search_by_index_ignores_the_filter() {
+        let url = stub_log(&["a.json", "b.json"]);
+        let filter = EntryFilter {
+            file_name: Some("a.json".to_string()),
+            ..Default::default()
+        };
+        let (entries, next) = find(&url, Some(1), filter, false).unwrap();
+        assert_eq!((found(&entries), next), (vec![(1, "b.json")], None));
+        let err = find(&url, Some(2), EntryFilter::default(), false).unwrap_err();
+        assert_eq!(err.to_string(), "No entry at index 2");
+
+        // A bad time bound fails before anything is fetched.
+        let filter = EntryFilter {
+            since: Some("yesterday".to_string()),
+            ..Default::default()
+        };
+        let err = find("http://127.0.0.1:9", None, filter, false).unwrap_err();
+        assert!(err.to_string().contains("Invalid RFC 3339 time"), "{}", err);
+    }
+}
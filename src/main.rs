@@ -1,15 +1,10 @@
 //! qs_notary: post-quantum SBOM notary CLI (Dilithium5 sign/verify).
 
-mod crypto;
-mod key_provider;
-mod ledger;
-mod policy;
-mod sign;
-mod sign_all;
-mod verify;
-
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use qs_notary::canonical::Canonicalization;
+use qs_notary::{client, crypto, key_provider, ledger, log, outbox, push, sign, sign_all, tls, verify};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "qs_notary")]
@@ -17,6 +12,9 @@ use std::path::PathBuf;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[command(flatten)]
+    tls: tls::ClientTls,
 }
 
 #[derive(Subcommand)]
@@ -29,27 +27,72 @@ enum Commands {
         output_dir: Option<PathBuf>,
     },
 
-    /// Sign an SBOM file (CycloneDX or SPDX JSON). Writes .sig and appends to ledger.
+    /// Sign an SBOM file (CycloneDX JSON/XML, SPDX JSON/tag-value/RDF). Writes .sig and appends to ledger.
     Sign {
         /// Path to the SBOM file.
         #[arg(value_name = "SBOM")]
         sbom_path: PathBuf,
 
+        /// Accept SBOMs with known-benign deviations (unknown properties, elements or tags,
+        /// malformed dates and identifiers), reported as warnings.
+        #[arg(long)]
+        lenient: bool,
+
+        /// Sign a canonical form of the SBOM instead of its bytes, so re-formatting or
+        /// re-ordering keys does not break the signature (jcs: RFC 8785, JSON SBOMs only).
+        #[arg(long, value_enum, value_name = "MODE")]
+        canonical: Option<Canonicalization>,
+
+        /// Embed a JSON Signature Format signature in the SBOM (CycloneDX JSON only) instead of
+        /// writing a .sig file. The SBOM is rewritten in place.
+        #[arg(long, conflicts_with_all = ["canonical", "tsa_url", "format"])]
+        embed: bool,
+
+        /// Signature output: sig (a .sig file, the default) or dsse (an in-toto attestation in a
+        /// DSSE envelope, <SBOM>.intoto.json, with the SBOM as predicate; JSON SBOMs only).
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        format: Option<sign::Format>,
+
+        /// Artifact the attestation is about, by file (repeatable). Added to the subjects the SBOM
+        /// itself names with hashes (the CycloneDX metadata.component, SPDX described packages).
+        #[arg(long = "subject", value_name = "FILE", requires = "format")]
+        subjects: Vec<PathBuf>,
+
         /// Path to the private key file (ignored if --kms is set).
-        #[arg(value_name = "PRIVATE_KEY", short, long)]
-        private_key: PathBuf,
+        #[arg(
+            value_name = "PRIVATE_KEY",
+            short,
+            long,
+            required_unless_present_any = ["key", "kms"]
+        )]
+        private_key: Option<PathBuf>,
+
+        /// Key held by a signing service: remote://<host[:port]>/<key name> (remotes:// for HTTPS).
+        /// The service logs each entry; the team's API token is read from QS_API_TOKEN.
+        #[arg(long, value_name = "URI", conflicts_with_all = ["private_key", "kms"])]
+        key: Option<String>,
 
         /// Use mock KMS instead of local key file (test only).
         #[arg(long)]
         kms: bool,
 
-        /// Path to the ledger file (default: ledger.json in current directory).
+        /// Path to the ledger (default: ledger.json in current directory; .db/.sqlite selects SQLite).
         #[arg(long, default_value = "ledger.json")]
         ledger: PathBuf,
 
-        /// URL of transparency log server (e.g. http://localhost:8080); uploads entry in background.
+        #[command(flatten)]
+        rotation: ledger::Rotation,
+
+        /// Timestamp authority (a qs_server URL); its signed timestamp token is embedded in the .sig.
+        #[arg(long)]
+        tsa_url: Option<String>,
+
+        /// URL of transparency log server (e.g. http://localhost:8080); entry is queued and uploaded.
         #[arg(long)]
         server_url: Option<String>,
+
+        #[command(flatten)]
+        outbox: OutboxArgs,
     },
 
     /// Verify an SBOM file against a signature and public key.
@@ -58,9 +101,17 @@ enum Commands {
         #[arg(value_name = "SBOM")]
         sbom_path: PathBuf,
 
-        /// Path to the signature file.
-        #[arg(value_name = "SIGNATURE")]
-        signature_path: PathBuf,
+        /// Path to the signature file (.sig, or a DSSE attestation from `sign --format dsse`).
+        #[arg(value_name = "SIGNATURE", required_unless_present = "embedded")]
+        signature_path: Option<PathBuf>,
+
+        /// Check the JSF signature embedded in the SBOM by `sign --embed` instead of a .sig file.
+        #[arg(long, conflicts_with_all = ["signature_path", "log_public_key"])]
+        embedded: bool,
+
+        /// Artifact that must be a subject of the attestation (repeatable; DSSE signature files).
+        #[arg(long = "subject", value_name = "FILE", conflicts_with = "embedded")]
+        subjects: Vec<PathBuf>,
 
         /// Path to the public key file.
         #[arg(value_name = "PUBLIC_KEY", short, long)]
@@ -69,6 +120,16 @@ enum Commands {
         /// Path to policy JSON (optional; enforces allowlist and max_age when set).
         #[arg(long)]
         policy: Option<PathBuf>,
+
+        /// Log server public key; requires a valid inclusion receipt (or inclusion proof from
+        /// `log prove`) in the signature file.
+        #[arg(long)]
+        log_public_key: Option<PathBuf>,
+
+        /// Key registry: a server URL (fetches GET /keys) or a saved snapshot file. Rejects keys
+        /// that are not enrolled or were revoked before the signature was made.
+        #[arg(long, value_name = "URL|FILE")]
+        registry: Option<String>,
     },
 
     /// Recursively sign all files in a directory, then create and sign manifest.json.
@@ -79,25 +140,298 @@ enum Commands {
         dir: PathBuf,
 
         /// Path to the private key file (ignored if --kms is set).
-        #[arg(value_name = "PRIVATE_KEY", short, long)]
-        private_key: PathBuf,
+        #[arg(
+            value_name = "PRIVATE_KEY",
+            short,
+            long,
+            required_unless_present_any = ["key", "kms"]
+        )]
+        private_key: Option<PathBuf>,
+
+        /// Key held by a signing service: remote://<host[:port]>/<key name> (remotes:// for HTTPS).
+        /// The service logs each entry; the team's API token is read from QS_API_TOKEN.
+        #[arg(long, value_name = "URI", conflicts_with_all = ["private_key", "kms"])]
+        key: Option<String>,
 
         /// Use mock KMS instead of local key file (test only).
         #[arg(long)]
         kms: bool,
 
-        /// Path to the ledger file (default: ledger.json).
+        /// Path to the ledger (default: ledger.json; .db/.sqlite selects SQLite).
         #[arg(long, default_value = "ledger.json")]
         ledger: PathBuf,
 
-        /// URL of transparency log server; uploads each entry in background.
+        #[command(flatten)]
+        rotation: ledger::Rotation,
+
+        /// Timestamp authority (a qs_server URL); its signed timestamp token is embedded in the .sig.
+        #[arg(long)]
+        tsa_url: Option<String>,
+
+        /// URL of transparency log server; each entry is queued and uploaded.
         #[arg(long)]
         server_url: Option<String>,
+
+        #[command(flatten)]
+        outbox: OutboxArgs,
+    },
+
+    /// Upload everything still queued in the outbox (retries failed uploads immediately).
+    Sync {
+        /// Path to the ledger whose outbox to drain (default: ledger.json).
+        #[arg(long, default_value = "ledger.json")]
+        ledger: PathBuf,
+
+        /// Outbox directory (default: <ledger>.outbox).
+        #[arg(long)]
+        outbox: Option<PathBuf>,
+
+        /// Give up after this many seconds; remaining items stay queued.
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+    },
+
+    /// Local ledger maintenance.
+    Ledger {
+        #[command(subcommand)]
+        command: LedgerCommands,
+    },
+
+    /// Query the central transparency log server.
+    Log {
+        #[command(subcommand)]
+        command: LogCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogCommands {
+    /// Search entries by file name, key id, digest or time range, or fetch one by index.
+    Search {
+        /// URL of the transparency log server (e.g. http://localhost:8080).
+        #[arg(long)]
+        server_url: String,
+
+        /// Fetch the single entry at this log index (other filters are ignored).
+        #[arg(long)]
+        index: Option<u64>,
+
+        #[command(flatten)]
+        filter: SearchFilter,
+
+        /// Follow pagination and print every match.
+        #[arg(long)]
+        all: bool,
+
+        /// Print JSON instead of one line per entry.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Follow new entries as they are appended, reconnecting and resuming after disconnects.
+    Watch {
+        /// URL of the transparency log server (e.g. http://localhost:8080).
+        #[arg(long)]
+        server_url: String,
+
+        /// Only entries signed by this key id.
+        #[arg(long)]
+        key_id: Option<String>,
+
+        /// Start after this log index, replaying what came since (default: the state file, else
+        /// only new entries).
+        #[arg(long)]
+        after: Option<u64>,
+
+        /// Shell command to run for each entry (entry JSON on stdin; QS_LOG_INDEX, QS_FILE_NAME,
+        /// QS_TIMESTAMP, QS_DIGEST, QS_KEY_ID in the environment) instead of printing it.
+        #[arg(long, value_name = "COMMAND")]
+        exec: Option<String>,
+
+        /// File recording the last seen index, so a restarted watch resumes where it stopped.
+        #[arg(long)]
+        state_file: Option<PathBuf>,
+
+        /// Print one JSON object per line instead of a summary line.
+        #[arg(long, conflicts_with = "exec")]
+        json: bool,
+    },
+
+    /// Add an inclusion proof into a witness-cosigned tree head to a signature file.
+    Prove {
+        /// Path to the signed SBOM file.
+        #[arg(value_name = "SBOM")]
+        sbom_path: PathBuf,
+
+        /// Path to the signature file (default: <SBOM>.sig).
+        #[arg(long)]
+        signature: Option<PathBuf>,
+
+        /// URL of the transparency log server (e.g. http://localhost:8080).
+        #[arg(long)]
+        server_url: String,
+
+        /// Log server public key; the tree head's signature is checked before the proof is saved.
+        #[arg(long)]
+        log_public_key: Option<PathBuf>,
     },
 }
 
+/// Filters for `log search`.
+#[derive(clap::Args)]
+struct SearchFilter {
+    /// Exact file name.
+    #[arg(long)]
+    file_name: Option<String>,
+
+    /// Signer key id (hex SHA3-256 of the public key).
+    #[arg(long)]
+    key_id: Option<String>,
+
+    /// Artifact digest (hex SHA3-256).
+    #[arg(long)]
+    digest: Option<String>,
+
+    /// Only entries at or after this RFC 3339 time.
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Only entries at or before this RFC 3339 time.
+    #[arg(long)]
+    until: Option<String>,
+
+    /// Skip this many matches.
+    #[arg(long, default_value_t = 0)]
+    offset: u64,
+
+    /// Page size (server maximum 1000).
+    #[arg(long)]
+    limit: Option<u64>,
+}
+
+impl From<SearchFilter> for ledger::EntryFilter {
+    fn from(f: SearchFilter) -> Self {
+        Self {
+            file_name: f.file_name,
+            key_id: f.key_id,
+            digest: f.digest,
+            since: f.since,
+            until: f.until,
+            from_index: None,
+            offset: f.offset,
+            limit: f.limit,
+        }
+    }
+}
+
+/// Upload outbox options shared by `sign` and `sign-all`.
+#[derive(clap::Args)]
+struct OutboxArgs {
+    /// Directory for queued uploads (default: <ledger>.outbox).
+    #[arg(long = "outbox")]
+    outbox_dir: Option<PathBuf>,
+
+    /// Seconds to spend uploading queued entries before exiting; the rest is left for `sync`.
+    #[arg(long, default_value_t = 10)]
+    upload_timeout: u64,
+
+    /// Upload synchronously and embed the server's signed inclusion receipt in each .sig
+    /// (entries that cannot be uploaded are queued and get no receipt).
+    #[arg(long, requires = "server_url")]
+    receipt: bool,
+}
+
+impl OutboxArgs {
+    fn dir(&self, ledger: &Path) -> PathBuf {
+        self.outbox_dir.clone().unwrap_or_else(|| outbox::default_dir(ledger))
+    }
+}
+
+#[derive(Subcommand)]
+enum LedgerCommands {
+    /// Copy all entries from one ledger into a new (empty) ledger, e.g. JSONL to SQLite.
+    Migrate {
+        /// Source ledger.
+        #[arg(long)]
+        from: PathBuf,
+
+        /// Destination ledger (must be empty or not exist).
+        #[arg(long)]
+        to: PathBuf,
+
+        /// Source backend (default: inferred from the file extension).
+        #[arg(long, value_enum)]
+        from_backend: Option<ledger::LedgerBackend>,
+
+        /// Destination backend (default: inferred from the file extension).
+        #[arg(long, value_enum)]
+        to_backend: Option<ledger::LedgerBackend>,
+    },
+
+    /// Check the hash chain and seals across all segments of a JSONL ledger.
+    Verify {
+        /// Path to the ledger (active segment; sealed archives are found next to it).
+        #[arg(long, default_value = "ledger.json")]
+        ledger: PathBuf,
+
+        /// Public key(s) allowed to sign seals; seal signatures are not checked if omitted.
+        #[arg(short = 'k', long = "public-key")]
+        public_keys: Vec<PathBuf>,
+    },
+
+    /// Upload local ledger entries that the central server does not have yet.
+    Push {
+        /// Path to the local ledger.
+        #[arg(long, default_value = "ledger.json")]
+        ledger: PathBuf,
+
+        /// URL of the transparency log server (e.g. http://localhost:8080).
+        #[arg(long)]
+        server_url: String,
+
+        /// Private key used to sign uploads (ignored if --kms is set).
+        #[arg(value_name = "PRIVATE_KEY", short, long, required_unless_present = "kms")]
+        private_key: Option<PathBuf>,
+
+        /// Use mock KMS instead of local key file (test only).
+        #[arg(long)]
+        kms: bool,
+
+        /// Only report what would be uploaded.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+fn key_provider_for(
+    kms: bool,
+    private_key: Option<&Path>,
+) -> anyhow::Result<Box<dyn key_provider::KeyProvider>> {
+    if kms {
+        return Ok(Box::new(key_provider::MockKmsProvider::new()));
+    }
+    let private_key =
+        private_key.ok_or_else(|| anyhow::anyhow!("A private key (-p) or --kms is required"))?;
+    Ok(Box::new(key_provider::FileSystemProvider::new(private_key)))
+}
+
+/// Key provider for `sign` and `sign-all`: a remote `--key`, or as `key_provider_for`.
+fn signing_key_provider(
+    kms: bool,
+    private_key: Option<&Path>,
+    key: Option<&str>,
+) -> anyhow::Result<Box<dyn key_provider::KeyProvider>> {
+    if let Some(uri) = key {
+        let token = std::env::var("QS_API_TOKEN")
+            .map_err(|_| anyhow::anyhow!("Remote signing needs an API token in QS_API_TOKEN"))?;
+        return Ok(Box::new(key_provider::RemoteProvider::from_uri(uri, token)?));
+    }
+    key_provider_for(kms, private_key)
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    client::configure_tls(&cli.tls)?;
     match cli.command {
         Commands::GenerateKeys { output_dir } => {
             let dir = output_dir.unwrap_or_else(|| PathBuf::from("."));
@@ -106,52 +440,242 @@ fn main() -> anyhow::Result<()> {
         }
         Commands::Sign {
             sbom_path,
+            lenient,
+            canonical,
+            embed,
+            format,
+            subjects,
             private_key,
+            key,
             kms,
             ledger,
+            rotation,
+            tsa_url,
             server_url,
+            outbox,
         } => {
-            let provider: Box<dyn key_provider::KeyProvider> = if kms {
-                Box::new(key_provider::MockKmsProvider::new())
-            } else {
-                Box::new(key_provider::FileSystemProvider::new(&private_key))
+            let provider = signing_key_provider(kms, private_key.as_deref(), key.as_deref())?;
+            let outbox_dir = outbox.dir(&ledger);
+            let opts = sign::SignOptions {
+                ledger_path: &ledger,
+                rotation: &rotation,
+                tsa_url: tsa_url.as_deref(),
+                upload: server_url.as_deref().map(|server_url| sign::Upload {
+                    server_url,
+                    outbox_dir: &outbox_dir,
+                    wait_for_receipt: outbox.receipt,
+                }),
             };
-            sign::run(
-                &sbom_path,
-                provider.as_ref(),
-                &ledger,
-                server_url.as_deref(),
-            )?;
+            let output = match format {
+                _ if embed => sign::Output::Embedded,
+                Some(sign::Format::Dsse) if canonical.is_some() => {
+                    return Err(anyhow::anyhow!(
+                        "--canonical applies to .sig files; a DSSE envelope signs its own payload"
+                    ));
+                }
+                Some(sign::Format::Dsse) => sign::Output::Attestation(subjects),
+                Some(sign::Format::Sig) if !subjects.is_empty() => {
+                    return Err(anyhow::anyhow!("--subject needs --format dsse"));
+                }
+                Some(sign::Format::Sig) | None => sign::Output::Detached(canonical),
+            };
+            sign::run(&sbom_path, provider.as_ref(), &opts, lenient, output)?;
             println!("Signed and ledger updated.");
+            if server_url.is_some() {
+                outbox::flush_before_exit(&outbox_dir, Duration::from_secs(outbox.upload_timeout));
+            }
         }
         Commands::Verify {
             sbom_path,
             signature_path,
+            embedded: _,
+            subjects,
             public_key,
             policy,
+            log_public_key,
+            registry,
         } => {
+            // Without a signature file, clap has made sure --embedded was given.
             verify::run(
                 &sbom_path,
-                &signature_path,
+                signature_path.as_deref(),
+                &subjects,
                 &public_key,
                 policy.as_deref(),
+                log_public_key.as_deref(),
+                registry.as_deref(),
             )?;
         }
         Commands::SignAll {
             dir,
             private_key,
+            key,
             kms,
             ledger,
+            rotation,
+            tsa_url,
             server_url,
+            outbox,
         } => {
-            let provider: Box<dyn key_provider::KeyProvider> = if kms {
-                Box::new(key_provider::MockKmsProvider::new())
-            } else {
-                Box::new(key_provider::FileSystemProvider::new(&private_key))
+            let provider = signing_key_provider(kms, private_key.as_deref(), key.as_deref())?;
+            let outbox_dir = outbox.dir(&ledger);
+            let opts = sign::SignOptions {
+                ledger_path: &ledger,
+                rotation: &rotation,
+                tsa_url: tsa_url.as_deref(),
+                upload: server_url.as_deref().map(|server_url| sign::Upload {
+                    server_url,
+                    outbox_dir: &outbox_dir,
+                    wait_for_receipt: outbox.receipt,
+                }),
             };
-            sign_all::run(&dir, provider.as_ref(), &ledger, server_url.as_deref())?;
+            sign_all::run(&dir, provider.as_ref(), &opts)?;
             println!("Signed all files and manifest.");
+            if server_url.is_some() {
+                outbox::flush_before_exit(&outbox_dir, Duration::from_secs(outbox.upload_timeout));
+            }
+        }
+        Commands::Sync {
+            ledger,
+            outbox: outbox_dir,
+            timeout,
+        } => {
+            let dir = outbox_dir.unwrap_or_else(|| outbox::default_dir(&ledger));
+            let report = outbox::flush(&dir, Duration::from_secs(timeout), true)?;
+            for item in &report.rejected {
+                eprintln!(
+                    "Rejected: {} ({}): {}",
+                    item.upload.entry.file_name,
+                    item.server_url,
+                    item.last_error.as_deref().unwrap_or_default()
+                );
+            }
+            println!(
+                "{} uploaded, {} failed, {} rejected, {} still queued",
+                report.sent,
+                report.failed,
+                report.rejected.len(),
+                report.remaining()
+            );
+            if !report.rejected.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "{} upload(s) were rejected by the server and moved to {}",
+                    report.rejected.len(),
+                    outbox::rejected_dir(&dir).display()
+                ));
+            }
+            if report.remaining() > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} upload(s) remain in {}",
+                    report.remaining(),
+                    dir.display()
+                ));
+            }
         }
+        Commands::Ledger { command } => match command {
+            LedgerCommands::Migrate {
+                from,
+                to,
+                from_backend,
+                to_backend,
+            } => {
+                let n = ledger::migrate(&from, from_backend, &to, to_backend)?;
+                println!("Migrated {} entries from {} to {}", n, from.display(), to.display());
+            }
+            LedgerCommands::Verify {
+                ledger,
+                public_keys,
+            } => {
+                let keys = public_keys
+                    .iter()
+                    .map(|p| crypto::load_public_key(p))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let report = ledger::verify_chain(&ledger, &keys)?;
+                println!(
+                    "Ledger chain intact: {} entries, {} sealed segments, head {}",
+                    report.total_entries, report.sealed_segments, report.head
+                );
+            }
+            LedgerCommands::Push {
+                ledger,
+                server_url,
+                private_key,
+                kms,
+                dry_run,
+            } => {
+                let provider = key_provider_for(kms, private_key.as_deref())?;
+                let report = push::run(&ledger, &server_url, provider.as_ref(), dry_run)?;
+                let verb = if dry_run { "Would upload" } else { "Uploaded" };
+                for entry in &report.uploaded {
+                    println!(
+                        "{}: {} ({})",
+                        verb,
+                        entry.file_name,
+                        hex::encode(ledger::leaf_hash(entry))
+                    );
+                }
+                for (entry, error) in &report.failed {
+                    eprintln!(
+                        "Failed: {} ({}): {}",
+                        entry.file_name,
+                        hex::encode(ledger::leaf_hash(entry)),
+                        error
+                    );
+                }
+                println!(
+                    "{} entries checked, {} already on server, {} {}, {} failed",
+                    report.checked,
+                    report.already_present,
+                    report.uploaded.len(),
+                    if dry_run { "missing" } else { "uploaded" },
+                    report.failed.len()
+                );
+                if !report.failed.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "{} entries could not be pushed; re-run ledger push once the cause is fixed",
+                        report.failed.len()
+                    ));
+                }
+            }
+        },
+        Commands::Log { command } => match command {
+            LogCommands::Search {
+                server_url,
+                index,
+                filter,
+                all,
+                json,
+            } => {
+                log::search(&server_url, index, filter.into(), all, json)?;
+            }
+            LogCommands::Watch {
+                server_url,
+                key_id,
+                after,
+                exec,
+                state_file,
+                json,
+            } => {
+                let opts = log::WatchOptions {
+                    key_id: key_id.as_deref(),
+                    after,
+                    exec: exec.as_deref(),
+                    state_file: state_file.as_deref(),
+                    json,
+                };
+                log::watch(&server_url, &opts)?;
+            }
+            LogCommands::Prove {
+                sbom_path,
+                signature,
+                server_url,
+                log_public_key,
+            } => {
+                let sig_path = signature.unwrap_or_else(|| sign::sig_path_for(&sbom_path));
+                let log_pk = log_public_key.map(|p| crypto::load_public_key(&p)).transpose()?;
+                log::prove(&server_url, &sbom_path, &sig_path, log_pk.as_ref())?;
+            }
+        },
     }
     Ok(())
 }
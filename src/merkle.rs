@@ -0,0 +1,316 @@
+//! Merkle tree over the log's leaf hashes (RFC 6962 / RFC 9162 shape, with SHA3-256): tree roots,
+//! inclusion proofs and consistency proofs.
+//!
+//! Leaves are the entries' leaf hashes (`ledger::leaf_hash`, SHA3-256 of the entry JSON); interior
+//! nodes are SHA3-256(0x01 || left || right). Entry JSON never starts with 0x01, so a leaf can
+//! never be passed off as an interior node.
+
+use sha3::{Digest, Sha3_256};
+
+pub type Hash = [u8; 32];
+
+/// Interior node hash.
+fn node(left: &Hash, right: &Hash) -> Hash {
+    let mut h = Sha3_256::new();
+    h.update([0x01]);
+    h.update(left);
+    h.update(right);
+    h.finalize().into()
+}
+
+/// Largest power of two strictly below `n` (`n` > 1).
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k << 1 < n {
+        k <<= 1;
+    }
+    k
+}
+
+/// Root of the tree over `leaves`; the empty tree's root is SHA3-256 of the empty string.
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha3_256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// Audit path proving that leaf `index` is in the tree over `leaves`.
+pub fn inclusion_proof(leaves: &[Hash], index: usize) -> Vec<Hash> {
+    let n = leaves.len();
+    if n <= 1 || index >= n {
+        return Vec::new();
+    }
+    let k = split(n);
+    let (mut proof, sibling) = if index < k {
+        (inclusion_proof(&leaves[..k], index), root(&leaves[k..]))
+    } else {
+        (inclusion_proof(&leaves[k..], index - k), root(&leaves[..k]))
+    };
+    proof.push(sibling);
+    proof
+}
+
+/// Check an inclusion proof for `leaf` at `index` in a tree of `size` leaves with root `root`.
+pub fn verify_inclusion(leaf: &Hash, index: u64, size: u64, proof: &[Hash], root: &Hash) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut fnode, mut snode) = (index, size - 1);
+    let mut r = *leaf;
+    for p in proof {
+        if snode == 0 {
+            return false;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            r = node(p, &r);
+            while fnode & 1 == 0 && fnode != 0 {
+                fnode >>= 1;
+                snode >>= 1;
+            }
+        } else {
+            r = node(&r, p);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    snode == 0 && r == *root
+}
+
+/// Proof that the tree over `leaves[..old_size]` is a prefix of the tree over `leaves`.
+pub fn consistency_proof(leaves: &[Hash], old_size: usize) -> Vec<Hash> {
+    if old_size == 0 || old_size >= leaves.len() {
+        return Vec::new();
+    }
+    subproof(old_size, leaves, true)
+}
+
+fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+    let n = leaves.len();
+    if m == n {
+        return if complete { Vec::new() } else { vec![root(leaves)] };
+    }
+    let k = split(n);
+    let (mut proof, sibling) = if m <= k {
+        (subproof(m, &leaves[..k], complete), root(&leaves[k..]))
+    } else {
+        (subproof(m - k, &leaves[k..], false), root(&leaves[..k]))
+    };
+    proof.push(sibling);
+    proof
+}
+
+/// Check that the tree of `old_size` leaves with root `old_root` is a prefix of the tree of
+/// `new_size` leaves with root `new_root`.
+pub fn verify_consistency(
+    old_size: u64,
+    new_size: u64,
+    old_root: &Hash,
+    new_root: &Hash,
+    proof: &[Hash],
+) -> bool {
+    if old_size > new_size {
+        return false;
+    }
+    if old_size == new_size {
+        return proof.is_empty() && old_root == new_root;
+    }
+    if old_size == 0 {
+        return proof.is_empty();
+    }
+    let mut path: Vec<Hash> = Vec::with_capacity(proof.len() + 1);
+    if old_size.is_power_of_two() {
+        path.push(*old_root);
+    }
+    path.extend_from_slice(proof);
+    let Some((first, rest)) = path.split_first() else {
+        return false;
+    };
+    let (mut fnode, mut snode) = (old_size - 1, new_size - 1);
+    while fnode & 1 == 1 {
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    let (mut fr, mut sr) = (*first, *first);
+    for c in rest {
+        if snode == 0 {
+            return false;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            fr = node(c, &fr);
+            sr = node(c, &sr);
+            while fnode & 1 == 0 && fnode != 0 {
+                fnode >>= 1;
+                snode >>= 1;
+            }
+        } else {
+            sr = node(&sr, c);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    fr == *old_root && sr == *new_root && snode == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The leaf inputs of the RFC 6962 reference tests (certificate-transparency `merkle_tree_test`),
+    /// hashed the way this log hashes its leaves.
+    const INPUTS: [&str; 8] = [
+        "",
+        "00",
+        "10",
+        "2021",
+        "3031",
+        "40414243",
+        "5051525354555657",
+        "606162636465666768696a6b6c6d6e6f",
+    ];
+
+    /// Roots over the first n leaves, computed independently from the RFC 6962 MTH definition
+    /// with SHA3-256 and the 0x01 node prefix.
+    const ROOTS: [&str; 9] = [
+        "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+        "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+        "d6b6cfe3168480d32634180f1d773ebafd1c3c98fbaf2d086aa6b42e39bc48dc",
+        "55fe003f48c77cb9fbbfbb11bd059f5e1c326821859de5ce898de55c34b76bf7",
+        "cde49a0b152185a17bca8f6395b930c1d19be102f139b3cbbe904fa20054755e",
+        "e2bcf59914a45e7fef1493162c6074e05c94f45800b1a39c1667888f8913c972",
+        "bbe89ad3d1d552287c243a5c92120d37fc7dc954d813476fb5dde10e66bcab2a",
+        "fe556ce695ca35d10e2593bd6547c1e7d12c5da3d3f85f1dafa4918985f2e8df",
+        "33d73a20562e5fd0de0ec5fee8fd5a7bce2c8b05321fa4bd94f4143d88e4328a",
+    ];
+
+    fn leaves() -> Vec<Hash> {
+        INPUTS
+            .iter()
+            .map(|i| Sha3_256::digest(hex::decode(i).unwrap()).into())
+            .collect()
+    }
+
+    fn flip(hash: &Hash) -> Hash {
+        let mut h = *hash;
+        h[0] ^= 1;
+        h
+    }
+
+    #[test]
+    fn known_answer_roots() {
+        let leaves = leaves();
+        for (n, expected) in ROOTS.iter().enumerate() {
+            assert_eq!(hex::encode(root(&leaves[..n])), *expected, "size {}", n);
+        }
+        // The shape is RFC 6962's: 7 leaves split 4 + 3, then 2 + 1.
+        let l = &leaves;
+        let left = node(&node(&l[0], &l[1]), &node(&l[2], &l[3]));
+        let right = node(&node(&l[4], &l[5]), &l[6]);
+        assert_eq!(root(&l[..7]), node(&left, &right));
+    }
+
+    #[test]
+    fn inclusion_proofs_round_trip() {
+        let leaves = leaves();
+        for size in 1..=8 {
+            let tree = &leaves[..size];
+            let r = root(tree);
+            for index in 0..size {
+                let proof = inclusion_proof(tree, index);
+                assert!(
+                    verify_inclusion(&tree[index], index as u64, size as u64, &proof, &r),
+                    "index {} size {}",
+                    index,
+                    size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_inclusion_proofs_fail() {
+        let leaves = leaves();
+        for size in 1..=8 {
+            let tree = &leaves[..size];
+            let r = root(tree);
+            for index in 0..size {
+                let (i, n) = (index as u64, size as u64);
+                let proof = inclusion_proof(tree, index);
+                let leaf = &tree[index];
+                assert!(!verify_inclusion(leaf, i, n, &proof, &flip(&r)));
+                assert!(!verify_inclusion(&flip(leaf), i, n, &proof, &r));
+                assert!(!verify_inclusion(leaf, n, n, &proof, &r));
+                for k in 0..proof.len() {
+                    let mut bad = proof.clone();
+                    bad[k] = flip(&bad[k]);
+                    assert!(!verify_inclusion(leaf, i, n, &bad, &r), "element {} of {}/{}", k, index, size);
+                }
+                if !proof.is_empty() {
+                    assert!(!verify_inclusion(leaf, i, n, &proof[..proof.len() - 1], &r));
+                }
+                let mut longer = proof.clone();
+                longer.push(r);
+                assert!(!verify_inclusion(leaf, i, n, &longer, &r));
+                // The proof only fits the position it was made for.
+                for other in (0..size).filter(|&o| o != index) {
+                    assert!(!verify_inclusion(leaf, other as u64, n, &proof, &r));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn consistency_proofs_round_trip() {
+        let leaves = leaves();
+        for new in 1..=8 {
+            let new_root = root(&leaves[..new]);
+            for old in 0..=new {
+                let proof = consistency_proof(&leaves[..new], old);
+                assert!(
+                    verify_consistency(old as u64, new as u64, &root(&leaves[..old]), &new_root, &proof),
+                    "{} -> {}",
+                    old,
+                    new
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tampered_consistency_proofs_fail() {
+        let leaves = leaves();
+        for new in 1..=8 {
+            let new_root = root(&leaves[..new]);
+            for old in 1..=new {
+                let (m, n) = (old as u64, new as u64);
+                let old_root = root(&leaves[..old]);
+                let proof = consistency_proof(&leaves[..new], old);
+                assert!(!verify_consistency(m, n, &flip(&old_root), &new_root, &proof));
+                assert!(!verify_consistency(m, n, &old_root, &flip(&new_root), &proof));
+                for k in 0..proof.len() {
+                    let mut bad = proof.clone();
+                    bad[k] = flip(&bad[k]);
+                    assert!(!verify_consistency(m, n, &old_root, &new_root, &bad), "element {} of {} -> {}", k, old, new);
+                }
+                if !proof.is_empty() {
+                    assert!(!verify_consistency(m, n, &old_root, &new_root, &proof[..proof.len() - 1]));
+                }
+                let mut longer = proof.clone();
+                longer.push(new_root);
+                assert!(!verify_consistency(m, n, &old_root, &new_root, &longer));
+                if old < new {
+                    // A shrinking tree is never consistent.
+                    assert!(!verify_consistency(n, m, &new_root, &old_root, &proof));
+                    // Nor is a different history of the same length.
+                    let mut forked = leaves[..new].to_vec();
+                    forked[0] = flip(&forked[0]);
+                    assert!(!verify_consistency(m, n, &old_root, &root(&forked), &proof));
+                }
+            }
+        }
+    }
+}
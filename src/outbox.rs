@@ -0,0 +1,359 @@
+//! Durable upload outbox: ledger entries waiting to be sent to a transparency log server.
+//!
+//! Each pending upload is one JSON file in the outbox directory (default: `<ledger>.outbox/`),
+//! so concurrent signers never contend on a shared file. Delivered items are deleted; failed
+//! items record their attempt count and the earliest time of the next attempt (exponential backoff).
+//! Items the server refuses outright (e.g. **403** for a key it does not accept) are never retried:
+//! they are moved to the `rejected/` subdirectory for inspection.
+
+use crate::client;
+use crate::ledger;
+use crate::protocol::SignedUpload;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Tries per item within one flush before it is left for a later run.
+const TRIES_PER_FLUSH: u32 = 3;
+/// First in-run retry delay; doubled on each further try.
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+/// Upper bound for the persisted backoff between runs.
+const MAX_BACKOFF_SECS: i64 = 3600;
+/// Subdirectory of the outbox that refused items are moved to.
+const REJECTED_DIR: &str = "rejected";
+
+/// One pending upload, signed when queued so `sync` needs no key access.
+#[derive(Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub server_url: String,
+    pub upload: SignedUpload,
+    #[serde(default)]
+    pub attempts: u32,
+    /// RFC 3339; the item is skipped by `flush` (but not `sync`) until then.
+    #[serde(default)]
+    pub next_attempt_at: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Outcome of a flush.
+#[derive(Default)]
+pub struct FlushReport {
+    pub sent: usize,
+    pub failed: usize,
+    /// Items skipped because their backoff has not elapsed or the deadline was reached.
+    pub deferred: usize,
+    /// Items the server refused outright, now in the `rejected/` subdirectory; `last_error` says why.
+    pub rejected: Vec<OutboxItem>,
+}
+
+impl FlushReport {
+    pub fn remaining(&self) -> usize {
+        self.failed + self.deferred
+    }
+}
+
+/// Default outbox directory for a ledger: `<ledger>.outbox` next to it.
+pub fn default_dir(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_owned();
+    name.push(".outbox");
+    PathBuf::from(name)
+}
+
+fn item_path(dir: &Path, server_url: &str, upload: &SignedUpload) -> PathBuf {
+    let url_hash = hex::encode(Sha3_256::digest(client::base_url(server_url).as_bytes()));
+    let leaf = hex::encode(ledger::leaf_hash(&upload.entry));
+    dir.join(format!("{}-{}.json", leaf, &url_hash[..8]))
+}
+
+/// Write via a temporary file and rename, so a crash never leaves a half-written item.
+fn write_item(path: &Path, item: &OutboxItem) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(item)?)
+        .map_err(|e| anyhow::anyhow!("Failed to write outbox item {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, path)
+        .map_err(|e| anyhow::anyhow!("Failed to write outbox item {}: {}", path.display(), e))
+}
+
+/// Queue a signed entry for upload to `server_url`. Queuing the same entry twice is a no-op.
+pub fn enqueue(dir: &Path, server_url: &str, upload: &SignedUpload) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to create outbox {}: {}", dir.display(), e))?;
+    let path = item_path(dir, server_url, upload);
+    if path.exists() {
+        return Ok(());
+    }
+    write_item(
+        &path,
+        &OutboxItem {
+            server_url: client::base_url(server_url),
+            upload: upload.clone(),
+            attempts: 0,
+            next_attempt_at: None,
+            last_error: None,
+        },
+    )
+}
+
+/// Directory that items the server refused are moved to.
+pub fn rejected_dir(dir: &Path) -> PathBuf {
+    dir.join(REJECTED_DIR)
+}
+
+/// Pending items in the outbox (oldest file first).
+pub fn pending(dir: &Path) -> anyhow::Result<Vec<(PathBuf, OutboxItem)>> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read outbox {}: {}", dir.display(), e)),
+    };
+    let mut paths: Vec<(std::time::SystemTime, PathBuf)> = read_dir
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+        .map(|p| {
+            let modified = std::fs::metadata(&p)
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::UNIX_EPOCH);
+            (modified, p)
+        })
+        .collect();
+    paths.sort();
+    let mut items = Vec::new();
+    for (_, path) in paths {
+        // Another process may have delivered and removed the item meanwhile.
+        let Ok(bytes) = std::fs::read(&path) else {
+            continue;
+        };
+        let item: OutboxItem = serde_json::from_slice(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid outbox item {}: {}", path.display(), e))?;
+        items.push((path, item));
+    }
+    Ok(items)
+}
+
+fn backoff_elapsed(item: &OutboxItem) -> bool {
+    item.next_attempt_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_none_or(|t| chrono::Utc::now() >= t)
+}
+
+/// Try to deliver queued items until `deadline` has passed. Each item gets a few tries with a
+/// short doubling delay; items that still fail are kept with an exponential backoff before the
+/// next run, and items the server refuses outright are moved to the `rejected/` subdirectory.
+/// With `force`, the persisted backoff is ignored (used by `sync`).
+pub fn flush(dir: &Path, deadline: Duration, force: bool) -> anyhow::Result<FlushReport> {
+    let start = Instant::now();
+    let mut report = FlushReport::default();
+    for (path, mut item) in pending(dir)? {
+        if start.elapsed() >= deadline || !(force || backoff_elapsed(&item)) {
+            report.deferred += 1;
+            continue;
+        }
+        let mut delay = RETRY_DELAY;
+        let mut result = client::upload(&item.server_url, &item.upload);
+        for _ in 1..TRIES_PER_FLUSH {
+            let rejected = matches!(&result, Err(e) if e.is::<client::Rejected>());
+            if result.is_ok() || rejected || start.elapsed() + delay >= deadline {
+                break;
+            }
+            std::thread::sleep(delay);
+            delay *= 2;
+            result = client::upload(&item.server_url, &item.upload);
+        }
+        match result {
+            Ok(_) => {
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(anyhow::anyhow!(
+                            "Failed to remove outbox item {}: {}",
+                            path.display(),
+                            e
+                        ))
+                    }
+                }
+                report.sent += 1;
+            }
+            Err(e) if e.is::<client::Rejected>() => {
+                item.attempts += 1;
+                item.next_attempt_at = None;
+                item.last_error = Some(e.to_string());
+                let rejected = rejected_dir(dir);
+                std::fs::create_dir_all(&rejected).map_err(|e| {
+                    anyhow::anyhow!("Failed to create {}: {}", rejected.display(), e)
+                })?;
+                write_item(&rejected.join(path.file_name().unwrap_or_default()), &item)?;
+                std::fs::remove_file(&path).map_err(|e| {
+                    anyhow::anyhow!("Failed to remove outbox item {}: {}", path.display(), e)
+                })?;
+                report.rejected.push(item);
+            }
+            Err(e) => {
+                item.attempts += 1;
+                let backoff = (1i64 << item.attempts.min(12)).min(MAX_BACKOFF_SECS);
+                item.next_attempt_at =
+                    Some((chrono::Utc::now() + chrono::Duration::seconds(backoff)).to_rfc3339());
+                item.last_error = Some(e.to_string());
+                write_item(&path, &item)?;
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Flush before a sign command exits; failures only produce a warning since the items stay queued
+/// (or, if refused, in the `rejected/` subdirectory).
+pub fn flush_before_exit(dir: &Path, deadline: Duration) {
+    match flush(dir, deadline, false) {
+        Ok(report) => {
+            for item in &report.rejected {
+                eprintln!(
+                    "Warning: {} rejected {}: {}",
+                    item.server_url,
+                    item.upload.entry.file_name,
+                    item.last_error.as_deref().unwrap_or_default()
+                );
+            }
+            if !report.rejected.is_empty() {
+                eprintln!(
+                    "Warning: {} rejected upload(s) moved to {}",
+                    report.rejected.len(),
+                    rejected_dir(dir).display()
+                );
+            }
+            if report.remaining() > 0 {
+                eprintln!(
+                    "Warning: {} upload(s) still pending in {}; run `qs_notary sync` to retry",
+                    report.remaining(),
+                    dir.display()
+                );
+            }
+        }
+        Err(e) => eprintln!("Warning: could not flush upload outbox: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, StubLog};
+    use axum::http::StatusCode;
+    use std::sync::Arc;
+
+    /// An outbox in a scratch directory with uploads of `names` queued for `server_url`.
+    fn queued(name: &str, server_url: &str, names: &[&str]) -> PathBuf {
+        let dir = testing::scratch_dir(name);
+        let key = testing::key(&dir);
+        let outbox = dir.join("ledger.json.outbox");
+        for name in names {
+            let upload = SignedUpload::sign(testing::entry(name), &key).unwrap();
+            enqueue(&outbox, server_url, &upload).unwrap();
+            // Queued twice, kept once.
+            enqueue(&outbox, server_url, &upload).unwrap();
+        }
+        outbox
+    }
+
+    fn next_attempt_in_secs(item: &OutboxItem) -> i64 {
+        let at =
+            chrono::DateTime::parse_from_rfc3339(item.next_attempt_at.as_deref().unwrap()).unwrap();
+        (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds()
+    }
+
+    #[test]
+    fn delivered_items_are_removed() {
+        let log = Arc::new(StubLog::default());
+        let url = testing::serve(log.clone());
+        let outbox = queued("outbox_delivered", &url, &["a.json", "b.json"]);
+        assert_eq!(pending(&outbox).unwrap().len(), 2);
+
+        let report = flush(&outbox, Duration::from_secs(10), false).unwrap();
+        assert_eq!(report.sent, 2);
+        assert_eq!(report.remaining(), 0);
+        assert!(pending(&outbox).unwrap().is_empty());
+        assert_eq!(log.uploads(), 2);
+    }
+
+    #[test]
+    fn failed_items_back_off_until_forced() {
+        let log = Arc::new(StubLog::default());
+        log.refuse("a.json", StatusCode::SERVICE_UNAVAILABLE);
+        let url = testing::serve(log.clone());
+        let outbox = queued("outbox_backoff", &url, &["a.json"]);
+
+        let report = flush(&outbox, Duration::from_secs(10), false).unwrap();
+        assert_eq!((report.sent, report.failed), (0, 1));
+        assert_eq!(log.uploads(), TRIES_PER_FLUSH as usize);
+        let (_, item) = pending(&outbox).unwrap().pop().unwrap();
+        assert_eq!(item.attempts, 1);
+        assert!((1..=2).contains(&next_attempt_in_secs(&item)));
+        assert!(item.last_error.unwrap().contains("503"));
+
+        // Not due yet: skipped without a request.
+        let report = flush(&outbox, Duration::from_secs(10), false).unwrap();
+        assert_eq!((report.failed, report.deferred), (0, 1));
+        assert_eq!(log.uploads(), TRIES_PER_FLUSH as usize);
+
+        // `force` ignores the backoff, which doubles after the next failure.
+        let report = flush(&outbox, Duration::from_secs(10), true).unwrap();
+        assert_eq!(report.failed, 1);
+        let (_, item) = pending(&outbox).unwrap().pop().unwrap();
+        assert_eq!(item.attempts, 2);
+        assert!((3..=4).contains(&next_attempt_in_secs(&item)));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let log = Arc::new(StubLog::default());
+        log.refuse("a.json", StatusCode::SERVICE_UNAVAILABLE);
+        let url = testing::serve(log);
+        let outbox = queued("outbox_backoff_cap", &url, &["a.json"]);
+        let (path, mut item) = pending(&outbox).unwrap().pop().unwrap();
+        item.attempts = 40;
+        write_item(&path, &item).unwrap();
+
+        flush(&outbox, Duration::from_secs(10), true).unwrap();
+        let (_, item) = pending(&outbox).unwrap().pop().unwrap();
+        assert_eq!(item.attempts, 41);
+        assert!((MAX_BACKOFF_SECS - 1..=MAX_BACKOFF_SECS).contains(&next_attempt_in_secs(&item)));
+    }
+
+    #[test]
+    fn deadline_defers_remaining_items() {
+        let log = Arc::new(StubLog::default());
+        let url = testing::serve(log.clone());
+        let outbox = queued("outbox_deadline", &url, &["a.json", "b.json"]);
+
+        let report = flush(&outbox, Duration::ZERO, true).unwrap();
+        assert_eq!((report.sent, report.deferred), (0, 2));
+        assert_eq!(log.uploads(), 0);
+        assert_eq!(pending(&outbox).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn refused_items_are_moved_aside_without_retrying() {
+        let log = Arc::new(StubLog::default());
+        log.refuse("a.json", StatusCode::FORBIDDEN);
+        let url = testing::serve(log.clone());
+        let outbox = queued("outbox_rejected", &url, &["a.json", "b.json"]);
+
+        let report = flush(&outbox, Duration::from_secs(10), true).unwrap();
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.remaining(), 0);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].upload.entry.file_name, "a.json");
+        assert!(report.rejected[0].last_error.as_deref().unwrap().contains("403"));
+        // One request for each item: a refusal is not retried.
+        assert_eq!(log.uploads(), 2);
+
+        assert!(pending(&outbox).unwrap().is_empty());
+        let rejected = pending(&rejected_dir(&outbox)).unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].1.upload.entry.file_name, "a.json");
+    }
+}
@@ -1,4 +1,4 @@
-//! Policy engine for verification rules (allowlist, max age).
+//! Policy engine for verification rules (allowlist, trusted timestamps, max age, witnesses).
 
 use serde::Deserialize;
 use std::path::Path;
@@ -14,6 +14,18 @@ pub struct Policy {
 
     /// Hex-encoded public key bytes; verification key must be in this list if set.
     pub allowed_public_keys: Option<Vec<String>>,
+
+    /// Hex-encoded public keys of trusted timestamp authorities. If set, the signature must carry a
+    /// timestamp token from one of them, and max_age_days is measured from the token's time.
+    pub trusted_tsa_keys: Option<Vec<String>>,
+
+    /// Hex-encoded public keys of trusted witnesses. If set, the signature file must carry an
+    /// inclusion proof (`log prove`) into a tree head cosigned by at least `min_witnesses` of them;
+    /// checking it needs the log's public key.
+    pub witness_keys: Option<Vec<String>>,
+
+    /// Number of distinct witnesses from witness_keys that must have cosigned (default 1).
+    pub min_witnesses: Option<usize>,
 }
 
 impl Policy {
@@ -0,0 +1,729 @@
+//! Wire types shared by the CLI and qs_server.
+
+use crate::crypto::{key_id, load_signature, verify_signature};
+use crate::key_provider::KeyProvider;
+use crate::ledger::{leaf_hash, KeyRevocation, LedgerEntry, SealBody};
+use crate::merkle;
+use pqcrypto_dilithium::dilithium5::PublicKey;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+/// Domain separator for upload signatures, so they cannot be mistaken for receipts, timestamp
+/// tokens or other signed messages.
+const UPLOAD_CONTEXT: &[u8] = b"qs_notary/upload/v2\n";
+
+/// Body of `POST /upload`: a ledger entry signed by the uploader's enrolled key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignedUpload {
+    pub entry: LedgerEntry,
+    /// Key id of the uploading key (hex SHA3-256 of its public key).
+    pub key_id: String,
+    /// Hex Dilithium5 signature over `upload_message(entry)`.
+    pub signature: String,
+}
+
+/// A context string followed by the JSON of `value`. Signed as is, never hashed first: every such
+/// message is longer than 32 bytes, so no signature over an artifact digest (which a signing
+/// service makes for any digest its callers send) can pass for one.
+fn context_message(context: &[u8], value: &impl Serialize) -> anyhow::Result<Vec<u8>> {
+    let mut message = context.to_vec();
+    message.extend(serde_json::to_vec(value)?);
+    Ok(message)
+}
+
+/// Check a hex Dilithium5 signature over `message`.
+fn verify_hex(signature: &str, message: &[u8], pk: &PublicKey) -> anyhow::Result<()> {
+    let sig_bytes =
+        hex::decode(signature).map_err(|e| anyhow::anyhow!("Invalid signature hex: {}", e))?;
+    verify_signature(&load_signature(&sig_bytes)?, message, pk)
+}
+
+/// Message signed for an upload: the context string followed by the entry JSON.
+pub fn upload_message(entry: &LedgerEntry) -> anyhow::Result<Vec<u8>> {
+    context_message(UPLOAD_CONTEXT, entry)
+}
+
+impl SignedUpload {
+    /// Sign `entry` for upload. The provider must know its key id (see `KeyProvider::key_id`).
+    pub fn sign(entry: LedgerEntry, key_provider: &dyn KeyProvider) -> anyhow::Result<Self> {
+        let key_id = key_provider.key_id().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Uploads must be signed, but the signing key id is unknown (place the public key next to the private key)"
+            )
+        })?;
+        let signature = hex::encode(key_provider.sign(&upload_message(&entry)?)?);
+        Ok(Self {
+            entry,
+            key_id,
+            signature,
+        })
+    }
+
+    /// Check the upload signature against the uploader's public key.
+    pub fn verify(&self, pk: &PublicKey) -> anyhow::Result<()> {
+        verify_hex(&self.signature, &upload_message(&self.entry)?, pk)
+    }
+}
+
+/// A ledger entry with its 0-based position in the log.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexedEntry {
+    pub index: u64,
+    pub entry: LedgerEntry,
+}
+
+/// Response of `GET /entries` and `GET /entries/by-digest/{hex}`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntryPage {
+    pub entries: Vec<IndexedEntry>,
+    /// Offset of the next page, if there may be more matches.
+    pub next_offset: Option<u64>,
+}
+
+/// Domain separator for inclusion receipts issued by the log server.
+const RECEIPT_CONTEXT: &[u8] = b"qs_notary/receipt/v2\n";
+
+/// What the log server attests in a receipt.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReceiptBody {
+    /// 0-based position of the entry in the server's log.
+    pub log_index: u64,
+    /// RFC 3339 time at which the server integrated the entry (for a repeated upload of an entry
+    /// it already held: the time it confirmed it).
+    pub integrated_time: String,
+    /// Hex leaf hash of the logged entry (see `ledger::leaf_hash`).
+    pub leaf_hash: String,
+    /// Key id of the server's signing key.
+    pub log_key_id: String,
+}
+
+impl ReceiptBody {
+    /// Message signed by the server: the context string followed by the body JSON.
+    pub fn message(&self) -> anyhow::Result<Vec<u8>> {
+        context_message(RECEIPT_CONTEXT, self)
+    }
+}
+
+/// Response of `POST /upload`: a signed promise that the entry is in the log, checkable offline
+/// with the server's public key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Receipt {
+    #[serde(flatten)]
+    pub body: ReceiptBody,
+    /// Hex Dilithium5 signature over `body.message()`.
+    pub signature: String,
+}
+
+impl Receipt {
+    pub fn sign(body: ReceiptBody, key_provider: &dyn KeyProvider) -> anyhow::Result<Self> {
+        let signature = hex::encode(key_provider.sign(&body.message()?)?);
+        Ok(Self { body, signature })
+    }
+
+    /// Check that the receipt was issued by the server with public key `pk` for `entry`.
+    pub fn verify(&self, entry: &LedgerEntry, pk: &PublicKey) -> anyhow::Result<()> {
+        if !self.body.log_key_id.eq_ignore_ascii_case(&key_id(pk)) {
+            return Err(anyhow::anyhow!("Receipt was issued by a different log key"));
+        }
+        if !self.body.leaf_hash.eq_ignore_ascii_case(&hex::encode(leaf_hash(entry))) {
+            return Err(anyhow::anyhow!("Receipt does not match the logged entry"));
+        }
+        verify_hex(&self.signature, &self.body.message()?, pk)
+            .map_err(|_| anyhow::anyhow!("Invalid receipt signature"))
+    }
+}
+
+/// Domain separator for timestamp tokens issued by the server's timestamp authority.
+const TIMESTAMP_CONTEXT: &[u8] = b"qs_notary/timestamp/v2\n";
+
+/// Body of `POST /timestamp`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimestampRequest {
+    /// Hex SHA3-256 digest to timestamp (`sign` sends the digest of its signature bytes).
+    pub digest: String,
+}
+
+/// What the timestamp authority attests: this digest existed at this time.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimestampBody {
+    pub digest: String,
+    /// RFC 3339 time taken from the server's clock.
+    pub time: String,
+    /// Unique, increasing token number.
+    pub serial: u64,
+    /// Key id of the timestamp authority's signing key.
+    pub tsa_key_id: String,
+}
+
+impl TimestampBody {
+    /// Message signed by the timestamp authority: the context string followed by the body JSON.
+    pub fn message(&self) -> anyhow::Result<Vec<u8>> {
+        context_message(TIMESTAMP_CONTEXT, self)
+    }
+}
+
+/// Response of `POST /timestamp`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimestampToken {
+    #[serde(flatten)]
+    pub body: TimestampBody,
+    /// Hex Dilithium5 signature over `body.message()`.
+    pub signature: String,
+}
+
+impl TimestampToken {
+    pub fn sign(body: TimestampBody, key_provider: &dyn KeyProvider) -> anyhow::Result<Self> {
+        let signature = hex::encode(key_provider.sign(&body.message()?)?);
+        Ok(Self { body, signature })
+    }
+
+    /// Check that the token was issued with public key `pk` for `digest`.
+    pub fn verify(&self, digest: &[u8], pk: &PublicKey) -> anyhow::Result<()> {
+        if !self.body.tsa_key_id.eq_ignore_ascii_case(&key_id(pk)) {
+            return Err(anyhow::anyhow!("Timestamp token was issued by a different key"));
+        }
+        if !self.body.digest.eq_ignore_ascii_case(&hex::encode(digest)) {
+            return Err(anyhow::anyhow!("Timestamp token is for a different digest"));
+        }
+        verify_hex(&self.signature, &self.body.message()?, pk)
+            .map_err(|_| anyhow::anyhow!("Invalid timestamp token signature"))
+    }
+}
+
+/// Domain separator for artifact signatures that cover signed attributes.
+const ARTIFACT_CONTEXT: &[u8] = b"qs_notary/artifact/v1\n";
+
+/// Facts about the artifact that the signature covers along with its digest: for an SBOM, the
+/// format, spec version and encoding `sign` detected (e.g. CycloneDX, 1.6, xml).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedAttributes {
+    pub sbom_format: String,
+    pub spec_version: String,
+    /// `json`, `xml`, `tag-value` or `rdf-xml`.
+    pub encoding: String,
+    /// Set if the digest is over a canonical form of the file instead of its bytes (`jcs`, see
+    /// `canonical::Canonicalization`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonicalization: Option<String>,
+}
+
+impl SignedAttributes {
+    /// Message signed for an artifact with these attributes: SHA3-256 of the context string
+    /// followed by the JSON of the hex digest and the attributes.
+    pub fn signed_digest(&self, digest: &[u8]) -> anyhow::Result<[u8; 32]> {
+        #[derive(Serialize)]
+        struct Signed<'a> {
+            digest: String,
+            attributes: &'a SignedAttributes,
+        }
+        let message = context_message(
+            ARTIFACT_CONTEXT,
+            &Signed {
+                digest: hex::encode(digest),
+                attributes: self,
+            },
+        )?;
+        Ok(Sha3_256::digest(message).into())
+    }
+}
+
+/// What an artifact signature is over: the SHA3-256 `digest` itself or, with attributes, their
+/// `signed_digest`. Either way 32 bytes, so never one of the longer messages `context_message`
+/// builds for uploads, receipts and the other protocol structures.
+pub fn artifact_message(digest: &[u8], attributes: Option<&SignedAttributes>) -> anyhow::Result<Vec<u8>> {
+    Ok(match attributes {
+        Some(attributes) => attributes.signed_digest(digest)?.to_vec(),
+        None => digest.to_vec(),
+    })
+}
+
+/// Wrapped `.sig` file written by `sign` and `sign-all`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SignatureEnvelope {
+    /// Hex Dilithium5 signature over the SHA3-256 of the artifact (see `artifact_message`).
+    pub signature: String,
+    /// Signed attributes of the artifact (SBOM format and version); covered by `signature`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<SignedAttributes>,
+    /// Signer's own clock (RFC 3339).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    /// Token from a timestamp authority over the SHA3-256 of the signature bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_token: Option<TimestampToken>,
+    /// The ledger entry as logged by the server, with the server's inclusion receipt for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_entry: Option<LedgerEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    /// Proof that `log_entry` is in a tree head cosigned by witnesses (added by `log prove`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion_proof: Option<WitnessedInclusion>,
+}
+
+/// Body of `POST /sign` (authenticated with `Authorization: Bearer <API token>`).
+/// The response is a `SignatureEnvelope` including the logged entry and its receipt.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignRequest {
+    /// Name of the server-held signing key.
+    pub key: String,
+    /// Hex SHA3-256 digest of the artifact.
+    pub digest: String,
+    /// Artifact file name recorded in the log entry.
+    pub file_name: String,
+    /// Attributes to sign along with the digest (see `SignedAttributes::signed_digest`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<SignedAttributes>,
+}
+
+/// Outcome of one verification check.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Check {
+    /// `signature`, `log_inclusion`, `allowlist`, `trusted_timestamp`, `max_age`, `witnesses` or
+    /// `registry`.
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Result of verifying a signature: every check that applied, in order.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Verdict {
+    /// True if every check passed.
+    pub verified: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Verdict {
+    /// Record a check; an `Err` is a failure whose message becomes the detail.
+    pub fn push(&mut self, name: &str, result: anyhow::Result<String>) {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(e) => (false, e.to_string()),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            ok,
+            detail,
+        });
+        self.verified = self.checks.iter().all(|c| c.ok);
+    }
+}
+
+/// Body of `POST /verify`. Give the artifact as `digest` (hex SHA3-256) or as the exact `sbom` text.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VerifyRequest {
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub sbom: Option<String>,
+    /// The `.sig` envelope.
+    pub signature: SignatureEnvelope,
+    /// Hex public key of the signer; if absent, the key is looked up by `key_id` (or the logged
+    /// entry's key id) among keys the server knows.
+    #[serde(default)]
+    pub public_key: Option<String>,
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Name of a policy in the server's policy store.
+    #[serde(default)]
+    pub policy: Option<String>,
+}
+
+/// Domain separator for the server's signature on a logged key revocation.
+const REVOCATION_CONTEXT: &[u8] = b"qs_notary/revocation/v2\n";
+
+/// Message the server signs for a revocation entry (its `signature_hash`); the entry's `digest` is
+/// the SHA3-256 of this message.
+pub fn revocation_message(revocation: &KeyRevocation) -> anyhow::Result<Vec<u8>> {
+    context_message(REVOCATION_CONTEXT, revocation)
+}
+
+/// Domain separator for the signed trailer that seals a ledger segment.
+const SEAL_CONTEXT: &[u8] = b"qs_notary/seal/v1\n";
+
+/// Message signed for a segment seal: the context string followed by the seal body JSON.
+pub fn seal_message(body: &SealBody) -> anyhow::Result<Vec<u8>> {
+    context_message(SEAL_CONTEXT, body)
+}
+
+/// Body of `POST /keys`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EnrollRequest {
+    /// Hex Dilithium5 public key.
+    pub public_key: String,
+}
+
+/// Body of `POST /keys/{id}/revoke`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RevokeRequest {
+    pub reason: String,
+    /// RFC 3339; defaults to now. Signatures from this time on are rejected.
+    #[serde(default)]
+    pub effective_at: Option<String>,
+}
+
+/// Response of `POST /keys/{id}/revoke`: the logged revocation and the receipt for its entry.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RevokeResponse {
+    pub revocation: KeyRevocation,
+    pub receipt: Receipt,
+}
+
+/// One registry key, as served by `GET /keys` and `GET /keys/{id}`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KeyInfo {
+    pub key_id: String,
+    /// Hex Dilithium5 public key.
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<KeyRevocation>,
+}
+
+/// Response of `GET /keys`; saved to a file it is the registry snapshot `verify --registry` reads.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegistrySnapshot {
+    pub keys: Vec<KeyInfo>,
+}
+
+impl RegistrySnapshot {
+    pub fn get(&self, key_id: &str) -> Option<&KeyInfo> {
+        self.keys.iter().find(|k| k.key_id.eq_ignore_ascii_case(key_id))
+    }
+}
+
+/// Domain separator for signed tree heads.
+const TREE_HEAD_CONTEXT: &[u8] = b"qs_notary/tree-head/v2\n";
+
+/// What the log server attests in a tree head: the Merkle root over its first `tree_size` entries.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TreeHeadBody {
+    pub tree_size: u64,
+    /// Hex Merkle root (see `merkle::root`) over the leaf hashes of the first `tree_size` entries.
+    pub root_hash: String,
+    /// RFC 3339 time at which the head was signed.
+    pub timestamp: String,
+    /// Key id of the server's signing key.
+    pub log_key_id: String,
+}
+
+impl TreeHeadBody {
+    /// Message signed by the server: the context string followed by the body JSON.
+    pub fn message(&self) -> anyhow::Result<Vec<u8>> {
+        context_message(TREE_HEAD_CONTEXT, self)
+    }
+
+    /// Decoded root hash.
+    pub fn root(&self) -> anyhow::Result<merkle::Hash> {
+        decode_hash(&self.root_hash)
+    }
+}
+
+/// Response of `GET /tree-head`: the log's current size and root, signed by the server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedTreeHead {
+    #[serde(flatten)]
+    pub body: TreeHeadBody,
+    /// Hex Dilithium5 signature over `body.message()`.
+    pub signature: String,
+}
+
+impl SignedTreeHead {
+    pub fn sign(body: TreeHeadBody, key_provider: &dyn KeyProvider) -> anyhow::Result<Self> {
+        let signature = hex::encode(key_provider.sign(&body.message()?)?);
+        Ok(Self { body, signature })
+    }
+
+    /// Check that the head was signed by the server with public key `pk`.
+    pub fn verify(&self, pk: &PublicKey) -> anyhow::Result<()> {
+        if !self.body.log_key_id.eq_ignore_ascii_case(&key_id(pk)) {
+            return Err(anyhow::anyhow!("Tree head was signed by a different log key"));
+        }
+        verify_hex(&self.signature, &self.body.message()?, pk)
+            .map_err(|_| anyhow::anyhow!("Invalid tree head signature"))
+    }
+}
+
+/// Response of `GET /tree-head/consistency`: proof that the tree of `first` entries is a prefix of
+/// the tree of `second` entries.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConsistencyProof {
+    pub first: u64,
+    pub second: u64,
+    /// Hex node hashes (see `merkle::consistency_proof`).
+    pub proof: Vec<String>,
+}
+
+impl ConsistencyProof {
+    /// Check the proof against two tree heads of the same log (`old` no larger than `new`).
+    pub fn verify(&self, old: &TreeHeadBody, new: &TreeHeadBody) -> anyhow::Result<()> {
+        if self.first != old.tree_size || self.second != new.tree_size {
+            return Err(anyhow::anyhow!("Consistency proof is for different tree sizes"));
+        }
+        let proof = self
+            .proof
+            .iter()
+            .map(|h| decode_hash(h))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if !merkle::verify_consistency(self.first, self.second, &old.root()?, &new.root()?, &proof) {
+            return Err(anyhow::anyhow!(
+                "Tree heads of size {} and {} are not consistent",
+                self.first,
+                self.second
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Domain separator for witness cosignatures on tree heads.
+const COSIGNATURE_CONTEXT: &[u8] = b"qs_notary/cosignature/v2\n";
+
+/// What a witness attests: it saw the log with this key at this size and root, consistent with
+/// every head it cosigned before. The log's own timestamp is not covered, so the cosignature holds
+/// for any head the log signs over the same tree.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CosignatureBody {
+    pub log_key_id: String,
+    pub tree_size: u64,
+    pub root_hash: String,
+    /// RFC 3339 time at which the witness cosigned.
+    pub timestamp: String,
+    /// Key id of the witness key.
+    pub witness_key_id: String,
+}
+
+impl CosignatureBody {
+    /// Message signed by the witness: the context string followed by the body JSON.
+    pub fn message(&self) -> anyhow::Result<Vec<u8>> {
+        context_message(COSIGNATURE_CONTEXT, self)
+    }
+
+    /// True if this cosignature is about the tree described by `head`.
+    pub fn covers(&self, head: &TreeHeadBody) -> bool {
+        self.log_key_id.eq_ignore_ascii_case(&head.log_key_id)
+            && self.tree_size == head.tree_size
+            && self.root_hash.eq_ignore_ascii_case(&head.root_hash)
+    }
+}
+
+/// A witness's signature on a tree head; body of `POST /tree-head/cosignatures`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Cosignature {
+    #[serde(flatten)]
+    pub body: CosignatureBody,
+    /// Hex Dilithium5 signature over `body.message()`.
+    pub signature: String,
+}
+
+impl Cosignature {
+    pub fn sign(body: CosignatureBody, key_provider: &dyn KeyProvider) -> anyhow::Result<Self> {
+        let signature = hex::encode(key_provider.sign(&body.message()?)?);
+        Ok(Self { body, signature })
+    }
+
+    /// Check that the witness with public key `pk` cosigned `head`.
+    pub fn verify(&self, head: &TreeHeadBody, pk: &PublicKey) -> anyhow::Result<()> {
+        if !self.body.covers(head) {
+            return Err(anyhow::anyhow!("Cosignature is for a different tree head"));
+        }
+        self.verify_signature(pk)
+    }
+
+    /// Check only that the witness with public key `pk` signed this body.
+    pub fn verify_signature(&self, pk: &PublicKey) -> anyhow::Result<()> {
+        if !self.body.witness_key_id.eq_ignore_ascii_case(&key_id(pk)) {
+            return Err(anyhow::anyhow!("Cosignature is from a different witness key"));
+        }
+        verify_hex(&self.signature, &self.body.message()?, pk)
+            .map_err(|_| anyhow::anyhow!("Invalid cosignature"))
+    }
+}
+
+/// A signed tree head with the witness cosignatures the log has collected for it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CosignedTreeHead {
+    #[serde(flatten)]
+    pub tree_head: SignedTreeHead,
+    #[serde(default)]
+    pub cosignatures: Vec<Cosignature>,
+}
+
+/// Response of `GET /tree-head/cosigned`: recently cosigned tree heads, largest first.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CosignedTreeHeads {
+    pub tree_heads: Vec<CosignedTreeHead>,
+}
+
+/// Response of `GET /entries/{index}/proof`: audit path from entry `index` to the root of the tree
+/// of the first `tree_size` entries.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InclusionProof {
+    pub index: u64,
+    pub tree_size: u64,
+    /// Hex node hashes (see `merkle::inclusion_proof`).
+    pub proof: Vec<String>,
+}
+
+impl InclusionProof {
+    /// Check that `entry` is at `index` in the tree described by `head`.
+    pub fn verify(&self, entry: &LedgerEntry, head: &TreeHeadBody) -> anyhow::Result<()> {
+        if self.tree_size != head.tree_size {
+            return Err(anyhow::anyhow!("Inclusion proof is for a different tree size"));
+        }
+        let proof = self
+            .proof
+            .iter()
+            .map(|h| decode_hash(h))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if !merkle::verify_inclusion(&leaf_hash(entry), self.index, self.tree_size, &proof, &head.root()?) {
+            return Err(anyhow::anyhow!(
+                "Entry is not at index {} in the tree head of size {}",
+                self.index,
+                self.tree_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Inclusion proof for a logged entry together with the cosigned tree head it leads to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WitnessedInclusion {
+    pub proof: InclusionProof,
+    pub tree_head: CosignedTreeHead,
+}
+
+/// Decode a hex SHA3-256 hash.
+fn decode_hash(s: &str) -> anyhow::Result<merkle::Hash> {
+    hex::decode(s)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid hash: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TestKey};
+
+    fn issue_receipt(entry: &LedgerEntry, log: &TestKey) -> Receipt {
+        let body = ReceiptBody {
+            log_index: 3,
+            integrated_time: "2024-05-01T12:00:00Z".to_string(),
+            leaf_hash: hex::encode(leaf_hash(entry)),
+            log_key_id: log.key_id().unwrap(),
+        };
+        Receipt::sign(body, log).unwrap()
+    }
+
+    #[test]
+    fn receipt_verifies_only_for_its_entry_and_log_key() {
+        let log = TestKey::generate();
+        let entry = testing::entry("sbom.json");
+        let receipt = issue_receipt(&entry, &log);
+        receipt.verify(&entry, &log.pk).unwrap();
+
+        let err = receipt.verify(&entry, &TestKey::generate().pk).unwrap_err();
+        assert_eq!(err.to_string(), "Receipt was issued by a different log key");
+        let other = testing::entry("other.json");
+        let err = receipt.verify(&other, &log.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Receipt does not match the logged entry");
+        let mut tampered = receipt.clone();
+        tampered.body.log_index = 4;
+        let err = tampered.verify(&entry, &log.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid receipt signature");
+        // Another key cannot issue receipts in the log key's name.
+        let mut forged = issue_receipt(&entry, &TestKey::generate());
+        forged.body.log_key_id = log.key_id().unwrap();
+        assert!(forged.verify(&entry, &log.pk).is_err());
+    }
+
+    #[test]
+    fn timestamp_token_verifies_only_for_its_digest_and_tsa_key() {
+        let tsa = TestKey::generate();
+        let digest = Sha3_256::digest(b"signature");
+        let body = TimestampBody {
+            digest: hex::encode(digest),
+            time: "2024-05-01T12:00:00Z".to_string(),
+            serial: 1,
+            tsa_key_id: tsa.key_id().unwrap(),
+        };
+        let token = TimestampToken::sign(body, &tsa).unwrap();
+        token.verify(&digest, &tsa.pk).unwrap();
+
+        let err = token.verify(&Sha3_256::digest(b"other"), &tsa.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Timestamp token is for a different digest");
+        let err = token.verify(&digest, &TestKey::generate().pk).unwrap_err();
+        assert_eq!(err.to_string(), "Timestamp token was issued by a different key");
+        let mut backdated = token.clone();
+        backdated.body.time = "2020-01-01T00:00:00Z".to_string();
+        let err = backdated.verify(&digest, &tsa.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid timestamp token signature");
+    }
+
+    #[test]
+    fn upload_signature_covers_the_entry() {
+        let key = TestKey::generate();
+        let upload = SignedUpload::sign(testing::entry("sbom.json"), &key).unwrap();
+        upload.verify(&key.pk).unwrap();
+        assert!(upload.verify(&TestKey::generate().pk).is_err());
+        let mut tampered = upload.clone();
+        tampered.entry.file_name = "other.json".to_string();
+        assert!(tampered.verify(&key.pk).is_err());
+    }
+
+    #[test]
+    fn inclusion_proof_leads_to_a_signed_and_cosigned_tree_head() {
+        let log = TestKey::generate();
+        let witness = TestKey::generate();
+        let entries: Vec<LedgerEntry> = ["a.json", "b.json", "c.json"].iter().map(|n| testing::entry(n)).collect();
+        let leaves: Vec<merkle::Hash> = entries.iter().map(leaf_hash).collect();
+        let body = TreeHeadBody {
+            tree_size: 3,
+            root_hash: hex::encode(merkle::root(&leaves)),
+            timestamp: "2024-05-01T12:00:00Z".to_string(),
+            log_key_id: log.key_id().unwrap(),
+        };
+        let head = SignedTreeHead::sign(body.clone(), &log).unwrap();
+        head.verify(&log.pk).unwrap();
+        assert!(head.verify(&witness.pk).is_err());
+
+        let proof = InclusionProof {
+            index: 1,
+            tree_size: 3,
+            proof: merkle::inclusion_proof(&leaves, 1).iter().map(hex::encode).collect(),
+        };
+        proof.verify(&entries[1], &body).unwrap();
+        let err = proof.verify(&entries[0], &body).unwrap_err();
+        assert_eq!(err.to_string(), "Entry is not at index 1 in the tree head of size 3");
+        let larger = TreeHeadBody {
+            tree_size: 4,
+            ..body.clone()
+        };
+        assert!(proof.verify(&entries[1], &larger).is_err());
+
+        let cosignature = Cosignature::sign(
+            CosignatureBody {
+                log_key_id: body.log_key_id.clone(),
+                tree_size: body.tree_size,
+                root_hash: body.root_hash.clone(),
+                timestamp: "2024-05-01T12:01:00Z".to_string(),
+                witness_key_id: witness.key_id().unwrap(),
+            },
+            &witness,
+        )
+        .unwrap();
+        cosignature.verify(&body, &witness.pk).unwrap();
+        // The log's timestamp is not covered: a later head over the same tree keeps it.
+        let later = TreeHeadBody {
+            timestamp: "2024-05-02T00:00:00Z".to_string(),
+            ..body.clone()
+        };
+        cosignature.verify(&later, &witness.pk).unwrap();
+        let err = cosignature.verify(&larger, &witness.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Cosignature is for a different tree head");
+        let err = cosignature.verify(&body, &log.pk).unwrap_err();
+        assert_eq!(err.to_string(), "Cosignature is from a different witness key");
+    }
+}
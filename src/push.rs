@@ -0,0 +1,131 @@
+//! ledger push: reconcile the local ledger with the central server and upload missing entries.
+
+use crate::client;
+use crate::key_provider::KeyProvider;
+use crate::ledger::{self, LedgerEntry};
+use crate::protocol::SignedUpload;
+use std::path::Path;
+
+/// Outcome of a push.
+pub struct PushReport {
+    pub checked: usize,
+    pub already_present: usize,
+    /// Entries uploaded (or, with `dry_run`, that would have been).
+    pub uploaded: Vec<LedgerEntry>,
+    /// Entries that could not be looked up or uploaded, with the reason; the push carries on past
+    /// them.
+    pub failed: Vec<(LedgerEntry, String)>,
+}
+
+/// Check every local entry against the server by leaf hash and upload the ones it lacks.
+/// Uploads are signed with `key_provider` and idempotent on the server, so an interrupted push
+/// can simply be re-run. A failed lookup or upload (e.g. an entry signed by a key the server
+/// refuses) is recorded in the report and the remaining entries are still pushed.
+pub fn run(
+    ledger_path: &Path,
+    server_url: &str,
+    key_provider: &dyn KeyProvider,
+    dry_run: bool,
+) -> anyhow::Result<PushReport> {
+    let entries = ledger::open_store(ledger_path, None)?.entries()?;
+    let mut report = PushReport {
+        checked: entries.len(),
+        already_present: 0,
+        uploaded: Vec::new(),
+        failed: Vec::new(),
+    };
+    for entry in entries {
+        match push_entry(server_url, &entry, key_provider, dry_run) {
+            Ok(true) => report.already_present += 1,
+            Ok(false) => report.uploaded.push(entry),
+            Err(e) => report.failed.push((entry, e.to_string())),
+        }
+    }
+    Ok(report)
+}
+
+/// Upload `entry` unless the server has it; returns whether it was already there.
+fn push_entry(
+    server_url: &str,
+    entry: &LedgerEntry,
+    key_provider: &dyn KeyProvider,
+    dry_run: bool,
+) -> anyhow::Result<bool> {
+    if client::has_entry(server_url, &hex::encode(ledger::leaf_hash(entry)))? {
+        return Ok(true);
+    }
+    if !dry_run {
+        client::upload(server_url, &SignedUpload::sign(entry.clone(), key_provider)?)?;
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_provider::FileSystemProvider;
+    use crate::testing::{self, StubLog};
+    use axum::http::StatusCode;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    /// A ledger with entries for `names`, and a key to sign uploads with.
+    fn local_ledger(name: &str, names: &[&str]) -> (PathBuf, FileSystemProvider) {
+        let dir = testing::scratch_dir(name);
+        let ledger = dir.join("ledger.json");
+        for name in names {
+            ledger::append_entry(&ledger, &testing::entry(name)).unwrap();
+        }
+        (ledger, testing::key(&dir))
+    }
+
+    fn names(entries: &[LedgerEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.file_name.as_str()).collect()
+    }
+
+    #[test]
+    fn refused_entry_is_reported_and_the_rest_still_pushed() {
+        let (ledger, key) = local_ledger("push_refused", &["a.json", "b.json", "c.json", "d.json"]);
+        let log = Arc::new(StubLog::default());
+        log.leaves.lock().unwrap().push(hex::encode(ledger::leaf_hash(
+            &ledger::open_store(&ledger, None).unwrap().entries().unwrap()[3],
+        )));
+        log.refuse("b.json", StatusCode::FORBIDDEN);
+        let url = testing::serve(log.clone());
+
+        let report = run(&ledger, &url, &key, false).unwrap();
+        assert_eq!(report.checked, 4);
+        assert_eq!(report.already_present, 1);
+        assert_eq!(names(&report.uploaded), ["a.json", "c.json"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0.file_name, "b.json");
+        assert!(report.failed[0].1.contains("403"), "{}", report.failed[0].1);
+
+        // A re-run only tries the entry the server still lacks.
+        let report = run(&ledger, &url, &key, false).unwrap();
+        assert_eq!(report.already_present, 3);
+        assert!(report.uploaded.is_empty());
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(log.uploads(), 4);
+        assert_eq!(log.received.lock().unwrap()[3].entry.file_name, "b.json");
+    }
+
+    #[test]
+    fn dry_run_lists_missing_entries_without_uploading() {
+        let (ledger, key) = local_ledger("push_dry_run", &["a.json", "b.json"]);
+        let log = Arc::new(StubLog::default());
+        let url = testing::serve(log.clone());
+        let report = run(&ledger, &url, &key, true).unwrap();
+        assert_eq!(names(&report.uploaded), ["a.json", "b.json"]);
+        assert_eq!(log.uploads(), 0);
+    }
+
+    #[test]
+    fn unreachable_server_fails_each_entry() {
+        let (ledger, key) = local_ledger("push_unreachable", &["a.json", "b.json"]);
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let report = run(&ledger, &format!("http://127.0.0.1:{}", port), &key, false).unwrap();
+        assert_eq!(report.failed.len(), 2);
+        assert!(report.uploaded.is_empty());
+    }
+}
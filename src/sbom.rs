@@ -0,0 +1,173 @@
+//! SBOM formats: detect the format, spec version and encoding of an SBOM and validate it. JSON is
+//! checked against the bundled schemas (`schema`), CycloneDX XML and SPDX RDF/XML in `xml`, SPDX
+//! tag-value in `tag_value`.
+
+mod tag_value;
+mod xml;
+
+use crate::protocol::SignedAttributes;
+use crate::schema;
+use anyhow::anyhow;
+use std::fmt;
+
+/// Most validation errors listed in one failure message.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// The SBOM standards `sign` accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+impl fmt::Display for SbomFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SbomFormat::CycloneDx => "CycloneDX",
+            SbomFormat::Spdx => "SPDX",
+        })
+    }
+}
+
+/// How the SBOM is serialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbomEncoding {
+    Json,
+    Xml,
+    TagValue,
+    RdfXml,
+}
+
+impl SbomEncoding {
+    /// Name recorded in the signed attributes.
+    pub fn id(&self) -> &'static str {
+        match self {
+            SbomEncoding::Json => "json",
+            SbomEncoding::Xml => "xml",
+            SbomEncoding::TagValue => "tag-value",
+            SbomEncoding::RdfXml => "rdf-xml",
+        }
+    }
+}
+
+impl fmt::Display for SbomEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SbomEncoding::Json => "JSON",
+            SbomEncoding::Xml => "XML",
+            SbomEncoding::TagValue => "tag-value",
+            SbomEncoding::RdfXml => "RDF/XML",
+        })
+    }
+}
+
+/// Format, spec version and encoding of a validated SBOM, e.g. CycloneDX 1.6 XML.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SbomSpec {
+    pub format: SbomFormat,
+    pub version: String,
+    pub encoding: SbomEncoding,
+}
+
+impl SbomSpec {
+    /// The spec as recorded in the signed attributes.
+    pub fn attributes(&self) -> SignedAttributes {
+        SignedAttributes {
+            sbom_format: self.format.to_string(),
+            spec_version: self.version.clone(),
+            encoding: self.encoding.id().to_string(),
+            canonicalization: None,
+        }
+    }
+}
+
+impl fmt::Display for SbomSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.format, self.version, self.encoding)
+    }
+}
+
+/// Spec versions with a validator.
+pub(crate) const SUPPORTED: &str = "CycloneDX 1.4, 1.5, 1.6 (JSON, XML); SPDX 2.2, 2.3 (JSON, tag-value, RDF/XML); SPDX 3.0 (JSON-LD)";
+
+/// Error for a version without a validator.
+pub(crate) fn unsupported(format: SbomFormat, version: &str, encoding: SbomEncoding) -> anyhow::Error {
+    anyhow!(
+        "Unsupported SBOM version {} {} {} (supported: {})",
+        format,
+        version,
+        encoding,
+        SUPPORTED
+    )
+}
+
+/// Problems found while validating one SBOM. Errors always fail validation; known-benign
+/// deviations (unknown properties, tags or elements, malformed dates and identifiers) are printed
+/// as warnings with `lenient` and fail otherwise.
+pub(crate) struct Findings {
+    lenient: bool,
+    errors: Vec<String>,
+}
+
+impl Findings {
+    pub(crate) fn new(lenient: bool) -> Self {
+        Self {
+            lenient,
+            errors: Vec::new(),
+        }
+    }
+
+    /// A violation at `location` (JSON pointer, element path or line).
+    pub(crate) fn error(&mut self, location: &str, message: impl fmt::Display) {
+        self.errors.push(format!("  at {}: {}", location, message));
+    }
+
+    /// A known-benign deviation at `location`.
+    pub(crate) fn deviation(&mut self, location: &str, message: impl fmt::Display) {
+        if self.lenient {
+            eprintln!("Warning: at {}: {} (ignored with --lenient)", location, message);
+        } else {
+            self.error(location, message);
+        }
+    }
+
+    /// `spec` if nothing failed, else an error listing the first `MAX_REPORTED_ERRORS` problems.
+    pub(crate) fn finish(mut self, spec: SbomSpec) -> anyhow::Result<SbomSpec> {
+        if self.errors.is_empty() {
+            return Ok(spec);
+        }
+        let count = self.errors.len();
+        self.errors.truncate(MAX_REPORTED_ERRORS);
+        let more = match count.saturating_sub(MAX_REPORTED_ERRORS) {
+            0 => String::new(),
+            n => format!("\n  ... and {} more", n),
+        };
+        Err(anyhow!(
+            "Invalid {} SBOM ({} error{}):\n{}{}",
+            spec,
+            count,
+            if count == 1 { "" } else { "s" },
+            self.errors.join("\n"),
+            more
+        ))
+    }
+}
+
+/// Detect the encoding from the content (JSON object, XML document or SPDX tag-value), then the
+/// format and spec version, and validate the SBOM.
+pub fn validate(bytes: &[u8], lenient: bool) -> anyhow::Result<SbomSpec> {
+    let text = std::str::from_utf8(bytes).map_err(|e| anyhow!("Invalid SBOM: not UTF-8 ({})", e))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    match text.trim_start().chars().next() {
+        Some('{') => {
+            let value: serde_json::Value =
+                serde_json::from_str(text).map_err(|e| anyhow!("Invalid JSON: {}", e))?;
+            schema::validate(&value, lenient)
+        }
+        Some('<') => xml::validate(text, lenient),
+        _ if tag_value::looks_like(text) => tag_value::validate(text, lenient),
+        _ => Err(anyhow!(
+            "Invalid SBOM: expected CycloneDX (JSON or XML) or SPDX (JSON, tag-value or RDF/XML)"
+        )),
+    }
+}
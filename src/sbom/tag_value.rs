@@ -0,0 +1,495 @@
+//! SPDX 2.2/2.3 tag-value documents (`.spdx`): `Tag: value` lines, multi-line values wrapped in
+//! `<text>...</text>`, sections started by `PackageName`, `FileName`, `SnippetSPDXID` and
+//! `LicenseID`.
+
+use super::{unsupported, Findings, SbomEncoding, SbomFormat, SbomSpec};
+use anyhow::anyhow;
+
+const DOCUMENT_TAGS: &[&str] = &[
+    "SPDXVersion",
+    "DataLicense",
+    "SPDXID",
+    "DocumentName",
+    "DocumentNamespace",
+    "ExternalDocumentRef",
+    "LicenseListVersion",
+    "Creator",
+    "Created",
+    "CreatorComment",
+    "DocumentComment",
+];
+const PACKAGE_TAGS: &[&str] = &[
+    "PackageName",
+    "SPDXID",
+    "PackageVersion",
+    "PackageFileName",
+    "PackageSupplier",
+    "PackageOriginator",
+    "PackageDownloadLocation",
+    "FilesAnalyzed",
+    "PackageVerificationCode",
+    "PackageChecksum",
+    "PackageHomePage",
+    "PackageSourceInfo",
+    "PackageLicenseConcluded",
+    "PackageLicenseInfoFromFiles",
+    "PackageLicenseDeclared",
+    "PackageLicenseComments",
+    "PackageCopyrightText",
+    "PackageSummary",
+    "PackageDescription",
+    "PackageComment",
+    "ExternalRef",
+    "ExternalRefComment",
+    "PackageAttributionText",
+];
+/// Package tags added in SPDX 2.3.
+const PACKAGE_TAGS_2_3: &[&str] = &[
+    "PrimaryPackagePurpose",
+    "ReleaseDate",
+    "BuiltDate",
+    "ValidUntilDate",
+];
+const FILE_TAGS: &[&str] = &[
+    "FileName",
+    "SPDXID",
+    "FileType",
+    "FileChecksum",
+    "LicenseConcluded",
+    "LicenseInfoInFile",
+    "LicenseComments",
+    "FileCopyrightText",
+    "FileComment",
+    "FileNotice",
+    "FileContributor",
+    "FileAttributionText",
+    "FileDependency",
+];
+const SNIPPET_TAGS: &[&str] = &[
+    "SnippetSPDXID",
+    "SnippetFromFileSPDXID",
+    "SnippetByteRange",
+    "SnippetLineRange",
+    "SnippetLicenseConcluded",
+    "LicenseInfoInSnippet",
+    "SnippetLicenseComments",
+    "SnippetCopyrightText",
+    "SnippetComment",
+    "SnippetName",
+    "SnippetAttributionText",
+];
+const LICENSE_TAGS: &[&str] = &[
+    "LicenseID",
+    "ExtractedText",
+    "LicenseName",
+    "LicenseCrossReference",
+    "LicenseComment",
+];
+/// Allowed in any section.
+const ANY_SECTION_TAGS: &[&str] = &[
+    "Relationship",
+    "RelationshipComment",
+    "Annotator",
+    "AnnotationDate",
+    "AnnotationType",
+    "SPDXREF",
+    "AnnotationComment",
+    "Reviewer",
+    "ReviewDate",
+    "ReviewComment",
+];
+
+const CHECKSUM_ALGORITHMS_2_2: &[&str] = &[
+    "SHA1", "SHA224", "SHA256", "SHA384", "SHA512", "MD2", "MD4", "MD5", "MD6",
+];
+/// Checksum algorithms added in SPDX 2.3.
+const CHECKSUM_ALGORITHMS_2_3: &[&str] = &[
+    "SHA3-256",
+    "SHA3-384",
+    "SHA3-512",
+    "BLAKE2b-256",
+    "BLAKE2b-384",
+    "BLAKE2b-512",
+    "BLAKE3",
+    "ADLER32",
+];
+const PACKAGE_PURPOSES: &[&str] = &[
+    "APPLICATION",
+    "FRAMEWORK",
+    "LIBRARY",
+    "CONTAINER",
+    "OPERATING-SYSTEM",
+    "DEVICE",
+    "FIRMWARE",
+    "SOURCE",
+    "ARCHIVE",
+    "FILE",
+    "INSTALL",
+    "OTHER",
+];
+
+/// One `Tag: value` pair and the line it starts on.
+struct Pair<'a> {
+    line: usize,
+    tag: &'a str,
+    value: &'a str,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Document,
+    Package,
+    File,
+    Snippet,
+    License,
+}
+
+impl Section {
+    fn started_by(tag: &str) -> Option<Self> {
+        match tag {
+            "PackageName" => Some(Section::Package),
+            "FileName" => Some(Section::File),
+            "SnippetSPDXID" => Some(Section::Snippet),
+            "LicenseID" => Some(Section::License),
+            _ => None,
+        }
+    }
+
+    fn tags(self, version: &str) -> Vec<&'static str> {
+        let mut tags = match self {
+            Section::Document => DOCUMENT_TAGS.to_vec(),
+            Section::Package => PACKAGE_TAGS.to_vec(),
+            Section::File => FILE_TAGS.to_vec(),
+            Section::Snippet => SNIPPET_TAGS.to_vec(),
+            Section::License => LICENSE_TAGS.to_vec(),
+        };
+        if self == Section::Package && version == "2.3" {
+            tags.extend_from_slice(PACKAGE_TAGS_2_3);
+        }
+        tags.extend_from_slice(ANY_SECTION_TAGS);
+        tags
+    }
+
+    /// Tags each section of this kind must have, after the one that starts it.
+    fn required(self, version: &str) -> &'static [&'static str] {
+        match (self, version) {
+            (Section::Document, _) => &[
+                "SPDXVersion",
+                "DataLicense",
+                "SPDXID",
+                "DocumentName",
+                "DocumentNamespace",
+                "Creator",
+                "Created",
+            ],
+            (Section::Package, "2.2") => &[
+                "SPDXID",
+                "PackageDownloadLocation",
+                "PackageLicenseConcluded",
+                "PackageLicenseDeclared",
+                "PackageCopyrightText",
+            ],
+            (Section::Package, _) => &["SPDXID", "PackageDownloadLocation"],
+            (Section::File, _) => &["SPDXID", "FileChecksum"],
+            (Section::Snippet, _) => &["SnippetFromFileSPDXID", "SnippetByteRange"],
+            (Section::License, _) => &["ExtractedText"],
+        }
+    }
+}
+
+/// True if the text has an `SPDXVersion:` line, as every tag-value document starts with.
+pub(super) fn looks_like(text: &str) -> bool {
+    text.lines()
+        .any(|l| l.trim_start().starts_with("SPDXVersion:"))
+}
+
+/// Split the document into pairs; `<text>` values may span lines.
+fn parse<'a>(text: &'a str, findings: &mut Findings) -> Vec<Pair<'a>> {
+    let mut pairs = Vec::new();
+    let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l));
+    while let Some((line, current)) = lines.next() {
+        let trimmed = current.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let Some((tag, value)) = current.split_once(':') else {
+            findings.error(&format!("line {}", line), "expected `Tag: value`");
+            continue;
+        };
+        let value = value.trim();
+        let Some(body) = value.strip_prefix("<text>") else {
+            pairs.push(Pair {
+                line,
+                tag: tag.trim(),
+                value,
+            });
+            continue;
+        };
+        // The value runs to the closing tag, possibly lines later; find it in the original text
+        // so the value is one slice.
+        let start = body.as_ptr() as usize - text.as_ptr() as usize;
+        let Some(len) = text[start..].find("</text>") else {
+            findings.error(&format!("line {}", line), "<text> without </text>");
+            break;
+        };
+        let value = &text[start..start + len];
+        for _ in 0..value.matches('\n').count() {
+            lines.next();
+        }
+        pairs.push(Pair {
+            line,
+            tag: tag.trim(),
+            value,
+        });
+    }
+    pairs
+}
+
+fn is_date_time(s: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(s).is_ok()
+}
+
+pub(super) fn validate(text: &str, lenient: bool) -> anyhow::Result<SbomSpec> {
+    let mut findings = Findings::new(lenient);
+    let pairs = parse(text, &mut findings);
+    let version = pairs
+        .iter()
+        .find(|p| p.tag == "SPDXVersion")
+        .map(|p| p.value.strip_prefix("SPDX-").unwrap_or(p.value))
+        .ok_or_else(|| anyhow!("Invalid SBOM: tag-value document without SPDXVersion"))?;
+    let spec = SbomSpec {
+        format: SbomFormat::Spdx,
+        version: version.to_string(),
+        encoding: SbomEncoding::TagValue,
+    };
+    if !matches!(version, "2.2" | "2.3") {
+        return Err(unsupported(spec.format, version, spec.encoding));
+    }
+    let mut checksum_algorithms = CHECKSUM_ALGORITHMS_2_2.to_vec();
+    if version == "2.3" {
+        checksum_algorithms.extend_from_slice(CHECKSUM_ALGORITHMS_2_3);
+    }
+
+    // Each section as (kind, line it starts on, tags seen).
+    let mut sections = vec![(Section::Document, 1, Vec::new())];
+    for pair in &pairs {
+        let at = format!("line {} ({})", pair.line, pair.tag);
+        if let Some(section) = Section::started_by(pair.tag) {
+            sections.push((section, pair.line, Vec::new()));
+        }
+        let (section, _, seen) = sections.last_mut().expect("document section");
+        if !section.tags(version).contains(&pair.tag) {
+            findings.deviation(&at, "unknown tag for this section");
+        }
+        seen.push(pair.tag);
+        match pair.tag {
+            "DataLicense" if pair.value != "CC0-1.0" => {
+                findings.error(&at, format!("{:?} must be CC0-1.0", pair.value))
+            }
+            "SPDXID" if *section == Section::Document && pair.value != "SPDXRef-DOCUMENT" => {
+                findings.error(&at, format!("{:?} must be SPDXRef-DOCUMENT", pair.value))
+            }
+            "SPDXID" | "SnippetSPDXID" if !pair.value.starts_with("SPDXRef-") => findings
+                .deviation(
+                    &at,
+                    format!("{:?} does not start with SPDXRef-", pair.value),
+                ),
+            "Created" | "ReleaseDate" | "BuiltDate" | "ValidUntilDate" | "AnnotationDate"
+                if !is_date_time(pair.value) =>
+            {
+                findings.deviation(&at, format!("{:?} is not a date-time", pair.value))
+            }
+            "Creator"
+                if !["Person:", "Organization:", "Tool:"]
+                    .iter()
+                    .any(|p| pair.value.starts_with(p)) =>
+            {
+                findings.error(&at, "must start with Person:, Organization: or Tool:")
+            }
+            "PackageChecksum" | "FileChecksum" => {
+                let algorithm = pair.value.split_once(':').map(|(a, _)| a.trim());
+                if !algorithm.is_some_and(|a| checksum_algorithms.contains(&a)) {
+                    findings.error(
+                        &at,
+                        format!(
+                            "expected `<algorithm>: <hex>` with one of {}",
+                            checksum_algorithms.join(", ")
+                        ),
+                    )
+                }
+            }
+            "PrimaryPackagePurpose" if !PACKAGE_PURPOSES.contains(&pair.value) => findings.error(
+                &at,
+                format!(
+                    "{:?} is not one of {}",
+                    pair.value,
+                    PACKAGE_PURPOSES.join(", ")
+                ),
+            ),
+            "FilesAnalyzed" if !matches!(pair.value, "true" | "false") => {
+                findings.error(&at, "must be true or false")
+            }
+            "Relationship" if pair.value.split_whitespace().count() != 3 => {
+                findings.error(&at, "expected `<element> <RELATIONSHIP_TYPE> <element>`")
+            }
+            _ => {}
+        }
+    }
+    for (section, line, seen) in &sections {
+        let start = seen.first().copied().unwrap_or("document");
+        for tag in section.required(version) {
+            if !seen.contains(tag) {
+                findings.error(
+                    &format!("line {} ({})", line, start),
+                    format!("{} is required", tag),
+                );
+            }
+        }
+    }
+    findings.finish(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "SPDXVersion: SPDX-VERSION
+DataLicense: CC0-1.0
+SPDXID: SPDXRef-DOCUMENT
+DocumentName: app
+DocumentNamespace: https://example.com/spdx/app-1.0.0
+Creator: Tool: qs_notary
+Created: 2024-05-01T12:00:00Z
+
+# The package
+PackageName: app
+SPDXID: SPDXRef-Package-app
+PackageDownloadLocation: NOASSERTION
+PackageChecksum: SHA256: 5f6c8b7d0e0f4a3c9b2e1d7a6c5b4a3928171615141312111009080706050403
+PACKAGE
+Relationship: SPDXRef-DOCUMENT DESCRIBES SPDXRef-Package-app
+";
+
+    const PACKAGE_2_2: &str = "PackageLicenseConcluded: NOASSERTION
+PackageLicenseDeclared: NOASSERTION
+PackageCopyrightText: NOASSERTION";
+
+    fn document(version: &str, package: &str) -> String {
+        DOCUMENT
+            .replace("VERSION", version)
+            .replace("PACKAGE\n", &format!("{}\n", package))
+    }
+
+    fn error(text: &str, lenient: bool) -> String {
+        validate(text, lenient).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_documents_pass() {
+        for (version, package) in [("2.2", PACKAGE_2_2), ("2.3", "PrimaryPackagePurpose: APPLICATION")] {
+            let text = document(version, package);
+            assert!(looks_like(&text));
+            let spec = validate(&text, false).unwrap();
+            assert_eq!(
+                (spec.format, spec.version.as_str(), spec.encoding),
+                (SbomFormat::Spdx, version, SbomEncoding::TagValue)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let err = error(&document("2.1", ""), true);
+        assert!(err.contains("Unsupported SBOM version SPDX 2.1"), "{}", err);
+        let err = error("DocumentName: app\n", true);
+        assert!(err.contains("without SPDXVersion"), "{}", err);
+    }
+
+    #[test]
+    fn text_values_span_lines() {
+        let comment = "PackageComment: <text>Built by CI.\nPackageName: not a section\n\n</text>";
+        let text = document("2.3", comment);
+        validate(&text, false).unwrap();
+
+        let mut findings = Findings::new(false);
+        let pairs = parse(&text, &mut findings);
+        let comment = pairs.iter().find(|p| p.tag == "PackageComment").unwrap();
+        assert_eq!((comment.line, comment.value), (14, "Built by CI.\nPackageName: not a section\n\n"));
+        // The pair after the value keeps its own line number.
+        let relationship = pairs.iter().find(|p| p.tag == "Relationship").unwrap();
+        assert_eq!(relationship.line, 18);
+        assert_eq!(pairs.iter().filter(|p| p.tag == "PackageName").count(), 1);
+        // A one-line value.
+        let text = document("2.3", "PackageComment: <text>one line</text>");
+        let pairs = parse(&text, &mut findings);
+        assert_eq!(pairs.iter().find(|p| p.tag == "PackageComment").unwrap().value, "one line");
+
+        let err = error(&document("2.3", "PackageComment: <text>never closed"), true);
+        assert!(err.contains("at line 14: <text> without </text>"), "{}", err);
+        let err = error(&document("2.3", "no colon here"), true);
+        assert!(err.contains("at line 14: expected `Tag: value`"), "{}", err);
+    }
+
+    #[test]
+    fn sections_have_their_required_tags() {
+        let text = document("2.3", "").replace("DocumentNamespace: https://example.com/spdx/app-1.0.0\n", "");
+        let err = error(&text, true);
+        assert!(err.contains("at line 1 (SPDXVersion): DocumentNamespace is required"), "{}", err);
+
+        // 2.2 packages need license and copyright tags.
+        let err = error(&document("2.2", ""), true);
+        assert!(err.contains("(3 errors)"), "{}", err);
+        assert!(err.contains("at line 10 (PackageName): PackageLicenseConcluded is required"), "{}", err);
+
+        // A file section starts at FileName and needs a checksum.
+        let file = "FileName: ./app\nSPDXID: SPDXRef-File-app\nPackageVersion: 1.0";
+        let err = error(&document("2.3", file), true);
+        assert!(err.contains("(1 error)"), "{}", err);
+        assert!(err.contains("at line 14 (FileName): FileChecksum is required"), "{}", err);
+        // A package tag in the file section is a deviation.
+        let err = error(&document("2.3", file), false);
+        assert!(err.contains("at line 16 (PackageVersion): unknown tag for this section"), "{}", err);
+
+        let err = error(&document("2.3", "SnippetSPDXID: SPDXRef-Snippet"), true);
+        assert!(err.contains("SnippetFromFileSPDXID is required"), "{}", err);
+        assert!(err.contains("SnippetByteRange is required"), "{}", err);
+    }
+
+    #[test]
+    fn tags_and_values_follow_the_version() {
+        let purpose = "PrimaryPackagePurpose: APPLICATION";
+        let err = error(&document("2.2", &format!("{}\n{}", PACKAGE_2_2, purpose)), false);
+        assert!(err.contains("(PrimaryPackagePurpose): unknown tag for this section"), "{}", err);
+
+        let sha3 = "PackageChecksum: SHA3-256: 5f6c8b7d0e0f4a3c9b2e1d7a6c5b4a3928171615141312111009080706050403";
+        validate(&document("2.3", sha3), false).unwrap();
+        let err = error(&document("2.2", &format!("{}\n{}", PACKAGE_2_2, sha3)), true);
+        assert!(err.contains("(PackageChecksum): expected `<algorithm>: <hex>`"), "{}", err);
+    }
+
+    #[test]
+    fn lenient_tolerates_only_deviations() {
+        let text = document("2.3", "PackageVendor: example\nPackageComment: from the CI")
+            .replace("2024-05-01T12:00:00Z", "yesterday")
+            .replace("SPDXRef-Package-app\nPackageDownloadLocation", "Package-app\nPackageDownloadLocation");
+        let err = error(&text, false);
+        assert!(err.contains("(3 errors)"), "{}", err);
+        assert!(err.contains("(PackageVendor): unknown tag for this section"), "{}", err);
+        assert!(err.contains("(Created): \"yesterday\" is not a date-time"), "{}", err);
+        assert!(err.contains("(SPDXID): \"Package-app\" does not start with SPDXRef-"), "{}", err);
+        validate(&text, true).unwrap();
+
+        let text = text
+            .replace("DataLicense: CC0-1.0", "DataLicense: MIT")
+            .replace("Creator: Tool: qs_notary", "Creator: qs_notary")
+            .replace("SPDXID: SPDXRef-DOCUMENT", "SPDXID: SPDXRef-DOC")
+            .replace("PackageComment: from the CI", "FilesAnalyzed: yes\nPrimaryPackagePurpose: APP");
+        let err = error(&text, true);
+        assert!(err.contains("(5 errors)"), "{}", err);
+        assert!(err.contains("(DataLicense): \"MIT\" must be CC0-1.0"), "{}", err);
+        assert!(err.contains("(Creator): must start with Person:, Organization: or Tool:"), "{}", err);
+        assert!(err.contains("(SPDXID): \"SPDXRef-DOC\" must be SPDXRef-DOCUMENT"), "{}", err);
+        assert!(err.contains("(FilesAnalyzed): must be true or false"), "{}", err);
+        assert!(err.contains("(PrimaryPackagePurpose): \"APP\" is not one of"), "{}", err);
+    }
+}
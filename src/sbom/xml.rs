@@ -0,0 +1,609 @@
+//! XML SBOMs: CycloneDX XML (`<bom>` in the `http://cyclonedx.org/schema/bom/<version>` namespace)
+//! and SPDX 2 RDF/XML (`<rdf:RDF>` holding an `spdx:SpdxDocument`). Documents with a DTD are
+//! rejected by the parser, so no entities are expanded.
+
+use super::{unsupported, Findings, SbomEncoding, SbomFormat, SbomSpec};
+use anyhow::anyhow;
+use roxmltree::{Document, Node};
+
+const CYCLONEDX_NS_PREFIX: &str = "http://cyclonedx.org/schema/bom/";
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const SPDX_NS: &str = "http://spdx.org/rdf/terms#";
+
+const COMPONENT_TYPES_1_4: &[&str] = &[
+    "application",
+    "framework",
+    "library",
+    "container",
+    "operating-system",
+    "device",
+    "firmware",
+    "file",
+];
+/// Added in CycloneDX 1.5.
+const COMPONENT_TYPES_1_5: &[&str] = &[
+    "platform",
+    "device-driver",
+    "machine-learning-model",
+    "data",
+];
+/// Added in CycloneDX 1.6.
+const COMPONENT_TYPES_1_6: &[&str] = &["cryptographic-asset"];
+
+const BOM_CHILDREN_1_4: &[&str] = &[
+    "metadata",
+    "components",
+    "services",
+    "externalReferences",
+    "dependencies",
+    "compositions",
+    "properties",
+    "vulnerabilities",
+    "signature",
+];
+const BOM_CHILDREN_1_5: &[&str] = &["annotations", "formulation"];
+const BOM_CHILDREN_1_6: &[&str] = &["declarations", "definitions"];
+
+const COMPONENT_CHILDREN_1_4: &[&str] = &[
+    "supplier",
+    "author",
+    "publisher",
+    "group",
+    "name",
+    "version",
+    "description",
+    "scope",
+    "hashes",
+    "licenses",
+    "copyright",
+    "cpe",
+    "purl",
+    "swid",
+    "modified",
+    "pedigree",
+    "externalReferences",
+    "properties",
+    "components",
+    "evidence",
+    "releaseNotes",
+    "signature",
+];
+const COMPONENT_CHILDREN_1_5: &[&str] = &["modelCard", "data"];
+const COMPONENT_CHILDREN_1_6: &[&str] = &[
+    "authors",
+    "manufacturer",
+    "omniborId",
+    "swhid",
+    "cryptoProperties",
+    "tags",
+];
+
+const HASH_ALGORITHMS: &[&str] = &[
+    "MD5",
+    "SHA-1",
+    "SHA-256",
+    "SHA-384",
+    "SHA-512",
+    "SHA3-256",
+    "SHA3-384",
+    "SHA3-512",
+    "BLAKE2b-256",
+    "BLAKE2b-384",
+    "BLAKE2b-512",
+    "BLAKE3",
+];
+
+/// A list defined in CycloneDX 1.4 plus what later versions added, up to `version`.
+fn cyclonedx_list(
+    version: &str,
+    v1_4: &[&'static str],
+    v1_5: &[&'static str],
+    v1_6: &[&'static str],
+) -> Vec<&'static str> {
+    let mut list = v1_4.to_vec();
+    if version != "1.4" {
+        list.extend_from_slice(v1_5);
+    }
+    if version == "1.6" {
+        list.extend_from_slice(v1_6);
+    }
+    list
+}
+
+/// Element path with 1-based positions among same-named siblings and the line, e.g.
+/// `/bom/components/component[2] (line 14)`.
+fn location(doc: &Document, node: Node) -> String {
+    let mut parts = Vec::new();
+    for n in node.ancestors().filter(Node::is_element) {
+        let name = n.tag_name().name();
+        let same = |s: &Node| s.is_element() && s.tag_name() == n.tag_name();
+        // Both axes start at `n` itself.
+        let position = n.prev_siblings().filter(same).count();
+        let total = position + n.next_siblings().filter(same).count() - 1;
+        parts.push(if total > 1 {
+            format!("{}[{}]", name, position)
+        } else {
+            name.to_string()
+        });
+    }
+    parts.reverse();
+    format!(
+        "/{} (line {})",
+        parts.join("/"),
+        doc.text_pos_at(node.range().start).row
+    )
+}
+
+fn is_date_time(s: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(s.trim()).is_ok()
+}
+
+fn text<'a>(node: Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or("").trim()
+}
+
+pub(super) fn validate(text: &str, lenient: bool) -> anyhow::Result<SbomSpec> {
+    let doc = Document::parse(text).map_err(|e| anyhow!("Invalid XML: {}", e))?;
+    let root = doc.root_element();
+    let ns = root.tag_name().namespace().unwrap_or("");
+    match (root.tag_name().name(), ns) {
+        ("bom", ns) if ns.starts_with(CYCLONEDX_NS_PREFIX) => {
+            let version = &ns[CYCLONEDX_NS_PREFIX.len()..];
+            cyclonedx(&doc, version, Findings::new(lenient))
+        }
+        ("RDF", RDF_NS) => spdx_rdf(&doc, Findings::new(lenient)),
+        (name, ns) => Err(anyhow!(
+            "Invalid SBOM: XML root <{}> (namespace {:?}) is neither a CycloneDX <bom> nor an SPDX <rdf:RDF>",
+            name,
+            ns
+        )),
+    }
+}
+
+/// CycloneDX XML: the document structure, components (type, name, hashes, scope, nested
+/// components), dependencies and services. Elements in other namespaces are extensions and are
+/// allowed.
+fn cyclonedx(doc: &Document, version: &str, mut findings: Findings) -> anyhow::Result<SbomSpec> {
+    let spec = SbomSpec {
+        format: SbomFormat::CycloneDx,
+        version: version.to_string(),
+        encoding: SbomEncoding::Xml,
+    };
+    if !matches!(version, "1.4" | "1.5" | "1.6") {
+        return Err(unsupported(spec.format, version, spec.encoding));
+    }
+    let ns = doc.root_element().tag_name().namespace();
+    let bom = doc.root_element();
+    let at = |node: Node| location(doc, node);
+    if let Some(v) = bom.attribute("version") {
+        if v.parse::<u32>().map_or(true, |v| v < 1) {
+            findings.error(
+                &at(bom),
+                format!("version {:?} must be a positive integer", v),
+            );
+        }
+    }
+    if let Some(serial) = bom.attribute("serialNumber") {
+        let uuid = serial.strip_prefix("urn:uuid:").is_some_and(|u| {
+            u.len() == 36
+                && u.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+                && u.chars().all(|c| c == '-' || c.is_ascii_hexdigit())
+        });
+        if !uuid {
+            findings.deviation(
+                &at(bom),
+                format!("serialNumber {:?} is not a urn:uuid", serial),
+            );
+        }
+    }
+    let own = |n: &Node| n.is_element() && n.tag_name().namespace() == ns;
+    let bom_children = cyclonedx_list(
+        version,
+        BOM_CHILDREN_1_4,
+        BOM_CHILDREN_1_5,
+        BOM_CHILDREN_1_6,
+    );
+    for child in bom.children().filter(own) {
+        if !bom_children.contains(&child.tag_name().name()) {
+            findings.deviation(
+                &at(child),
+                format!("unexpected element <{}>", child.tag_name().name()),
+            );
+        }
+    }
+    let types = cyclonedx_list(
+        version,
+        COMPONENT_TYPES_1_4,
+        COMPONENT_TYPES_1_5,
+        COMPONENT_TYPES_1_6,
+    );
+    let component_children = cyclonedx_list(
+        version,
+        COMPONENT_CHILDREN_1_4,
+        COMPONENT_CHILDREN_1_5,
+        COMPONENT_CHILDREN_1_6,
+    );
+    for node in bom.descendants().filter(own) {
+        let parent = node
+            .parent_element()
+            .map(|p| p.tag_name().name())
+            .unwrap_or("");
+        match (node.tag_name().name(), parent) {
+            ("timestamp", "metadata") if !is_date_time(text(node)) => {
+                findings.deviation(&at(node), format!("{:?} is not a date-time", text(node)));
+            }
+            ("component", "components" | "metadata") => {
+                match node.attribute("type") {
+                    None => findings.error(&at(node), "attribute \"type\" is required"),
+                    Some(t) if !types.contains(&t) => findings.error(
+                        &at(node),
+                        format!("component type {:?} is not one of {}", t, types.join(", ")),
+                    ),
+                    _ => {}
+                }
+                if !node
+                    .children()
+                    .any(|c| own(&c) && c.tag_name().name() == "name")
+                {
+                    findings.error(&at(node), "element <name> is required");
+                }
+                for child in node.children().filter(own) {
+                    if !component_children.contains(&child.tag_name().name()) {
+                        findings.deviation(
+                            &at(child),
+                            format!("unexpected element <{}>", child.tag_name().name()),
+                        );
+                    }
+                }
+            }
+            ("scope", "component")
+                if !matches!(text(node), "required" | "optional" | "excluded") =>
+            {
+                findings.error(
+                    &at(node),
+                    format!(
+                        "scope {:?} is not one of required, optional, excluded",
+                        text(node)
+                    ),
+                );
+            }
+            ("hash", "hashes") => {
+                match node.attribute("alg") {
+                    Some(alg) if HASH_ALGORITHMS.contains(&alg) => {}
+                    alg => findings.error(
+                        &at(node),
+                        format!(
+                            "hash algorithm {:?} is not one of {}",
+                            alg.unwrap_or(""),
+                            HASH_ALGORITHMS.join(", ")
+                        ),
+                    ),
+                }
+                let content = text(node);
+                if !matches!(content.len(), 32 | 40 | 64 | 96 | 128)
+                    || !content.chars().all(|c| c.is_ascii_hexdigit())
+                {
+                    findings
+                        .deviation(&at(node), format!("hash {:?} is not a hex digest", content));
+                }
+            }
+            ("dependency", _) if node.attribute("ref").is_none_or(str::is_empty) => {
+                findings.error(&at(node), "attribute \"ref\" is required");
+            }
+            ("service", "services")
+                if !node
+                    .children()
+                    .any(|c| own(&c) && c.tag_name().name() == "name") =>
+            {
+                findings.error(&at(node), "element <name> is required");
+            }
+            _ => {}
+        }
+    }
+    findings.finish(spec)
+}
+
+/// The first element child of `node` named `name` in the SPDX namespace.
+fn spdx_child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|c| {
+        c.is_element() && c.tag_name().namespace() == Some(SPDX_NS) && c.tag_name().name() == name
+    })
+}
+
+/// Literal value or resource IRI of a property element.
+fn property_value<'a>(node: Node<'a, '_>) -> &'a str {
+    node.attribute((RDF_NS, "resource"))
+        .unwrap_or_else(|| text(node))
+}
+
+/// SPDX 2.2/2.3 RDF/XML: the document (version, data license, name, namespace, creation info),
+/// packages and files.
+fn spdx_rdf(doc: &Document, mut findings: Findings) -> anyhow::Result<SbomSpec> {
+    let at = |node: Node| location(doc, node);
+    let document = doc
+        .descendants()
+        .find(|n| {
+            n.is_element()
+                && n.tag_name().namespace() == Some(SPDX_NS)
+                && n.tag_name().name() == "SpdxDocument"
+        })
+        .ok_or_else(|| anyhow!("Invalid SBOM: RDF/XML document without spdx:SpdxDocument"))?;
+    let version = spdx_child(document, "specVersion")
+        .map(text)
+        .ok_or_else(|| anyhow!("Invalid SBOM: spdx:SpdxDocument without spdx:specVersion"))?;
+    let version = version.strip_prefix("SPDX-").unwrap_or(version);
+    let spec = SbomSpec {
+        format: SbomFormat::Spdx,
+        version: version.to_string(),
+        encoding: SbomEncoding::RdfXml,
+    };
+    if !matches!(version, "2.2" | "2.3") {
+        return Err(unsupported(spec.format, version, spec.encoding));
+    }
+    if document
+        .attribute((RDF_NS, "about"))
+        .is_none_or(str::is_empty)
+    {
+        findings.error(
+            &at(document),
+            "rdf:about (the document namespace) is required",
+        );
+    }
+    match spdx_child(document, "dataLicense").map(property_value) {
+        Some(license) if license.trim_end_matches('/').ends_with("CC0-1.0") => {}
+        Some(license) => findings.error(
+            &at(document),
+            format!("dataLicense {:?} must be CC0-1.0", license),
+        ),
+        None => findings.error(&at(document), "spdx:dataLicense is required"),
+    }
+    if spdx_child(document, "name").is_none() {
+        findings.error(&at(document), "spdx:name is required");
+    }
+    match spdx_child(document, "creationInfo").and_then(|c| spdx_child(c, "CreationInfo")) {
+        None => findings.error(
+            &at(document),
+            "spdx:creationInfo with an spdx:CreationInfo is required",
+        ),
+        Some(info) => {
+            match spdx_child(info, "created").map(text) {
+                None => findings.error(&at(info), "spdx:created is required"),
+                Some(created) if !is_date_time(created) => findings.deviation(
+                    &at(info),
+                    format!("created {:?} is not a date-time", created),
+                ),
+                _ => {}
+            }
+            if spdx_child(info, "creator").is_none() {
+                findings.error(&at(info), "at least one spdx:creator is required");
+            }
+        }
+    }
+    let mut package_required = vec!["name", "downloadLocation"];
+    if version == "2.2" {
+        package_required.extend(["licenseConcluded", "licenseDeclared", "copyrightText"]);
+    }
+    for node in doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().namespace() == Some(SPDX_NS))
+    {
+        let required: &[&str] = match node.tag_name().name() {
+            "Package" => &package_required,
+            "File" => &["fileName", "checksum"],
+            _ => continue,
+        };
+        // A reference to an element described elsewhere (rdf:about only, no properties).
+        if !node.children().any(|c| c.is_element()) {
+            continue;
+        }
+        for property in required {
+            if spdx_child(node, property).is_none() {
+                findings.error(&at(node), format!("spdx:{} is required", property));
+            }
+        }
+    }
+    findings.finish(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CYCLONEDX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<bom xmlns="http://cyclonedx.org/schema/bom/VERSION" serialNumber="urn:uuid:3e671687-395b-41f5-a30f-a58921a69b79" version="1">
+  <metadata>
+    <timestamp>2024-05-01T12:00:00Z</timestamp>
+    <component type="application"><name>app</name><version>1.0.0</version></component>
+  </metadata>
+  <components>
+    <component type="library" bom-ref="pkg:cargo/lib@2.1.0">
+      <name>lib</name>
+      <version>2.1.0</version>
+      <hashes><hash alg="SHA-256">5f6c8b7d0e0f4a3c9b2e1d7a6c5b4a3928171615141312111009080706050403</hash></hashes>
+    </component>
+    COMPONENT
+  </components>
+  <dependencies><dependency ref="pkg:cargo/lib@2.1.0"/></dependencies>
+  BOM_CHILD
+</bom>"#;
+
+    const SPDX_RDF: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:spdx="http://spdx.org/rdf/terms#">
+  <spdx:SpdxDocument rdf:about="https://example.com/spdx/app-1.0.0#SPDXRef-DOCUMENT">
+    <spdx:specVersion>SPDX-VERSION</spdx:specVersion>
+    DATA_LICENSE
+    <spdx:name>app</spdx:name>
+    <spdx:creationInfo>
+      <spdx:CreationInfo>
+        <spdx:created>2024-05-01T12:00:00Z</spdx:created>
+        <spdx:creator>Tool: qs_notary</spdx:creator>
+      </spdx:CreationInfo>
+    </spdx:creationInfo>
+    <spdx:describesPackage>
+      <spdx:Package rdf:about="#SPDXRef-Package-app">
+        <spdx:name>app</spdx:name>
+        <spdx:downloadLocation rdf:resource="http://spdx.org/rdf/terms#noassertion"/>
+        PACKAGE
+      </spdx:Package>
+    </spdx:describesPackage>
+    <spdx:relationship>
+      <spdx:Relationship>
+        <spdx:relationshipType rdf:resource="http://spdx.org/rdf/terms#relationshipType_describes"/>
+        <spdx:relatedSpdxElement><spdx:Package rdf:about="#SPDXRef-Package-app"/></spdx:relatedSpdxElement>
+      </spdx:Relationship>
+    </spdx:relationship>
+  </spdx:SpdxDocument>
+</rdf:RDF>"##;
+
+    const CC0: &str = r#"<spdx:dataLicense rdf:resource="http://spdx.org/licenses/CC0-1.0"/>"#;
+    const PACKAGE_2_2: &str = r#"<spdx:licenseConcluded rdf:resource="http://spdx.org/rdf/terms#noassertion"/>
+        <spdx:licenseDeclared rdf:resource="http://spdx.org/rdf/terms#noassertion"/>
+        <spdx:copyrightText>NOASSERTION</spdx:copyrightText>"#;
+
+    /// A CycloneDX XML BOM with one more `component` and one more child of `<bom>`.
+    fn cyclonedx(version: &str, component: &str, bom_child: &str) -> String {
+        CYCLONEDX
+            .replace("VERSION", version)
+            .replace("COMPONENT", component)
+            .replace("BOM_CHILD", bom_child)
+    }
+
+    fn spdx_rdf(version: &str, data_license: &str, package: &str) -> String {
+        SPDX_RDF
+            .replace("VERSION", version)
+            .replace("DATA_LICENSE", data_license)
+            .replace("PACKAGE", package)
+    }
+
+    fn error(text: &str, lenient: bool) -> String {
+        validate(text, lenient).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_documents_pass() {
+        for version in ["1.4", "1.5", "1.6"] {
+            let spec = validate(&cyclonedx(version, "", ""), false).unwrap();
+            assert_eq!(
+                (spec.format, spec.version.as_str(), spec.encoding),
+                (SbomFormat::CycloneDx, version, SbomEncoding::Xml)
+            );
+        }
+        for (version, package) in [("2.2", PACKAGE_2_2), ("2.3", "")] {
+            let spec = validate(&spdx_rdf(version, CC0, package), false).unwrap();
+            assert_eq!(
+                (spec.format, spec.version.as_str(), spec.encoding),
+                (SbomFormat::Spdx, version, SbomEncoding::RdfXml)
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_roots_and_versions_are_rejected() {
+        let err = error(&cyclonedx("1.3", "", ""), true);
+        assert!(err.contains("Unsupported SBOM version CycloneDX 1.3"), "{}", err);
+        let err = error(&spdx_rdf("2.1", CC0, ""), true);
+        assert!(err.contains("Unsupported SBOM version SPDX 2.1"), "{}", err);
+        let err = error(r#"<bom xmlns="http://example.com/bom"/>"#, true);
+        assert!(err.contains("neither a CycloneDX <bom> nor an SPDX <rdf:RDF>"), "{}", err);
+        let err = error("<bom", true);
+        assert!(err.contains("Invalid XML"), "{}", err);
+    }
+
+    #[test]
+    fn documents_with_a_dtd_are_rejected() {
+        let bom = cyclonedx("1.6", "", "").replacen(
+            "<bom ",
+            "<!DOCTYPE bom [<!ENTITY lib \"lib\">]>\n<bom ",
+            1,
+        );
+        let err = error(&bom, true);
+        assert!(err.contains("Invalid XML: XML with DTD detected"), "{}", err);
+    }
+
+    #[test]
+    fn cyclonedx_lists_follow_the_version() {
+        // (what is added, version that defines it, version that does not)
+        let cases = [
+            (r#"<component type="platform"><name>p</name></component>"#, "", "1.5", "1.4"),
+            (r#"<component type="cryptographic-asset"><name>c</name></component>"#, "", "1.6", "1.5"),
+            (r#"<component type="library"><name>m</name><modelCard/></component>"#, "", "1.5", "1.4"),
+            (r#"<component type="library"><name>t</name><tags/></component>"#, "", "1.6", "1.5"),
+            ("", "<annotations/>", "1.5", "1.4"),
+            ("", "<declarations/>", "1.6", "1.5"),
+        ];
+        for (component, bom_child, defined, undefined) in cases {
+            validate(&cyclonedx(defined, component, bom_child), false).unwrap();
+            assert!(validate(&cyclonedx(undefined, component, bom_child), false).is_err());
+        }
+        // A type from a later version is an error even when lenient; an element is a deviation.
+        let platform = r#"<component type="platform"><name>p</name></component>"#;
+        let err = error(&cyclonedx("1.4", platform, ""), true);
+        assert!(err.contains("component type \"platform\" is not one of"), "{}", err);
+        validate(&cyclonedx("1.4", "", "<annotations/>"), true).unwrap();
+    }
+
+    #[test]
+    fn errors_carry_element_paths_and_lines() {
+        let err = error(&cyclonedx("1.6", r#"<component type="gadget"><version>1</version></component>"#, ""), false);
+        assert!(err.contains("(2 errors)"), "{}", err);
+        assert!(err.contains("at /bom/components/component[2] (line 13): component type \"gadget\""), "{}", err);
+        assert!(err.contains("at /bom/components/component[2] (line 13): element <name> is required"), "{}", err);
+    }
+
+    #[test]
+    fn lenient_tolerates_only_deviations() {
+        let bom = cyclonedx("1.6", "", r#"<vendor>x</vendor><ext:note xmlns:ext="http://example.com/ext"/>"#)
+            .replace("2024-05-01T12:00:00Z", "yesterday")
+            .replace("urn:uuid:3e671687-395b-41f5-a30f-a58921a69b79", "not-a-urn")
+            .replace("5f6c8b7d0e0f4a3c9b2e1d7a6c5b4a3928171615141312111009080706050403", "xyz");
+        // The element in another namespace is an extension, not a deviation.
+        let err = error(&bom, false);
+        assert!(err.contains("(4 errors)"), "{}", err);
+        assert!(err.contains("unexpected element <vendor>"), "{}", err);
+        validate(&bom, true).unwrap();
+
+        let bom = bom
+            .replace(r#"alg="SHA-256""#, r#"alg="SHA-224""#)
+            .replace(r#"<dependency ref="pkg:cargo/lib@2.1.0"/>"#, "<dependency/>");
+        let err = error(&bom, true);
+        assert!(err.contains("(2 errors)"), "{}", err);
+        assert!(err.contains("hash algorithm \"SHA-224\""), "{}", err);
+        assert!(err.contains("attribute \"ref\" is required"), "{}", err);
+    }
+
+    #[test]
+    fn rdf_data_license_must_be_cc0() {
+        for license in [
+            CC0,
+            r#"<spdx:dataLicense rdf:resource="http://spdx.org/licenses/CC0-1.0/"/>"#,
+            "<spdx:dataLicense>CC0-1.0</spdx:dataLicense>",
+        ] {
+            validate(&spdx_rdf("2.3", license, ""), false).unwrap();
+        }
+        let mit = r#"<spdx:dataLicense rdf:resource="http://spdx.org/licenses/MIT"/>"#;
+        let err = error(&spdx_rdf("2.3", mit, ""), true);
+        assert!(err.contains("dataLicense \"http://spdx.org/licenses/MIT\" must be CC0-1.0"), "{}", err);
+        let err = error(&spdx_rdf("2.3", "", ""), true);
+        assert!(err.contains("spdx:dataLicense is required"), "{}", err);
+    }
+
+    #[test]
+    fn rdf_required_properties_follow_the_version() {
+        // 2.2 packages need license and copyright properties; the reference in the relationship
+        // has no properties and is not checked.
+        let err = error(&spdx_rdf("2.2", CC0, ""), true);
+        assert!(err.contains("(3 errors)"), "{}", err);
+        assert!(err.contains("spdx:licenseConcluded is required"), "{}", err);
+        assert!(err.contains("spdx:copyrightText is required"), "{}", err);
+
+        let doc = spdx_rdf("2.3", CC0, "")
+            .replace("<spdx:creator>Tool: qs_notary</spdx:creator>", "")
+            .replace("2024-05-01T12:00:00Z", "yesterday")
+            .replace(r#" rdf:about="https://example.com/spdx/app-1.0.0#SPDXRef-DOCUMENT""#, "");
+        let err = error(&doc, true);
+        assert!(err.contains("(2 errors)"), "{}", err);
+        assert!(err.contains("rdf:about (the document namespace) is required"), "{}", err);
+        assert!(err.contains("at least one spdx:creator is required"), "{}", err);
+        assert!(error(&doc, false).contains("created \"yesterday\" is not a date-time"));
+    }
+}
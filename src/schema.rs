@@ -0,0 +1,281 @@
+//! SBOM schema validation: detect the format and spec version of a JSON SBOM and check it against
+//! the bundled JSON schema for that version (`schemas/`).
+
+use crate::sbom::{self, Findings, SbomEncoding, SbomFormat, SbomSpec};
+use anyhow::anyhow;
+use serde_json::Value;
+
+/// Schema keywords whose violations `--lenient` tolerates: unknown properties (vendor extensions),
+/// string formats (e.g. a `date-time` without offset) and patterns (e.g. a serial number that is
+/// not a `urn:uuid`). The structure (required fields, types, enumerations) is always checked.
+const LENIENT_KEYWORDS: &[&str] = &["additionalProperties", "format", "pattern"];
+
+/// Every bundled schema document by its `$id`. References between documents (the upstream
+/// CycloneDX schemas point at `spdx.schema.json` and `jsf-0.82.schema.json` next to them) are
+/// resolved against this table; nothing is fetched.
+const BUNDLED: &[(&str, &str)] = &[
+    (
+        "http://cyclonedx.org/schema/bom-1.4.schema.json",
+        include_str!("../schemas/bom-1.4.schema.json"),
+    ),
+    (
+        "http://cyclonedx.org/schema/bom-1.5.schema.json",
+        include_str!("../schemas/bom-1.5.schema.json"),
+    ),
+    (
+        "http://cyclonedx.org/schema/bom-1.6.schema.json",
+        include_str!("../schemas/bom-1.6.schema.json"),
+    ),
+    ("http://spdx.org/rdf/terms/2.2", include_str!("../schemas/spdx-2.2.schema.json")),
+    ("http://spdx.org/rdf/terms/2.3", include_str!("../schemas/spdx-2.3.schema.json")),
+    (
+        "https://spdx.org/schema/3.0.1/spdx-json-schema.json",
+        include_str!("../schemas/spdx-3.0.schema.json"),
+    ),
+];
+
+/// `$id` of the schema for a spec version; `None` if the version is not supported.
+fn schema_for(spec: &SbomSpec) -> Option<&'static str> {
+    Some(match (spec.format, spec.version.as_str()) {
+        (SbomFormat::CycloneDx, "1.4") => "http://cyclonedx.org/schema/bom-1.4.schema.json",
+        (SbomFormat::CycloneDx, "1.5") => "http://cyclonedx.org/schema/bom-1.5.schema.json",
+        (SbomFormat::CycloneDx, "1.6") => "http://cyclonedx.org/schema/bom-1.6.schema.json",
+        (SbomFormat::Spdx, "2.2") => "http://spdx.org/rdf/terms/2.2",
+        (SbomFormat::Spdx, "2.3") => "http://spdx.org/rdf/terms/2.3",
+        (SbomFormat::Spdx, "3.0") => "https://spdx.org/schema/3.0.1/spdx-json-schema.json",
+        _ => return None,
+    })
+}
+
+/// The bundled schema document with this `$id`.
+fn bundled(id: &str) -> anyhow::Result<Value> {
+    let (_, text) = BUNDLED
+        .iter()
+        .find(|(bundled_id, _)| *bundled_id == id)
+        .ok_or_else(|| anyhow!("{} is not a bundled schema", id))?;
+    Ok(serde_json::from_str(text)?)
+}
+
+/// Resolves `$ref`s to other documents from `BUNDLED` only.
+struct BundledRetriever;
+
+impl jsonschema::Retrieve for BundledRetriever {
+    fn retrieve(
+        &self,
+        uri: &jsonschema::Uri<String>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        bundled(uri.as_str()).map_err(|e| e.to_string().into())
+    }
+}
+
+/// Read the format and spec version from the document: `bomFormat`/`specVersion` (CycloneDX),
+/// `spdxVersion` (SPDX 2.x), or the SPDX 3 JSON-LD `@context` and the `specVersion` of its
+/// `CreationInfo` (3.0.x is validated as 3.0).
+pub fn detect(value: &Value) -> anyhow::Result<SbomSpec> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| anyhow!("SBOM root must be a JSON object"))?;
+    if obj.get("bomFormat").and_then(Value::as_str) == Some("CycloneDX") {
+        let version = obj
+            .get("specVersion")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("Invalid SBOM: CycloneDX document without specVersion"))?;
+        return Ok(SbomSpec {
+            format: SbomFormat::CycloneDx,
+            version: version.to_string(),
+            encoding: SbomEncoding::Json,
+        });
+    }
+    if let Some(version) = obj.get("spdxVersion").and_then(Value::as_str) {
+        return Ok(SbomSpec {
+            format: SbomFormat::Spdx,
+            version: version.strip_prefix("SPDX-").unwrap_or(version).to_string(),
+            encoding: SbomEncoding::Json,
+        });
+    }
+    let context = obj.get("@context").and_then(Value::as_str).unwrap_or("");
+    if context.starts_with("https://spdx.org/rdf/3.") {
+        let declared = obj
+            .get("@graph")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|e| e.get("type").and_then(Value::as_str) == Some("CreationInfo"))
+            .find_map(|e| e.get("specVersion").and_then(Value::as_str));
+        let version = declared.unwrap_or("3.0");
+        return Ok(SbomSpec {
+            format: SbomFormat::Spdx,
+            version: match version.split('.').collect::<Vec<_>>()[..] {
+                ["3", "0", ..] => "3.0".to_string(),
+                _ => version.to_string(),
+            },
+            encoding: SbomEncoding::Json,
+        });
+    }
+    Err(anyhow!(
+        "Invalid SBOM: missing bomFormat (CycloneDX), spdxVersion (SPDX 2) or an SPDX 3 @context"
+    ))
+}
+
+/// Detect the spec version and validate the document against its schema. Errors list each
+/// violation with its JSON pointer; with `lenient`, violations of `LENIENT_KEYWORDS` are warnings.
+pub fn validate(value: &Value, lenient: bool) -> anyhow::Result<SbomSpec> {
+    let spec = detect(value)?;
+    let id = schema_for(&spec)
+        .ok_or_else(|| sbom::unsupported(spec.format, &spec.version, spec.encoding))?;
+    let validator = jsonschema::options()
+        .with_retriever(BundledRetriever)
+        .build(&bundled(id)?)
+        .map_err(|e| anyhow!("Invalid bundled schema for {}: {}", spec, e))?;
+    let mut findings = Findings::new(lenient);
+    for error in validator.iter_errors(value) {
+        let pointer = error.instance_path().to_string();
+        let location = if pointer.is_empty() { "/" } else { &pointer };
+        if LENIENT_KEYWORDS.contains(&error.kind().keyword()) {
+            findings.deviation(location, &error);
+        } else {
+            findings.error(location, &error);
+        }
+    }
+    findings.finish(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cyclonedx(version: &str) -> Value {
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": version,
+            "serialNumber": "urn:uuid:3e671687-395b-41f5-a30f-a58921a69b79",
+            "version": 1,
+            "metadata": {
+                "timestamp": "2024-05-01T12:00:00Z",
+                "component": { "type": "application", "name": "app", "version": "1.0.0" }
+            },
+            "components": [
+                {
+                    "type": "library",
+                    "name": "lib",
+                    "version": "2.1.0",
+                    "bom-ref": "pkg:cargo/lib@2.1.0",
+                    "purl": "pkg:cargo/lib@2.1.0",
+                    "licenses": [{ "license": { "id": "MIT" } }]
+                }
+            ],
+            "dependencies": [{ "ref": "pkg:cargo/lib@2.1.0", "dependsOn": [] }]
+        })
+    }
+
+    fn spdx_2_3() -> Value {
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": "app",
+            "documentNamespace": "https://example.com/spdx/app-1.0.0",
+            "creationInfo": {
+                "created": "2024-05-01T12:00:00Z",
+                "creators": ["Tool: qs_notary"]
+            },
+            "documentDescribes": ["SPDXRef-Package-app"],
+            "packages": [
+                {
+                    "SPDXID": "SPDXRef-Package-app",
+                    "name": "app",
+                    "versionInfo": "1.0.0",
+                    "downloadLocation": "NOASSERTION"
+                }
+            ],
+            "relationships": [
+                {
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": "SPDXRef-Package-app"
+                }
+            ]
+        })
+    }
+
+    fn error(value: &Value, lenient: bool) -> String {
+        validate(value, lenient).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn bundled_schemas_compile_and_refs_stay_local() {
+        for (id, _) in BUNDLED {
+            let schema = bundled(id).unwrap();
+            assert_eq!(schema["$id"], *id);
+            jsonschema::options()
+                .with_retriever(BundledRetriever)
+                .build(&schema)
+                .unwrap();
+        }
+        let remote = json!({ "$ref": "https://example.com/other.schema.json" });
+        let err = jsonschema::options()
+            .with_retriever(BundledRetriever)
+            .build(&remote)
+            .unwrap_err();
+        assert!(err.to_string().contains("not a bundled schema"), "{}", err);
+    }
+
+    #[test]
+    fn cyclonedx_without_spec_version_is_rejected() {
+        let err = error(&json!({ "bomFormat": "CycloneDX" }), true);
+        assert!(err.contains("specVersion"), "{}", err);
+        let err = error(&json!({ "bomFormat": "CycloneDX", "specVersion": "1.3" }), true);
+        assert!(err.contains("Unsupported"), "{}", err);
+    }
+
+    #[test]
+    fn valid_documents_pass() {
+        for version in ["1.4", "1.5", "1.6"] {
+            let spec = validate(&cyclonedx(version), false).unwrap();
+            assert_eq!((spec.format, spec.version.as_str()), (SbomFormat::CycloneDx, version));
+        }
+        let spec = validate(&spdx_2_3(), false).unwrap();
+        assert_eq!((spec.format, spec.version.as_str()), (SbomFormat::Spdx, "2.3"));
+    }
+
+    #[test]
+    fn errors_carry_json_pointers() {
+        let mut bom = cyclonedx("1.6");
+        bom["components"][0]["type"] = json!("gadget");
+        bom["metadata"]["component"]["name"] = json!(7);
+        let err = error(&bom, false);
+        assert!(err.contains("at /components/0/type:"), "{}", err);
+        assert!(err.contains("at /metadata/component/name:"), "{}", err);
+
+        let mut doc = spdx_2_3();
+        doc["packages"][0].as_object_mut().unwrap().remove("downloadLocation");
+        let err = error(&doc, false);
+        assert!(err.contains("at /packages/0:"), "{}", err);
+    }
+
+    #[test]
+    fn lenient_tolerates_only_benign_keywords() {
+        let mut bom = cyclonedx("1.4");
+        bom["x-vendor"] = json!(true); // additionalProperties
+        bom["metadata"]["timestamp"] = json!("yesterday"); // format
+        bom["serialNumber"] = json!("not-a-urn"); // pattern
+        let err = error(&bom, false);
+        assert!(err.contains("(3 errors)"), "{}", err);
+        assert!(err.contains("at /serialNumber:"), "{}", err);
+        assert!(err.contains("at /metadata/timestamp:"), "{}", err);
+        validate(&bom, true).unwrap();
+
+        // A missing required property fails even when lenient.
+        bom["components"][0].as_object_mut().unwrap().remove("name");
+        let err = error(&bom, true);
+        assert!(err.contains("(1 error)"), "{}", err);
+        assert!(err.contains("at /components/0:") && err.contains("name"), "{}", err);
+
+        let mut doc = spdx_2_3();
+        doc["creationInfo"]["creators"] = json!(["qs_notary"]); // pattern
+        validate(&doc, true).unwrap();
+        doc.as_object_mut().unwrap().remove("dataLicense");
+        assert!(error(&doc, true).contains("dataLicense"));
+    }
+}
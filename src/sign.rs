@@ -1,89 +1,315 @@
 //! Sign command: validate SBOM, hash, sign, write .sig, append ledger.
 
-use crate::key_provider::KeyProvider;
-use crate::ledger;
+use crate::attestation::{self, DsseEnvelope};
+use crate::canonical::{parse_strict, Canonicalization};
+use crate::client;
+use crate::key_provider::{ArtifactSignature, KeyProvider};
+use crate::ledger::{self, LedgerEntry, Rotation};
+use crate::outbox;
+use crate::protocol::{Receipt, SignatureEnvelope, SignedAttributes, SignedUpload, TimestampToken};
+use crate::jsf;
+use crate::sbom::{self, SbomEncoding, SbomFormat, SbomSpec};
 use anyhow::anyhow;
 use sha3::{Digest, Sha3_256};
-use std::path::Path;
-
-/// Validate that the file is valid JSON and contains CycloneDX or SPDX format markers.
-pub fn validate_sbom_json(bytes: &[u8]) -> anyhow::Result<()> {
-    let value: serde_json::Value = serde_json::from_slice(bytes)
-        .map_err(|e| anyhow!("Invalid JSON: {}", e))?;
-    let obj = value
-        .as_object()
-        .ok_or_else(|| anyhow!("SBOM root must be a JSON object"))?;
-    let has_cyclonedx = obj
-        .get("bomFormat")
-        .and_then(|v| v.as_str())
-        .map(|s| s == "CycloneDX")
-        .unwrap_or(false);
-    let has_spdx = obj.get("spdxVersion").and_then(|v| v.as_str()).is_some();
-    if has_cyclonedx || has_spdx {
-        Ok(())
-    } else {
-        Err(anyhow!(
-            "Invalid SBOM: missing bomFormat (CycloneDX) or spdxVersion (SPDX)"
-        ))
-    }
+use std::path::{Path, PathBuf};
+
+/// Validate that the file is a CycloneDX (JSON, XML) or SPDX (JSON, tag-value, RDF/XML) SBOM that
+/// matches its spec version, and return the detected format, version and encoding (see
+/// `sbom::validate`; `lenient` tolerates known-benign deviations).
+pub fn validate_sbom(bytes: &[u8], lenient: bool) -> anyhow::Result<SbomSpec> {
+    sbom::validate(bytes, lenient)
 }
 
-/// Run the sign command: validate SBOM, compute SHA3-256, sign, write .sig, append ledger.
-/// If server_url is set, spawns a background task to POST the ledger entry to the server (warns on failure).
-pub fn run(
-    sbom_path: &Path,
+/// Where new ledger entries go when signing with a server URL.
+pub struct Upload<'a> {
+    pub server_url: &'a str,
+    pub outbox_dir: &'a Path,
+    /// Upload right away and embed the server's receipt in the .sig instead of only queuing.
+    pub wait_for_receipt: bool,
+}
+
+/// Send a new ledger entry to the log server. With `wait_for_receipt` it is uploaded now and the
+/// receipt returned; otherwise, or if that upload fails, it is queued in the outbox.
+pub(crate) fn submit(
+    entry: &LedgerEntry,
     key_provider: &dyn KeyProvider,
-    ledger_path: &Path,
-    server_url: Option<&str>,
-) -> anyhow::Result<()> {
-    let bytes = std::fs::read(sbom_path)
-        .map_err(|e| anyhow!("Failed to read SBOM {}: {}", sbom_path.display(), e))?;
+    upload: &Upload,
+) -> anyhow::Result<Option<Receipt>> {
+    let signed = SignedUpload::sign(entry.clone(), key_provider)?;
+    if upload.wait_for_receipt {
+        match client::upload(upload.server_url, &signed) {
+            Ok(receipt) => return Ok(Some(receipt)),
+            Err(e) => eprintln!(
+                "Warning: {}; entry queued for later upload, {} has no receipt",
+                e, entry.file_name
+            ),
+        }
+    }
+    outbox::enqueue(upload.outbox_dir, upload.server_url, &signed)?;
+    Ok(None)
+}
 
-    validate_sbom_json(&bytes)?;
+/// Get a timestamp token for a signature from the timestamp authority at `tsa_url`.
+/// The token covers the SHA3-256 of the signature bytes, proving the signature existed at that time.
+pub(crate) fn timestamp_signature(
+    tsa_url: &str,
+    sig_bytes: &[u8],
+) -> anyhow::Result<TimestampToken> {
+    client::timestamp(tsa_url, &Sha3_256::digest(sig_bytes))
+}
 
-    let hash = Sha3_256::digest(&bytes);
-    let sig_bytes = key_provider.sign(&hash)?;
+/// The .sig envelope for a new entry. The logged entry is included with the server's receipt for
+/// it when the upload was synchronous (so `verify` can check inclusion offline).
+fn envelope_for(
+    entry: &LedgerEntry,
+    timestamp_token: Option<TimestampToken>,
+    receipt: Option<Receipt>,
+) -> SignatureEnvelope {
+    SignatureEnvelope {
+        signature: entry.signature_hash.clone(),
+        attributes: entry.attributes.clone(),
+        timestamp: Some(entry.timestamp.clone()),
+        timestamp_token,
+        log_entry: receipt.is_some().then(|| entry.clone()),
+        receipt,
+        inclusion_proof: None,
+    }
+}
 
-    // Convention: same path with .sig appended (e.g. sbom.json -> sbom.json.sig)
-    let sig_path = {
-        let ext = sbom_path
-            .extension()
-            .map(|e| format!("{}.sig", e.to_string_lossy()))
-            .unwrap_or_else(|| "sig".to_string());
-        sbom_path.with_extension(ext)
-    };
-    let timestamp = chrono::Utc::now().to_rfc3339();
-    let wrapped = serde_json::json!({
-        "signature": hex::encode(&sig_bytes),
-        "timestamp": timestamp,
-    });
-    std::fs::write(&sig_path, wrapped.to_string())
+/// Convention: same path with .sig appended (e.g. sbom.json -> sbom.json.sig).
+pub fn sig_path_for(file_path: &Path) -> PathBuf {
+    let ext = file_path
+        .extension()
+        .map(|e| format!("{}.sig", e.to_string_lossy()))
+        .unwrap_or_else(|| "sig".to_string());
+    file_path.with_extension(ext)
+}
+
+/// Where `sign` and `sign-all` record signatures.
+pub struct SignOptions<'a> {
+    pub ledger_path: &'a Path,
+    /// Seal and rotate the ledger first when due.
+    pub rotation: &'a Rotation,
+    /// Timestamp authority; the signature is timestamped before anything is written.
+    pub tsa_url: Option<&'a str>,
+    /// Log server for new entries (see `submit`); queued entries are flushed by the caller.
+    pub upload: Option<Upload<'a>>,
+}
+
+/// Sign the SHA3-256 `hash` of `file_path` (with `attributes`, if any), append the ledger entry,
+/// send it to the log server and write `<file>.sig`. Returns the hex signature.
+pub(crate) fn sign_file(
+    file_path: &Path,
+    hash: &[u8],
+    attributes: Option<SignedAttributes>,
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+) -> anyhow::Result<String> {
+    let envelope = sign_digest(file_path, hash, attributes, key_provider, opts)?;
+    let sig_path = sig_path_for(file_path);
+    std::fs::write(&sig_path, serde_json::to_string(&envelope)?)
         .map_err(|e| anyhow!("Failed to write signature {}: {}", sig_path.display(), e))?;
-    let file_name = sbom_path
+    Ok(envelope.signature)
+}
+
+fn file_name_of(file_path: &Path) -> String {
+    file_path
         .file_name()
         .and_then(|p| p.to_str())
         .unwrap_or("")
-        .to_string();
-    let signature_hash = hex::encode(&sig_bytes);
-    ledger::append_entry(ledger_path, timestamp.clone(), file_name.clone(), signature_hash.clone())?;
-
-    if let Some(url) = server_url {
-        let url = url.trim_end_matches('/').to_string();
-        let file_name = file_name.clone();
-        let signature_hash = signature_hash.clone();
-        let timestamp = timestamp.clone();
-        std::thread::spawn(move || {
-            let upload_url = format!("{}/upload", url);
-            let body = serde_json::json!({
-                "file_name": file_name,
-                "signature_hash": signature_hash,
-                "timestamp": timestamp,
-            });
-            if let Err(e) = ureq::post(&upload_url).send_json(body) {
-                eprintln!("Warning: could not reach server: {}", e);
-            }
-        });
+        .to_string()
+}
+
+/// Sign the SHA3-256 `hash` of `file_path` (with `attributes`, if any), append the ledger entry and
+/// send it to the log server. Returns the envelope for the signature.
+/// Entries signed by a signing service (`--key remote://...`) were logged by that service and are
+/// only appended locally.
+fn sign_digest(
+    file_path: &Path,
+    hash: &[u8],
+    attributes: Option<SignedAttributes>,
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+) -> anyhow::Result<SignatureEnvelope> {
+    let file_name = file_name_of(file_path);
+    // Rotate first: a signing service logs the entry as it signs, so fail before that.
+    ledger::rotate_if_needed(opts.ledger_path, opts.rotation, key_provider)?;
+    let signed = key_provider.sign_artifact(hash, &file_name, attributes.as_ref())?;
+    record(file_name, hash, attributes, signed, key_provider, opts)
+}
+
+/// Timestamp a signature over `hash` if asked to, append its ledger entry (unless a signing service
+/// already logged it) and send it to the log server. Returns the envelope for the signature.
+fn record(
+    file_name: String,
+    hash: &[u8],
+    attributes: Option<SignedAttributes>,
+    signed: ArtifactSignature,
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+) -> anyhow::Result<SignatureEnvelope> {
+    let timestamp_token = opts
+        .tsa_url
+        .map(|url| timestamp_signature(url, &signed.signature))
+        .transpose()?;
+    let (entry, receipt) = match signed.logged {
+        Some((entry, receipt)) => (entry, Some(receipt)),
+        None => (
+            LedgerEntry {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                file_name,
+                signature_hash: hex::encode(&signed.signature),
+                digest: Some(hex::encode(hash)),
+                key_id: key_provider.key_id(),
+                revocation: None,
+                attributes,
+            },
+            None,
+        ),
+    };
+    ledger::append_entry(opts.ledger_path, &entry)?;
+
+    let receipt = match (receipt, &opts.upload) {
+        (Some(receipt), _) => Some(receipt),
+        (None, Some(upload)) => submit(&entry, key_provider, upload)?,
+        (None, None) => None,
+    };
+    Ok(envelope_for(&entry, timestamp_token, receipt))
+}
+
+/// Sign a CycloneDX JSON BOM with a JSF signature embedded in the document (see `jsf`) and rewrite
+/// it in place. The entry is logged as for a detached signature, but the signature over the
+/// canonical BOM carries no attributes, timestamp token or receipt.
+fn sign_embedded(
+    sbom_path: &Path,
+    bytes: &[u8],
+    spec: &SbomSpec,
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+) -> anyhow::Result<()> {
+    if spec.format != SbomFormat::CycloneDx || spec.encoding != SbomEncoding::Json {
+        return Err(anyhow!("--embed needs a CycloneDX JSON SBOM, not {}", spec));
+    }
+    if opts.tsa_url.is_some() || opts.upload.as_ref().is_some_and(|u| u.wait_for_receipt) {
+        return Err(anyhow!(
+            "An embedded signature has no room for a timestamp token or receipt; sign without --embed for those"
+        ));
+    }
+    let template = jsf::template(bytes, key_provider.key_id())?;
+    let envelope = sign_digest(sbom_path, &template.digest, None, key_provider, opts)?;
+    let signature = hex::decode(&envelope.signature)?;
+    std::fs::write(sbom_path, jsf::embed(bytes, template.signature, &signature)?)
+        .map_err(|e| anyhow!("Failed to write SBOM {}: {}", sbom_path.display(), e))
+}
+
+/// Convention: same path with .intoto.json appended (e.g. sbom.json -> sbom.json.intoto.json).
+pub fn attestation_path_for(file_path: &Path) -> PathBuf {
+    let mut name = file_path.as_os_str().to_owned();
+    name.push(".intoto.json");
+    PathBuf::from(name)
+}
+
+/// Sign a JSON SBOM as an in-toto attestation (see `attestation`): a statement with the SBOM as
+/// predicate about the artifacts it describes and the `subject_files`, in a DSSE envelope written to
+/// `<file>.intoto.json`. The key signs the envelope's PAE itself, as DSSE specifies, so signing
+/// services (which only sign digests) cannot; the entry is logged with the SHA3-256 of the PAE as
+/// its digest.
+fn sign_attestation(
+    sbom_path: &Path,
+    bytes: &[u8],
+    spec: &SbomSpec,
+    subject_files: &[PathBuf],
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+) -> anyhow::Result<()> {
+    if spec.encoding != SbomEncoding::Json {
+        return Err(anyhow!("--format dsse needs a JSON SBOM, not {}", spec));
+    }
+    if opts.tsa_url.is_some() || opts.upload.as_ref().is_some_and(|u| u.wait_for_receipt) {
+        return Err(anyhow!(
+            "A DSSE envelope has no room for a timestamp token or receipt; sign without --format dsse for those"
+        ));
     }
+    let sbom = parse_strict(bytes)?;
+    let mut subjects = subject_files
+        .iter()
+        .map(|path| attestation::file_subject(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    subjects.extend(attestation::described_subjects(&sbom, spec.format));
+    if subjects.is_empty() {
+        return Err(anyhow!(
+            "{} names no artifact with a hash to attest to; give the artifacts with --subject",
+            sbom_path.display()
+        ));
+    }
+    let payload = serde_json::to_vec(&attestation::statement(sbom, spec.format, subjects))?;
+    let pae = attestation::pae(attestation::PAYLOAD_TYPE, &payload);
+    ledger::rotate_if_needed(opts.ledger_path, opts.rotation, key_provider)?;
+    let signed = ArtifactSignature {
+        signature: key_provider.sign(&pae)?,
+        logged: None,
+    };
+    let hash = Sha3_256::digest(&pae);
+    let envelope = record(file_name_of(sbom_path), &hash, None, signed, key_provider, opts)?;
+    let dsse = DsseEnvelope::new(&payload, &hex::decode(&envelope.signature)?, key_provider.key_id());
+    let path = attestation_path_for(sbom_path);
+    std::fs::write(&path, serde_json::to_string_pretty(&dsse)?)
+        .map_err(|e| anyhow!("Failed to write attestation {}: {}", path.display(), e))
+}
+
+/// Signature file format chosen with `sign --format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// qs_notary .sig envelope.
+    Sig,
+    /// DSSE envelope with an in-toto statement.
+    Dsse,
+}
+
+/// How `sign` attaches the signature to the SBOM.
+pub enum Output {
+    /// `<file>.sig` next to the SBOM, over the SHA3-256 of the file (or of its canonical form).
+    Detached(Option<Canonicalization>),
+    /// A JSF signature inside the (CycloneDX JSON) SBOM.
+    Embedded,
+    /// `<file>.intoto.json`: a DSSE envelope with an in-toto statement about these artifacts (and
+    /// those the SBOM describes).
+    Attestation(Vec<PathBuf>),
+}
+
+/// Run the sign command: validate SBOM, then embed a JSF signature (see `sign_embedded`), write an
+/// in-toto attestation (see `sign_attestation`) or compute SHA3-256 (of its canonical form, if requested), sign it with its detected format and
+/// spec version as signed attributes and record it (see `sign_file`).
+pub fn run(
+    sbom_path: &Path,
+    key_provider: &dyn KeyProvider,
+    opts: &SignOptions,
+    lenient: bool,
+    output: Output,
+) -> anyhow::Result<()> {
+    let bytes = std::fs::read(sbom_path)
+        .map_err(|e| anyhow!("Failed to read SBOM {}: {}", sbom_path.display(), e))?;
+
+    let spec = validate_sbom(&bytes, lenient)?;
+    let canonical = match output {
+        Output::Embedded => return sign_embedded(sbom_path, &bytes, &spec, key_provider, opts),
+        Output::Attestation(subjects) => {
+            return sign_attestation(sbom_path, &bytes, &spec, &subjects, key_provider, opts)
+        }
+        Output::Detached(canonical) => canonical,
+    };
+    let mut attributes = spec.attributes();
+    let hash = match canonical {
+        Some(Canonicalization::Jcs) if spec.encoding != SbomEncoding::Json => {
+            return Err(anyhow!("--canonical jcs needs a JSON SBOM, not {}", spec));
+        }
+        Some(canonical) => {
+            attributes.canonicalization = Some(canonical.id().to_string());
+            Sha3_256::digest(canonical.apply(&bytes)?)
+        }
+        None => Sha3_256::digest(&bytes),
+    };
 
+    sign_file(sbom_path, &hash, Some(attributes), key_provider, opts)?;
     Ok(())
 }
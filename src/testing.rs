@@ -0,0 +1,297 @@
+//! Test helpers: a stub log server that answers `/upload`, `/sign`, `/entries` (listed, by index
+//! and by leaf) and `/entries/stream` the way qs_server does and records what it was sent, over
+//! HTTP or HTTPS.
+
+use crate::crypto::{generate_keypair, key_id, sign_hash};
+use crate::key_provider::{FileSystemProvider, KeyProvider};
+use crate::ledger::{self, EntryFilter, LedgerEntry};
+use crate::protocol::{
+    EntryPage, IndexedEntry, Receipt, ReceiptBody, SignRequest, SignatureEnvelope, SignedUpload,
+};
+use crate::tls;
+use axum::extract::{Json, Path as UrlPath, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::Router;
+use pqcrypto_dilithium::dilithium5::{keypair, PublicKey, SecretKey};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qs_notary_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A key pair generated in `dir`.
+pub fn key(dir: &Path) -> FileSystemProvider {
+    generate_keypair(dir).unwrap();
+    FileSystemProvider::new(&dir.join("private.key"))
+}
+
+/// A key pair held in memory.
+pub struct TestKey {
+    pub pk: PublicKey,
+    sk: SecretKey,
+}
+
+impl TestKey {
+    pub fn generate() -> Self {
+        let (pk, sk) = keypair();
+        Self { pk, sk }
+    }
+}
+
+impl KeyProvider for TestKey {
+    fn sign(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(sign_hash(data, &self.sk))
+    }
+
+    fn key_id(&self) -> Option<String> {
+        Some(key_id(&self.pk))
+    }
+}
+
+/// A ledger entry for `file_name`; the stub log does not check its signature.
+pub fn entry(file_name: &str) -> LedgerEntry {
+    LedgerEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        file_name: file_name.to_string(),
+        signature_hash: "00".repeat(8),
+        digest: None,
+        key_id: None,
+        revocation: None,
+        attributes: None,
+    }
+}
+
+/// A log server that keeps leaf hashes only.
+#[derive(Default)]
+pub struct StubLog {
+    /// Leaf hashes of the entries the log holds, in log order.
+    pub leaves: Mutex<Vec<String>>,
+    /// Every upload request, accepted or not.
+    pub received: Mutex<Vec<SignedUpload>>,
+    /// The `Idempotency-Key` header of each upload request.
+    pub idempotency_keys: Mutex<Vec<Option<String>>>,
+    /// Status to refuse uploads of entries with this file name with.
+    pub refuse: Mutex<HashMap<String, StatusCode>>,
+    /// What `/sign` answers, whatever it is asked (404 while unset).
+    pub signed: Mutex<Option<SignatureEnvelope>>,
+}
+
+impl StubLog {
+    pub fn refuse(&self, file_name: &str, status: StatusCode) {
+        self.refuse.lock().unwrap().insert(file_name.to_string(), status);
+    }
+
+    pub fn uploads(&self) -> usize {
+        self.received.lock().unwrap().len()
+    }
+}
+
+async fn upload(
+    State(log): State<Arc<StubLog>>,
+    headers: HeaderMap,
+    Json(upload): Json<SignedUpload>,
+) -> Result<Json<Receipt>, (StatusCode, &'static str)> {
+    let refused = log.refuse.lock().unwrap().get(&upload.entry.file_name).copied();
+    let leaf = hex::encode(ledger::leaf_hash(&upload.entry));
+    let idempotency_key = headers.get("idempotency-key").and_then(|v| v.to_str().ok());
+    log.idempotency_keys.lock().unwrap().push(idempotency_key.map(str::to_string));
+    log.received.lock().unwrap().push(upload);
+    if let Some(status) = refused {
+        return Err((status, "refused"));
+    }
+    let mut leaves = log.leaves.lock().unwrap();
+    let log_index = match leaves.iter().position(|l| *l == leaf) {
+        Some(index) => index,
+        None => {
+            leaves.push(leaf.clone());
+            leaves.len() - 1
+        }
+    };
+    Ok(Json(Receipt {
+        body: ReceiptBody {
+            log_index: log_index as u64,
+            integrated_time: chrono::Utc::now().to_rfc3339(),
+            leaf_hash: leaf,
+            log_key_id: String::new(),
+        },
+        signature: String::new(),
+    }))
+}
+
+async fn by_leaf(
+    State(log): State<Arc<StubLog>>,
+    UrlPath(leaf): UrlPath<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let index = log
+        .leaves
+        .lock()
+        .unwrap()
+        .iter()
+        .position(|l| *l == leaf)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::json!({ "leaf_hash": leaf, "index": index })))
+}
+
+async fn sign(
+    State(log): State<Arc<StubLog>>,
+    Json(_request): Json<SignRequest>,
+) -> Result<Json<SignatureEnvelope>, StatusCode> {
+    log.signed.lock().unwrap().clone().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `GET /entries`: the uploads received so far that match the filter, paginated like qs_server
+/// (100 to a page by default).
+async fn entries(State(log): State<Arc<StubLog>>, Query(filter): Query<EntryFilter>) -> Json<EntryPage> {
+    let limit = filter.limit.unwrap_or(100);
+    let received = log.received.lock().unwrap();
+    let mut entries: Vec<IndexedEntry> = received
+        .iter()
+        .enumerate()
+        .skip(filter.from_index.unwrap_or(0) as usize)
+        .filter(|(_, u)| filter.matches(&u.entry))
+        .skip(filter.offset as usize)
+        .take(limit as usize + 1)
+        .map(|(index, u)| IndexedEntry {
+            index: index as u64,
+            entry: u.entry.clone(),
+        })
+        .collect();
+    let next_offset = (entries.len() as u64 > limit).then(|| filter.offset + limit);
+    entries.truncate(limit as usize);
+    Json(EntryPage { entries, next_offset })
+}
+
+async fn by_index(
+    State(log): State<Arc<StubLog>>,
+    UrlPath(index): UrlPath<usize>,
+) -> Result<Json<IndexedEntry>, StatusCode> {
+    let received = log.received.lock().unwrap();
+    let upload = received.get(index).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(IndexedEntry {
+        index: index as u64,
+        entry: upload.entry.clone(),
+    }))
+}
+
+/// Every upload received so far as one server-sent `entry` event each; then the stream ends.
+async fn stream(State(log): State<Arc<StubLog>>) -> String {
+    let received = log.received.lock().unwrap();
+    received
+        .iter()
+        .enumerate()
+        .map(|(index, upload)| {
+            let entry = IndexedEntry {
+                index: index as u64,
+                entry: upload.entry.clone(),
+            };
+            format!("event: entry\ndata: {}\n\n", serde_json::to_string(&entry).unwrap())
+        })
+        .collect()
+}
+
+fn router(log: Arc<StubLog>) -> Router {
+    Router::new()
+        .route("/upload", post(upload))
+        .route("/sign", post(sign))
+        .route("/entries", get(entries))
+        .route("/entries/:index", get(by_index))
+        .route("/entries/by-leaf/:leaf", get(by_leaf))
+        .route("/entries/stream", get(stream))
+        .with_state(log)
+}
+
+/// Bind a local port and run `serve` on it from a background thread with its own runtime.
+fn spawn<F: std::future::Future<Output = ()> + Send + 'static>(
+    scheme: &str,
+    serve: impl FnOnce(std::net::TcpListener) -> F + Send + 'static,
+) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("{}://{}", scheme, listener.local_addr().unwrap());
+    listener.set_nonblocking(true).unwrap();
+    std::thread::spawn(move || tokio::runtime::Runtime::new().unwrap().block_on(serve(listener)));
+    url
+}
+
+/// Serve `log` over HTTP on a local port; returns its URL.
+pub fn serve(log: Arc<StubLog>) -> String {
+    spawn("http", move |listener| async move {
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        axum::serve(listener, router(log)).await.unwrap();
+    })
+}
+
+/// Serve `log` over HTTPS with the certificate `cert`/`key`; with `client_ca`, only clients with
+/// a certificate issued by that CA can connect. Returns its URL.
+pub fn serve_tls(log: Arc<StubLog>, cert: &Path, key: &Path, client_ca: Option<&Path>) -> String {
+    let builder = rustls::ServerConfig::builder_with_provider(tls::provider())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = match client_ca {
+        Some(ca) => builder.with_client_cert_verifier(
+            rustls::server::WebPkiClientVerifier::builder_with_provider(
+                Arc::new(tls::root_store(ca).unwrap()),
+                tls::provider(),
+            )
+            .build()
+            .unwrap(),
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(tls::load_certs(cert).unwrap(), tls::load_private_key(key).unwrap())
+        .unwrap();
+    let config = axum_server::tls_rustls::RustlsConfig::from_config(Arc::new(config));
+    spawn("https", move |listener| async move {
+        axum_server::from_tcp_rustls(listener, config)
+            .serve(router(log).into_make_service())
+            .await
+            .unwrap();
+    })
+}
+
+/// A private CA that issues certificates for 127.0.0.1, kept as PEM files in a directory.
+pub struct TestCa {
+    dir: PathBuf,
+    cert: rcgen::Certificate,
+    key: rcgen::KeyPair,
+    /// The CA certificate, as a CA bundle.
+    pub cert_path: PathBuf,
+}
+
+impl TestCa {
+    pub fn new(dir: &Path, name: &str) -> Self {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        params.distinguished_name.push(rcgen::DnType::CommonName, name);
+        let cert = params.self_signed(&key).unwrap();
+        let cert_path = dir.join(format!("{}.ca.pem", name));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        TestCa {
+            dir: dir.to_path_buf(),
+            cert,
+            key,
+            cert_path,
+        }
+    }
+
+    /// Issue a certificate for 127.0.0.1; returns the paths of its PEM certificate and key.
+    pub fn issue(&self, name: &str) -> (PathBuf, PathBuf) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["127.0.0.1".to_string()])
+            .unwrap()
+            .signed_by(&key, &self.cert, &self.key)
+            .unwrap();
+        let cert_path = self.dir.join(format!("{}.pem", name));
+        let key_path = self.dir.join(format!("{}.key.pem", name));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+}
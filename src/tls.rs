@@ -0,0 +1,93 @@
+//! TLS for connections to qs_server: PEM loading (shared with the server) and the client options
+//! for a private CA and a client certificate (mutual TLS).
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The crypto provider for every TLS connection, client and server.
+pub fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// All certificates in a PEM file (a chain or a CA bundle); error if there are none.
+pub fn load_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid PEM in {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("No certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// The first private key (PKCS#8, PKCS#1 or SEC1) in a PEM file.
+pub fn load_private_key(path: &Path) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|e| anyhow::anyhow!("Invalid PEM in {}: {}", path.display(), e))?
+        .ok_or_else(|| anyhow::anyhow!("No private key in {}", path.display()))
+}
+
+/// A root store holding every certificate of a PEM CA bundle.
+pub fn root_store(ca_bundle: &Path) -> anyhow::Result<rustls::RootCertStore> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in load_certs(ca_bundle)? {
+        roots
+            .add(cert)
+            .map_err(|e| anyhow::anyhow!("Invalid CA certificate in {}: {}", ca_bundle.display(), e))?;
+    }
+    Ok(roots)
+}
+
+/// Client TLS options, used for `https://` server URLs.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct ClientTls {
+    /// PEM bundle of CA certificates trusted for https:// servers, instead of the built-in web
+    /// roots (for a server with a private CA).
+    #[arg(long, env = "QS_CA_CERT", global = true)]
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate chain, for servers that require mutual TLS.
+    #[arg(long, env = "QS_CLIENT_CERT", requires = "client_key", global = true)]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate.
+    #[arg(long, env = "QS_CLIENT_KEY", requires = "client_cert", global = true)]
+    pub client_key: Option<PathBuf>,
+}
+
+impl ClientTls {
+    /// The rustls client config for these options; `None` if none are set (the built-in roots,
+    /// no client certificate).
+    pub fn client_config(&self) -> anyhow::Result<Option<rustls::ClientConfig>> {
+        if self.ca_cert.is_none() && self.client_cert.is_none() && self.client_key.is_none() {
+            return Ok(None);
+        }
+        let roots = match &self.ca_cert {
+            Some(path) => root_store(path)?,
+            None => rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            },
+        };
+        let builder = rustls::ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots);
+        let config = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_private_key(key)?)
+                .map_err(|e| anyhow::anyhow!("Invalid client certificate {}: {}", cert.display(), e))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "A client certificate needs both --client-cert and --client-key"
+                ))
+            }
+        };
+        Ok(Some(config))
+    }
+}
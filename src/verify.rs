@@ -1,86 +1,838 @@
-//! Verify command: load key and signature, recompute hash, verify, optional policy, colored output.
+//! Verify command: load key and signature, recompute hash, verify, optional receipt or inclusion
+//! proof, policy (allowlist, trusted timestamps, max age, witnesses) and key registry checks,
+//! colored output.
 
-use crate::crypto::{load_public_key, load_signature, verify_signature};
+use crate::attestation::{self, DsseEnvelope, Statement};
+use crate::canonical::{jcs, parse_strict, Canonicalization};
+use crate::client;
+use crate::jsf;
+use crate::schema;
+use crate::crypto::{key_id, load_public_key, load_signature, verify_signature};
 use crate::policy::Policy;
+use crate::ledger::LedgerEntry;
+use crate::protocol::{
+    artifact_message, RegistrySnapshot, SignatureEnvelope, Verdict, WitnessedInclusion,
+};
 use colored::Colorize;
 use pqcrypto_traits::sign::PublicKey as PublicKeyTrait;
-use pqcrypto_dilithium::dilithium5::DetachedSignature;
+use pqcrypto_dilithium::dilithium5::PublicKey;
 use sha3::{Digest, Sha3_256};
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
-/// Parsed signature file: raw bytes and optional timestamp (from wrapped JSON).
-fn load_signature_file(path: &Path) -> anyhow::Result<(Vec<u8>, Option<String>)> {
+/// Read a `.sig` file: the wrapped JSON envelope, or a legacy raw signature (which gets an
+/// envelope with only the signature).
+pub fn load_signature_file(path: &Path) -> anyhow::Result<SignatureEnvelope> {
     let content = std::fs::read(path).map_err(|e| {
         anyhow::anyhow!("Failed to read signature {}: {}", path.display(), e)
     })?;
     if content.first() == Some(&b'{') {
-        let value: serde_json::Value = serde_json::from_slice(&content)
-            .map_err(|e| anyhow::anyhow!("Invalid signature JSON: {}", e))?;
-        let obj = value.as_object().ok_or_else(|| anyhow::anyhow!("Signature JSON must be object"))?;
-        let sig_hex = obj.get("signature").and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing 'signature' in signature file"))?;
-        let sig_bytes = hex::decode(sig_hex).map_err(|e| anyhow::anyhow!("Invalid signature hex: {}", e))?;
-        let timestamp = obj.get("timestamp").and_then(|v| v.as_str()).map(String::from);
-        Ok((sig_bytes, timestamp))
+        serde_json::from_slice(&content)
+            .map_err(|e| anyhow::anyhow!("Invalid signature JSON: {}", e))
     } else {
-        Ok((content, None))
+        Ok(SignatureEnvelope {
+            signature: hex::encode(&content),
+            attributes: None,
+            timestamp: None,
+            timestamp_token: None,
+            log_entry: None,
+            receipt: None,
+            inclusion_proof: None,
+        })
     }
 }
 
+/// Where `log prove` puts the log entry and inclusion proof for a signature that cannot hold them
+/// (a DSSE attestation or a signature embedded in the SBOM): `<path>.proof.json`.
+pub fn proof_path_for(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".proof.json");
+    PathBuf::from(name)
+}
+
+/// Take the log entry and inclusion proof from the proof file at `proof_path`, if there is one for
+/// this signature.
+fn attach_proof(envelope: &mut SignatureEnvelope, proof_path: &Path) -> anyhow::Result<()> {
+    if !proof_path.exists() {
+        return Ok(());
+    }
+    let proof = load_signature_file(proof_path)?;
+    if proof.signature.eq_ignore_ascii_case(&envelope.signature) {
+        envelope.log_entry = proof.log_entry;
+        envelope.inclusion_proof = proof.inclusion_proof;
+    }
+    Ok(())
+}
+
+/// Time from the timestamp token, which must cover this signature and be signed by one of the
+/// trusted TSA keys (hex public keys from the policy).
+fn trusted_timestamp(
+    envelope: &SignatureEnvelope,
+    sig_bytes: &[u8],
+    trusted_keys: &[String],
+) -> anyhow::Result<String> {
+    let token = envelope.timestamp_token.as_ref().ok_or_else(|| {
+        anyhow::anyhow!("Verification failed: signature has no timestamp token from a trusted TSA.")
+    })?;
+    let sig_digest = Sha3_256::digest(sig_bytes);
+    for key_hex in trusted_keys {
+        let pk = hex::decode(key_hex.trim())
+            .ok()
+            .and_then(|b| PublicKey::from_bytes(&b).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid public key in policy trusted_tsa_keys"))?;
+        if key_id(&pk).eq_ignore_ascii_case(&token.body.tsa_key_id) {
+            token
+                .verify(&sig_digest, &pk)
+                .map_err(|e| anyhow::anyhow!("Verification failed: {}.", e))?;
+            return Ok(token.body.time.clone());
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Verification failed: timestamp token is not from a trusted TSA."
+    ))
+}
+
+/// The logged entry in the envelope, which must record this signature, artifact digest, signing
+/// key and signed attributes.
+fn logged_entry<'a>(
+    envelope: &'a SignatureEnvelope,
+    digest: &[u8],
+    pk: &PublicKey,
+) -> anyhow::Result<&'a LedgerEntry> {
+    let entry = envelope
+        .log_entry
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("signature file has no logged entry"))?;
+    if !entry.signature_hash.eq_ignore_ascii_case(&envelope.signature) {
+        return Err(anyhow::anyhow!("logged entry is for a different signature"));
+    }
+    if entry
+        .digest
+        .as_deref()
+        .is_some_and(|d| !d.eq_ignore_ascii_case(&hex::encode(digest)))
+    {
+        return Err(anyhow::anyhow!("logged entry is for a different artifact"));
+    }
+    if entry
+        .key_id
+        .as_deref()
+        .is_some_and(|k| !k.eq_ignore_ascii_case(&key_id(pk)))
+    {
+        return Err(anyhow::anyhow!("logged entry names a different signing key"));
+    }
+    if entry.attributes != envelope.attributes {
+        return Err(anyhow::anyhow!("logged entry has different signed attributes"));
+    }
+    Ok(entry)
+}
+
+/// Prove offline that this signature was logged, with the receipt or else the inclusion proof
+/// (its tree head must be signed by the log key). Returns the detail and the log's time for it.
+fn check_inclusion(
+    envelope: &SignatureEnvelope,
+    digest: &[u8],
+    pk: &PublicKey,
+    log_pk: &PublicKey,
+) -> anyhow::Result<(String, String)> {
+    if let Some(ref receipt) = envelope.receipt {
+        receipt.verify(logged_entry(envelope, digest, pk)?, log_pk)?;
+        return Ok((
+            format!("Logged at index {}", receipt.body.log_index),
+            receipt.body.integrated_time.clone(),
+        ));
+    }
+    let inclusion = envelope
+        .inclusion_proof
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("signature file has no inclusion receipt or proof"))?;
+    check_proof(inclusion, logged_entry(envelope, digest, pk)?, log_pk)?;
+    let head = &inclusion.tree_head.tree_head.body;
+    Ok((
+        format!("Logged at index {} (tree size {})", inclusion.proof.index, head.tree_size),
+        head.timestamp.clone(),
+    ))
+}
+
+/// The inclusion proof must lead from `entry` to a tree head signed by the log key.
+fn check_proof(
+    inclusion: &WitnessedInclusion,
+    entry: &LedgerEntry,
+    log_pk: &PublicKey,
+) -> anyhow::Result<()> {
+    let head = &inclusion.tree_head.tree_head;
+    head.verify(log_pk)?;
+    inclusion.proof.verify(entry, &head.body)
+}
+
+/// Witness check: the inclusion proof must lead to a log-signed tree head cosigned by at least
+/// `min` distinct witnesses among `witness_keys` (hex public keys from the policy).
+fn check_witnesses(
+    envelope: &SignatureEnvelope,
+    digest: &[u8],
+    pk: &PublicKey,
+    log_pk: Option<&PublicKey>,
+    witness_keys: &[String],
+    min: usize,
+) -> anyhow::Result<String> {
+    let log_pk = log_pk.ok_or_else(|| {
+        anyhow::anyhow!("Verification failed: witness cosignatures need the log's public key.")
+    })?;
+    let inclusion = envelope.inclusion_proof.as_ref().ok_or_else(|| {
+        anyhow::anyhow!("Verification failed: signature file has no inclusion proof (see `log prove`).")
+    })?;
+    check_proof(inclusion, logged_entry(envelope, digest, pk)?, log_pk)
+        .map_err(|e| anyhow::anyhow!("Verification failed: {}.", e))?;
+    let head = &inclusion.tree_head;
+    let mut witnesses = HashSet::new();
+    for key_hex in witness_keys {
+        let witness = hex::decode(key_hex.trim())
+            .ok()
+            .and_then(|b| PublicKey::from_bytes(&b).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid public key in policy witness_keys"))?;
+        let id = key_id(&witness);
+        let cosigned = head
+            .cosignatures
+            .iter()
+            .any(|c| c.verify(&head.tree_head.body, &witness).is_ok());
+        if cosigned {
+            witnesses.insert(id);
+        }
+    }
+    if witnesses.len() < min {
+        return Err(anyhow::anyhow!(
+            "Verification failed: tree head of size {} has {} trusted witness cosignature(s), policy requires {}.",
+            head.tree_head.body.tree_size,
+            witnesses.len(),
+            min
+        ));
+    }
+    Ok(format!(
+        "tree head of size {} cosigned by {} trusted witness(es)",
+        head.tree_head.body.tree_size,
+        witnesses.len()
+    ))
+}
+
+/// Policy checks: key allowlist, trusted timestamp, maximum age, witnesses. Returns the trusted
+/// timestamp token's time if the policy required one and it checked out.
+fn check_policy(
+    verdict: &mut Verdict,
+    policy: &Policy,
+    envelope: &SignatureEnvelope,
+    digest: &[u8],
+    pk: &PublicKey,
+    log_pk: Option<&PublicKey>,
+) -> Option<String> {
+    let sig_bytes = hex::decode(&envelope.signature).unwrap_or_default();
+    if let Some(ref list) = policy.allowed_public_keys {
+        let pk_hex_lower = hex::encode(pk.as_bytes()).to_lowercase();
+        let allowed = list.iter().any(|s| s.trim().to_lowercase() == pk_hex_lower);
+        verdict.push(
+            "allowlist",
+            if allowed {
+                Ok("public key is allowed".to_string())
+            } else {
+                Err(anyhow::anyhow!("Verification failed: public key not in policy allowlist."))
+            },
+        );
+    }
+    // With trusted TSA keys, only a valid token's time counts, never the signer's own clock.
+    let tsa_time = policy.trusted_tsa_keys.as_ref().and_then(|keys| {
+        let result = trusted_timestamp(envelope, &sig_bytes, keys);
+        let time = result.as_ref().ok().cloned();
+        verdict.push("trusted_timestamp", result.map(|t| format!("timestamped at {}", t)));
+        time
+    });
+    let timestamp = match policy.trusted_tsa_keys {
+        Some(_) => tsa_time.clone(),
+        None => envelope.timestamp.clone(),
+    };
+    if let Some(max_days) = policy.max_age_days {
+        if !policy.allow_expired {
+            let result = timestamp
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Verification failed: signature has no timestamp; cannot apply max_age_days.")
+                })
+                .and_then(|ts| {
+                    chrono::DateTime::parse_from_rfc3339(&ts)
+                        .map_err(|e| anyhow::anyhow!("Invalid timestamp in signature: {}", e))
+                })
+                .and_then(|t| {
+                    let age_days = (chrono::Utc::now() - t.with_timezone(&chrono::Utc)).num_days();
+                    if age_days > max_days as i64 {
+                        Err(anyhow::anyhow!("Verification failed: signature older than max_age_days."))
+                    } else {
+                        Ok(format!("signature is {} day(s) old", age_days))
+                    }
+                });
+            verdict.push("max_age", result);
+        }
+    }
+    if let Some(ref keys) = policy.witness_keys {
+        let min = policy.min_witnesses.unwrap_or(1);
+        verdict.push(
+            "witnesses",
+            check_witnesses(envelope, digest, pk, log_pk, keys, min),
+        );
+    }
+    tsa_time
+}
+
+/// Registry check: the key must be enrolled, and if it was revoked, trusted evidence (a verified
+/// receipt or timestamp token, never the signer's own clock) must show it signed before the
+/// revocation took effect.
+fn check_registry(
+    registry: &RegistrySnapshot,
+    pk: &PublicKey,
+    signed_before: Option<chrono::DateTime<chrono::Utc>>,
+) -> anyhow::Result<String> {
+    let info = registry
+        .get(&key_id(pk))
+        .ok_or_else(|| anyhow::anyhow!("Verification failed: key is not in the registry."))?;
+    let Some(ref revocation) = info.revocation else {
+        return Ok("key is enrolled".to_string());
+    };
+    let effective_at = chrono::DateTime::parse_from_rfc3339(&revocation.effective_at)
+        .map_err(|e| anyhow::anyhow!("Invalid revocation time in registry: {}", e))?;
+    match signed_before {
+        Some(t) if t < effective_at => Ok(format!(
+            "key revoked from {}, but the signature predates it",
+            revocation.effective_at
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Verification failed: key revoked from {} ({}).",
+            revocation.effective_at,
+            revocation.reason
+        )),
+    }
+}
+
+/// Load a registry snapshot from a server (`GET /keys`) or from a file holding that response.
+pub fn load_registry(source: &str) -> anyhow::Result<RegistrySnapshot> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return client::registry(source);
+    }
+    let bytes = std::fs::read(source)
+        .map_err(|e| anyhow::anyhow!("Failed to read registry {}: {}", source, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| anyhow::anyhow!("Invalid registry {}: {}", source, e))
+}
+
+/// SHA3-256 the signature in `envelope` is over: of the artifact bytes, or of their canonical form
+/// if the signed attributes name a canonicalization.
+pub fn artifact_digest(bytes: &[u8], envelope: &SignatureEnvelope) -> anyhow::Result<Vec<u8>> {
+    let canonicalization = envelope
+        .attributes
+        .as_ref()
+        .and_then(|a| a.canonicalization.as_deref());
+    Ok(match canonicalization {
+        Some(id) => Sha3_256::digest(Canonicalization::from_id(id)?.apply(bytes)?).to_vec(),
+        None => Sha3_256::digest(bytes).to_vec(),
+    })
+}
+
+/// What a signature is over.
+#[derive(Clone, Copy)]
+pub enum Signed<'a> {
+    /// An artifact's SHA3-256 (or that of its canonical form), signed with the envelope's signed
+    /// attributes, if any; its entry is logged with this digest.
+    Artifact(&'a [u8]),
+    /// A DSSE envelope's PAE, signed as is; its entry is logged with the PAE's SHA3-256.
+    Pae(&'a [u8]),
+}
+
+/// Run every check `verify` performs on a signature over `signed`: the signature itself, the
+/// inclusion receipt (or proof) when `log_pk` is given, the policy rules and the key's registry
+/// status. Log entries, receipts and timestamps are matched against the logged digest.
+pub fn evaluate(
+    signed: Signed,
+    envelope: &SignatureEnvelope,
+    pk: &PublicKey,
+    policy: Option<&Policy>,
+    log_pk: Option<&PublicKey>,
+    registry: Option<&RegistrySnapshot>,
+) -> Verdict {
+    let (digest, message) = match signed {
+        Signed::Artifact(digest) => (
+            digest.to_vec(),
+            artifact_message(digest, envelope.attributes.as_ref()),
+        ),
+        Signed::Pae(pae) => (Sha3_256::digest(pae).to_vec(), Ok(pae.to_vec())),
+    };
+    let digest = digest.as_slice();
+    let mut verdict = Verdict::default();
+    let sig_bytes = hex::decode(&envelope.signature).unwrap_or_default();
+    let signature = message
+        .and_then(|message| verify_signature(&load_signature(&sig_bytes)?, &message, pk))
+        .map(|_| match envelope.attributes {
+            Some(ref a) => format!(
+                "signature is valid for a {} {} SBOM ({})",
+                a.sbom_format, a.spec_version, a.encoding
+            ),
+            None => "signature is valid".to_string(),
+        })
+        .map_err(|_| anyhow::anyhow!("Signature verification failed"));
+    verdict.push("signature", signature);
+    // Times vouched for by a third party, used to date the signature against revocations.
+    let mut trusted_times = Vec::new();
+    if let Some(log_pk) = log_pk {
+        let inclusion = check_inclusion(envelope, digest, pk, log_pk)
+            .map(|(detail, logged_time)| {
+                trusted_times.push(logged_time);
+                detail
+            })
+            .map_err(|e| anyhow::anyhow!("Inclusion check failed: {}", e));
+        verdict.push("log_inclusion", inclusion);
+    }
+    if let Some(policy) = policy {
+        trusted_times.extend(check_policy(&mut verdict, policy, envelope, digest, pk, log_pk));
+    }
+    if let Some(registry) = registry {
+        let signed_before = trusted_times
+            .iter()
+            .filter_map(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&chrono::Utc))
+            .min();
+        verdict.push("registry", check_registry(registry, pk, signed_before));
+    }
+    verdict
+}
+
+/// Envelope and digest for the JSF signature embedded in a BOM by `sign --embed`; the `keyId`, if
+/// present, must name `pk`.
+fn embedded_signature(sbom_bytes: &[u8], pk: &PublicKey) -> anyhow::Result<(SignatureEnvelope, Vec<u8>)> {
+    let embedded = jsf::extract(sbom_bytes)?;
+    if let Some(ref id) = embedded.key_id {
+        if !id.eq_ignore_ascii_case(&key_id(pk)) {
+            return Err(anyhow::anyhow!(
+                "Embedded signature is by key {}, not the given public key",
+                id
+            ));
+        }
+    }
+    let envelope = SignatureEnvelope {
+        signature: hex::encode(&embedded.signature),
+        ..Default::default()
+    };
+    Ok((envelope, embedded.digest.to_vec()))
+}
+
+/// Envelope, signed message (the PAE) and statement for the DSSE attestation from
+/// `sign --format dsse`: the statement's predicate must be this SBOM (compared in canonical form)
+/// with the predicate type of its format, and each of `subject_files` must match one of its
+/// subjects.
+fn attestation_signature(
+    dsse: &DsseEnvelope,
+    sbom_bytes: &[u8],
+    subject_files: &[PathBuf],
+    pk: &PublicKey,
+) -> anyhow::Result<(SignatureEnvelope, Vec<u8>, Statement)> {
+    let statement = dsse.statement()?;
+    let sbom = parse_strict(sbom_bytes)?;
+    let predicate_type = attestation::predicate_type(schema::detect(&sbom)?.format);
+    if statement.predicate_type != predicate_type {
+        return Err(anyhow::anyhow!(
+            "Attestation predicate type is {}, not {}",
+            statement.predicate_type,
+            predicate_type
+        ));
+    }
+    if jcs(&statement.predicate) != jcs(&sbom) {
+        return Err(anyhow::anyhow!("SBOM does not match the attestation's predicate"));
+    }
+    for path in subject_files {
+        let file = attestation::file_subject(path)?;
+        if !statement.subject.iter().any(|s| s.matches(&file)) {
+            return Err(anyhow::anyhow!(
+                "{} does not match any subject of the attestation",
+                path.display()
+            ));
+        }
+    }
+    let envelope = SignatureEnvelope {
+        signature: hex::encode(dsse.signature_by(&key_id(pk))?),
+        ..Default::default()
+    };
+    Ok((envelope, dsse.signed_message()?, statement))
+}
+
+/// Run the verify command against `signature_path` (a .sig file or a DSSE attestation, whose
+/// subjects must include `subject_files`), or with `None` against the signature embedded in the
+/// SBOM.
 pub fn run(
     sbom_path: &Path,
-    signature_path: &Path,
+    signature_path: Option<&Path>,
+    subject_files: &[PathBuf],
     public_key_path: &Path,
     policy_path: Option<&Path>,
+    log_public_key_path: Option<&Path>,
+    registry_source: Option<&str>,
 ) -> anyhow::Result<()> {
     let pk = load_public_key(public_key_path)?;
-    let (sig_bytes, timestamp) = load_signature_file(signature_path)?;
-    let sig: DetachedSignature = load_signature(&sig_bytes)?;
+    let log_pk = log_public_key_path.map(load_public_key).transpose()?;
+    let policy = policy_path.map(Policy::load).transpose()?;
+    let registry = registry_source.map(load_registry).transpose()?;
 
     let sbom_bytes = std::fs::read(sbom_path).map_err(|e| {
         anyhow::anyhow!("Failed to read SBOM {}: {}", sbom_path.display(), e)
     })?;
-    let hash = Sha3_256::digest(&sbom_bytes);
+    let mut statement = None;
+    // The artifact digest, or the PAE for a DSSE attestation.
+    let (envelope, signed) = match signature_path {
+        Some(signature_path) => {
+            let content = std::fs::read(signature_path).map_err(|e| {
+                anyhow::anyhow!("Failed to read signature {}: {}", signature_path.display(), e)
+            })?;
+            if attestation::is_dsse(&content) {
+                let dsse: DsseEnvelope = serde_json::from_slice(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid DSSE envelope: {}", e))?;
+                let (mut envelope, pae, attested) =
+                    attestation_signature(&dsse, &sbom_bytes, subject_files, &pk)?;
+                attach_proof(&mut envelope, &proof_path_for(signature_path))?;
+                statement = Some(attested);
+                (envelope, pae)
+            } else if !subject_files.is_empty() {
+                return Err(anyhow::anyhow!("--subject needs a DSSE attestation from sign --format dsse"));
+            } else {
+                let envelope = load_signature_file(signature_path)?;
+                let hash = artifact_digest(&sbom_bytes, &envelope)?;
+                (envelope, hash)
+            }
+        }
+        None => {
+            let (mut envelope, digest) = embedded_signature(&sbom_bytes, &pk)?;
+            attach_proof(&mut envelope, &proof_path_for(sbom_path))?;
+            (envelope, digest)
+        }
+    };
 
-    match verify_signature(&sig, &hash, &pk) {
-        Ok(()) => {}
-        Err(_) => {
-            println!("{}", "Verification Failed".red());
-            return Err(anyhow::anyhow!("Signature verification failed"));
+    let signed = match statement {
+        Some(_) => Signed::Pae(&signed),
+        None => Signed::Artifact(&signed),
+    };
+    let verdict = evaluate(
+        signed,
+        &envelope,
+        &pk,
+        policy.as_ref(),
+        log_pk.as_ref(),
+        registry.as_ref(),
+    );
+    if let Some(failed) = verdict.checks.iter().find(|c| !c.ok) {
+        println!("{}", "Verification Failed".red());
+        return Err(anyhow::anyhow!("{}", failed.detail));
+    }
+    if let Some(ref attributes) = envelope.attributes {
+        println!(
+            "Signed as {} {} ({}{})",
+            attributes.sbom_format,
+            attributes.spec_version,
+            attributes.encoding,
+            attributes
+                .canonicalization
+                .as_deref()
+                .map_or(String::new(), |c| format!(", {} canonical form", c))
+        );
+    }
+    if let Some(ref statement) = statement {
+        println!("Attestation ({}) about:", statement.predicate_type);
+        for subject in &statement.subject {
+            let digests: Vec<String> = subject
+                .digest
+                .iter()
+                .map(|(alg, d)| format!("{}:{}", alg, d))
+                .collect();
+            println!("  {} {}", subject.name, digests.join(" "));
         }
     }
+    if let Some(inclusion) = verdict.checks.iter().find(|c| c.name == "log_inclusion") {
+        println!("{}", inclusion.detail);
+    }
+    println!("{}", "Verified Safe".green());
+    Ok(())
+}
 
-    if let Some(path) = policy_path {
-        let policy = Policy::load(path)?;
-        if let Some(ref list) = policy.allowed_public_keys {
-            let pk_hex = hex::encode(pk.as_bytes());
-            let pk_hex_lower = pk_hex.to_lowercase();
-            let allowed = list.iter().any(|s| s.trim().to_lowercase() == pk_hex_lower);
-            if !allowed {
-                println!("{}", "Verification Failed".red());
-                return Err(anyhow::anyhow!(
-                    "Verification failed: public key not in policy allowlist."
-                ));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_hash;
+    use crate::key_provider::KeyProvider;
+    use crate::merkle;
+    use crate::protocol::{
+        Cosignature, CosignatureBody, CosignedTreeHead, InclusionProof, Receipt, ReceiptBody,
+        SignedTreeHead, TimestampBody, TimestampToken, TreeHeadBody,
+    };
+    use crate::testing::TestKey;
+    use pqcrypto_dilithium::dilithium5::{keypair, SecretKey};
+
+    /// An envelope for `signature` whose logged entry records `digest` and has a receipt from
+    /// the log key `log_sk`.
+    fn logged_envelope(
+        signature: &[u8],
+        digest: &[u8],
+        pk: &PublicKey,
+        log_pk: &PublicKey,
+        log_sk: &SecretKey,
+    ) -> SignatureEnvelope {
+        let entry = LedgerEntry {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            file_name: "sbom.json".to_string(),
+            signature_hash: hex::encode(signature),
+            digest: Some(hex::encode(digest)),
+            key_id: Some(key_id(pk)),
+            revocation: None,
+            attributes: None,
+        };
+        let body = ReceiptBody {
+            log_index: 0,
+            integrated_time: entry.timestamp.clone(),
+            leaf_hash: hex::encode(crate::ledger::leaf_hash(&entry)),
+            log_key_id: key_id(log_pk),
+        };
+        let receipt = Receipt {
+            signature: hex::encode(sign_hash(&body.message().unwrap(), log_sk)),
+            body,
+        };
+        SignatureEnvelope {
+            signature: hex::encode(signature),
+            log_entry: Some(entry),
+            receipt: Some(receipt),
+            ..Default::default()
         }
-        if let Some(max_days) = policy.max_age_days {
-            if !policy.allow_expired {
-                let ts = timestamp.as_ref().ok_or_else(|| {
-                    anyhow::anyhow!("Verification failed: signature has no timestamp; cannot apply max_age_days.")
-                })?;
-                let t = chrono::DateTime::parse_from_rfc3339(ts)
-                    .map_err(|e| anyhow::anyhow!("Invalid timestamp in signature: {}", e))?;
-                let age_days = (chrono::Utc::now() - t.with_timezone(&chrono::Utc)).num_days();
-                if age_days > max_days as i64 {
-                    println!("{}", "Verification Failed".red());
-                    return Err(anyhow::anyhow!(
-                        "Verification failed: signature older than max_age_days."
-                    ));
-                }
-            }
+    }
+
+    fn passed(verdict: &Verdict, name: &str) -> bool {
+        verdict.checks.iter().any(|c| c.name == name && c.ok)
+    }
+
+    #[test]
+    fn dsse_signature_is_checked_over_the_pae_and_logged_by_its_digest() {
+        let (pk, sk) = keypair();
+        let (log_pk, log_sk) = keypair();
+        let pae = attestation::pae(attestation::PAYLOAD_TYPE, b"{}");
+        let digest = Sha3_256::digest(&pae);
+        let envelope = logged_envelope(&sign_hash(&pae, &sk), &digest, &pk, &log_pk, &log_sk);
+
+        let verdict = evaluate(Signed::Pae(&pae), &envelope, &pk, None, Some(&log_pk), None);
+        assert!(verdict.verified, "{:?}", verdict.checks);
+
+        // Taken for an artifact digest, the PAE does not match the logged entry.
+        let verdict = evaluate(Signed::Artifact(&pae), &envelope, &pk, None, Some(&log_pk), None);
+        assert!(passed(&verdict, "signature"));
+        assert!(!passed(&verdict, "log_inclusion"));
+
+        // A signature over the PAE's digest is not a signature over the PAE.
+        let envelope = logged_envelope(&sign_hash(&digest, &sk), &digest, &pk, &log_pk, &log_sk);
+        let verdict = evaluate(Signed::Pae(&pae), &envelope, &pk, None, Some(&log_pk), None);
+        assert!(!passed(&verdict, "signature"));
+    }
+
+    /// A signature by `sk` over the artifact with `digest`, logged with a receipt from `log_sk`.
+    fn signed_artifact(
+        digest: &[u8],
+        pk: &PublicKey,
+        sk: &SecretKey,
+        log_pk: &PublicKey,
+        log_sk: &SecretKey,
+    ) -> SignatureEnvelope {
+        let signature = sign_hash(&artifact_message(digest, None).unwrap(), sk);
+        logged_envelope(&signature, digest, pk, log_pk, log_sk)
+    }
+
+    fn days_ago(days: i64) -> String {
+        (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339()
+    }
+
+    #[test]
+    fn receipt_is_checked_offline_against_the_log_key() {
+        let (pk, sk) = keypair();
+        let (log_pk, log_sk) = keypair();
+        let digest = Sha3_256::digest(b"sbom");
+        let envelope = signed_artifact(&digest, &pk, &sk, &log_pk, &log_sk);
+        let check = |envelope: &SignatureEnvelope, digest: &[u8], log_pk: &PublicKey| {
+            evaluate(Signed::Artifact(digest), envelope, &pk, None, Some(log_pk), None)
+        };
+        let verdict = check(&envelope, &digest, &log_pk);
+        assert!(verdict.verified, "{:?}", verdict.checks);
+        assert!(passed(&verdict, "log_inclusion"));
+
+        let mut tampered = envelope.clone();
+        tampered.receipt.as_mut().unwrap().body.log_index += 1;
+        assert!(!passed(&check(&tampered, &digest, &log_pk), "log_inclusion"));
+        let mut tampered = envelope.clone();
+        tampered.log_entry.as_mut().unwrap().file_name = "other.json".to_string();
+        assert!(!passed(&check(&tampered, &digest, &log_pk), "log_inclusion"));
+
+        let verdict = check(&envelope, &digest, &keypair().0);
+        assert!(passed(&verdict, "signature"));
+        assert!(!passed(&verdict, "log_inclusion"));
+        assert!(!verdict.verified);
+
+        // The logged entry is for another artifact than the one checked.
+        let verdict = check(&envelope, &Sha3_256::digest(b"other"), &log_pk);
+        assert!(!passed(&verdict, "signature"));
+        assert!(!passed(&verdict, "log_inclusion"));
+    }
+
+    #[test]
+    fn policy_checks_the_allowlist_and_the_signer_timestamp_age() {
+        let (pk, sk) = keypair();
+        let digest = Sha3_256::digest(b"sbom");
+        let mut envelope = SignatureEnvelope {
+            signature: hex::encode(sign_hash(&artifact_message(&digest, None).unwrap(), &sk)),
+            timestamp: Some(days_ago(10)),
+            ..Default::default()
+        };
+        let check = |envelope: &SignatureEnvelope, policy: &Policy| {
+            evaluate(Signed::Artifact(&digest), envelope, &pk, Some(policy), None, None)
+        };
+
+        let allowed = |keys: Vec<&PublicKey>| Policy {
+            allowed_public_keys: Some(keys.iter().map(|k| hex::encode(k.as_bytes()).to_uppercase()).collect()),
+            ..Default::default()
+        };
+        assert!(check(&envelope, &allowed(vec![&keypair().0, &pk])).verified);
+        let verdict = check(&envelope, &allowed(vec![&keypair().0]));
+        assert!(!passed(&verdict, "allowlist"));
+        assert!(passed(&verdict, "signature"));
+
+        let max_age = |days, allow_expired| Policy {
+            max_age_days: Some(days),
+            allow_expired,
+            ..Default::default()
+        };
+        assert!(passed(&check(&envelope, &max_age(30, false)), "max_age"));
+        assert!(!check(&envelope, &max_age(5, false)).verified);
+        let verdict = check(&envelope, &max_age(5, true));
+        assert!(verdict.verified && verdict.checks.iter().all(|c| c.name != "max_age"));
+        envelope.timestamp = None;
+        assert!(!passed(&check(&envelope, &max_age(30, false)), "max_age"));
+    }
+
+    #[test]
+    fn trusted_timestamp_token_replaces_the_signer_clock() {
+        let (pk, sk) = keypair();
+        let tsa = TestKey::generate();
+        let digest = Sha3_256::digest(b"sbom");
+        let sig_bytes = sign_hash(&artifact_message(&digest, None).unwrap(), &sk);
+        let token = |time: String, covered: &[u8], tsa: &TestKey| {
+            let body = TimestampBody {
+                digest: hex::encode(Sha3_256::digest(covered)),
+                time,
+                serial: 1,
+                tsa_key_id: tsa.key_id().unwrap(),
+            };
+            TimestampToken::sign(body, tsa).unwrap()
+        };
+        let envelope = |token| SignatureEnvelope {
+            signature: hex::encode(&sig_bytes),
+            // The signer's own clock says the signature is fresh.
+            timestamp: Some(days_ago(0)),
+            timestamp_token: Some(token),
+            ..Default::default()
+        };
+        let policy = |max_age_days| Policy {
+            trusted_tsa_keys: Some(vec![hex::encode(tsa.pk.as_bytes())]),
+            max_age_days: Some(max_age_days),
+            ..Default::default()
+        };
+        let check = |envelope: &SignatureEnvelope, policy: &Policy| {
+            evaluate(Signed::Artifact(&digest), envelope, &pk, Some(policy), None, None)
+        };
+
+        let old = envelope(token(days_ago(10), &sig_bytes, &tsa));
+        let verdict = check(&old, &policy(30));
+        assert!(verdict.verified, "{:?}", verdict.checks);
+        assert!(passed(&verdict, "trusted_timestamp"));
+        let verdict = check(&old, &policy(5));
+        assert!(passed(&verdict, "trusted_timestamp"));
+        assert!(!passed(&verdict, "max_age"));
+
+        // A token from another TSA or for other bytes does not count, and then neither does the
+        // signer's clock.
+        for token in [
+            token(days_ago(0), &sig_bytes, &TestKey::generate()),
+            token(days_ago(0), b"other signature", &tsa),
+        ] {
+            let verdict = check(&envelope(token), &policy(30));
+            assert!(!passed(&verdict, "trusted_timestamp"));
+            assert!(!passed(&verdict, "max_age"));
         }
+        let mut backdated = token(days_ago(0), &sig_bytes, &tsa);
+        backdated.body.time = days_ago(1);
+        assert!(!passed(&check(&envelope(backdated), &policy(30)), "trusted_timestamp"));
     }
 
-    println!("{}", "Verified Safe".green());
-    Ok(())
+    #[test]
+    fn witnesses_must_cosign_the_tree_head_the_proof_leads_to() {
+        let (pk, sk) = keypair();
+        let (log_pk, log_sk) = keypair();
+        let log = |body: &TreeHeadBody| hex::encode(sign_hash(&body.message().unwrap(), &log_sk));
+        let (first, second) = (TestKey::generate(), TestKey::generate());
+        let digest = Sha3_256::digest(b"sbom");
+        let mut envelope = signed_artifact(&digest, &pk, &sk, &log_pk, &log_sk);
+        envelope.receipt = None;
+        let entry = envelope.log_entry.clone().unwrap();
+        let leaves = [[7; 32], crate::ledger::leaf_hash(&entry)];
+        let body = TreeHeadBody {
+            tree_size: 2,
+            root_hash: hex::encode(merkle::root(&leaves)),
+            timestamp: days_ago(0),
+            log_key_id: key_id(&log_pk),
+        };
+        let cosign = |witness: &TestKey| {
+            let cosignature = CosignatureBody {
+                log_key_id: body.log_key_id.clone(),
+                tree_size: body.tree_size,
+                root_hash: body.root_hash.clone(),
+                timestamp: days_ago(0),
+                witness_key_id: witness.key_id().unwrap(),
+            };
+            Cosignature::sign(cosignature, witness).unwrap()
+        };
+        envelope.inclusion_proof = Some(WitnessedInclusion {
+            proof: InclusionProof {
+                index: 1,
+                tree_size: 2,
+                proof: merkle::inclusion_proof(&leaves, 1).iter().map(hex::encode).collect(),
+            },
+            tree_head: CosignedTreeHead {
+                tree_head: SignedTreeHead {
+                    signature: log(&body),
+                    body: body.clone(),
+                },
+                cosignatures: vec![cosign(&first)],
+            },
+        });
+        let policy = |witnesses: &[&TestKey], min_witnesses| Policy {
+            witness_keys: Some(witnesses.iter().map(|w| hex::encode(w.pk.as_bytes())).collect()),
+            min_witnesses,
+            ..Default::default()
+        };
+        let check = |envelope: &SignatureEnvelope, policy: &Policy, log_pk: Option<&PublicKey>| {
+            evaluate(Signed::Artifact(&digest), envelope, &pk, Some(policy), log_pk, None)
+        };
+
+        let verdict = check(&envelope, &policy(&[&first, &second], None), Some(&log_pk));
+        assert!(verdict.verified, "{:?}", verdict.checks);
+        assert!(passed(&verdict, "log_inclusion") && passed(&verdict, "witnesses"));
+        let verdict = check(&envelope, &policy(&[&first, &second], Some(2)), Some(&log_pk));
+        assert!(!passed(&verdict, "witnesses"));
+        assert!(!passed(&check(&envelope, &policy(&[&second], None), Some(&log_pk)), "witnesses"));
+        assert!(!passed(&check(&envelope, &policy(&[&first], None), None), "witnesses"));
+
+        let mut cosigned = envelope.clone();
+        let head = &mut cosigned.inclusion_proof.as_mut().unwrap().tree_head;
+        head.cosignatures.push(cosign(&second));
+        // A second cosignature by the same witness counts once.
+        head.cosignatures.push(cosign(&first));
+        let verdict = check(&cosigned, &policy(&[&first, &second], Some(2)), Some(&log_pk));
+        assert!(verdict.verified, "{:?}", verdict.checks);
+        assert!(!passed(&check(&cosigned, &policy(&[&first], Some(2)), Some(&log_pk)), "witnesses"));
+
+        // A head the log did not sign proves nothing, however many witnesses cosigned it.
+        let mut unsigned = cosigned.clone();
+        unsigned.inclusion_proof.as_mut().unwrap().tree_head.tree_head.body.timestamp = days_ago(1);
+        let verdict = check(&unsigned, &policy(&[&first, &second], Some(2)), Some(&log_pk));
+        assert!(!passed(&verdict, "log_inclusion") && !passed(&verdict, "witnesses"));
+    }
 }
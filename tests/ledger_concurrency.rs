@@ -0,0 +1,68 @@
+//! Stress test: many concurrent signers appending to one shared ledger file.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SIGNERS: usize = 16;
+const FILES_PER_SIGNER: usize = 8;
+
+fn qs_notary() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_qs_notary"))
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("qs_notary_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_artifacts(dir: &Path) {
+    std::fs::create_dir_all(dir).unwrap();
+    for i in 0..FILES_PER_SIGNER {
+        std::fs::write(dir.join(format!("artifact_{i}.txt")), format!("artifact {i}")).unwrap();
+    }
+}
+
+#[test]
+fn concurrent_signers_do_not_interleave_ledger_lines() {
+    let root = scratch_dir("ledger_concurrency");
+    let status = qs_notary()
+        .args(["generate-keys", "--output-dir"])
+        .arg(&root)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let ledger = root.join("ledger.json");
+    let children: Vec<_> = (0..SIGNERS)
+        .map(|n| {
+            let dir = root.join(format!("signer_{n}"));
+            write_artifacts(&dir);
+            qs_notary()
+                .arg("sign-all")
+                .arg(&dir)
+                .arg("--private-key")
+                .arg(root.join("private.key"))
+                .arg("--ledger")
+                .arg(&ledger)
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let content = std::fs::read_to_string(&ledger).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    // Each signer writes one entry per file plus one for its manifest.
+    assert_eq!(lines.len(), SIGNERS * (FILES_PER_SIGNER + 1));
+    for line in lines {
+        let entry: serde_json::Value = serde_json::from_str(line)
+            .unwrap_or_else(|e| panic!("corrupt ledger line {line:?}: {e}"));
+        assert!(entry["signature_hash"].as_str().is_some());
+    }
+
+    let _ = std::fs::remove_dir_all(&root);
+}